@@ -1,6 +1,9 @@
-#[derive(Clone, Debug, PartialEq)]
+use crate::initializers;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Reg {
     AX,
+    BX,
     CX,
     DX,
     DI,
@@ -9,10 +12,15 @@ pub enum Reg {
     R9,
     R10,
     R11,
+    R12,
+    R13,
+    R14,
+    R15,
     SP,
+    BP,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Operand {
     Imm(i64),
     Reg(Reg),
@@ -32,6 +40,8 @@ pub enum BinaryOperator {
     Add,
     Sub,
     Mult,
+    Xor,
+    Shl,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -44,8 +54,10 @@ pub enum CondCode {
     LE,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum AsmType {
+    Byte,
+    Word,
     Longword,
     Quadword,
 }
@@ -53,7 +65,18 @@ pub enum AsmType {
 #[derive(Clone, Debug, PartialEq)]
 pub enum Instruction {
     Mov(AsmType, Operand, Operand),
-    Movsx(Operand, Operand),
+    Movsx {
+        src_t: AsmType,
+        dst_t: AsmType,
+        src: Operand,
+        dst: Operand,
+    },
+    MovZeroExtend {
+        src_t: AsmType,
+        dst_t: AsmType,
+        src: Operand,
+        dst: Operand,
+    },
     Unary(UnaryOperator, AsmType, Operand),
     Binary {
         op: BinaryOperator,
@@ -62,13 +85,17 @@ pub enum Instruction {
         dst: Operand,
     },
     Cmp(AsmType, Operand, Operand),
+    Test(AsmType, Operand, Operand),
     Idiv(AsmType, Operand),
     Cdq(AsmType),
     Jmp(String),
     JmpCC(CondCode, String),
     SetCC(CondCode, Operand),
     Label(String),
+    AllocateStack(i64),
+    DeallocateStack(i64),
     Push(Operand),
+    Pop(Reg),
     Call(String),
     Ret,
 }
@@ -84,7 +111,7 @@ pub enum TopLevel {
         name: String,
         alignment: i64,
         global: bool,
-        init: i64,
+        init: initializers::StaticInit,
     },
 }
 
@@ -7,6 +7,8 @@ pub enum Entry {
     Fun {
         defined: bool,
         bytes_required: i64,
+        // 函数体用到的被调用者保存的寄存器，要在序言里压栈、在返回前弹出
+        callee_saved_regs: Vec<assembly::Reg>,
     },
     Obj {
         t: assembly::AsmType,
@@ -23,6 +25,7 @@ pub fn add_fun(fun_name: String, defined: bool) {
     let entry = Entry::Fun {
         defined: defined,
         bytes_required: 0,
+        callee_saved_regs: vec![],
     };
     _map.insert(fun_name, entry);
 }
@@ -42,6 +45,7 @@ pub fn get_bytes_required(fun_name: String) -> i64 {
         Entry::Fun {
             defined: _,
             bytes_required,
+            callee_saved_regs: _,
         } => *bytes_required,
         Entry::Obj { t: _, is_static: _ } => panic!("内部错误：不是一个函数。"),
     }
@@ -50,6 +54,14 @@ pub fn get_bytes_required(fun_name: String) -> i64 {
 pub fn get_size(var_name: String) -> i64 {
     let mut _map = SYMBOL_TABLE.lock().unwrap();
     match _map.get(&var_name).unwrap() {
+        Entry::Obj {
+            t: assembly::AsmType::Byte,
+            is_static: _,
+        } => 1,
+        Entry::Obj {
+            t: assembly::AsmType::Word,
+            is_static: _,
+        } => 2,
         Entry::Obj {
             t: assembly::AsmType::Longword,
             is_static: _,
@@ -61,6 +73,7 @@ pub fn get_size(var_name: String) -> i64 {
         Entry::Fun {
             defined: _,
             bytes_required: _,
+            callee_saved_regs: _,
         } => panic!("内部错误：这是一个函数，不是一个对象。"),
     }
 }
@@ -68,6 +81,14 @@ pub fn get_size(var_name: String) -> i64 {
 pub fn get_alignment(var_name: String) -> i64 {
     let mut _map = SYMBOL_TABLE.lock().unwrap();
     match _map.get(&var_name).unwrap() {
+        Entry::Obj {
+            t: assembly::AsmType::Byte,
+            is_static: _,
+        } => 1,
+        Entry::Obj {
+            t: assembly::AsmType::Word,
+            is_static: _,
+        } => 2,
         Entry::Obj {
             t: assembly::AsmType::Longword,
             is_static: _,
@@ -79,16 +100,42 @@ pub fn get_alignment(var_name: String) -> i64 {
         Entry::Fun {
             defined: _,
             bytes_required: _,
+            callee_saved_regs: _,
         } => panic!("内部错误：这是一个函数，不是一个对象。"),
     }
 }
 
+pub fn add_callee_saved_regs_used(fun_name: String, regs: Vec<assembly::Reg>) {
+    let mut _map = SYMBOL_TABLE.lock().unwrap();
+    match _map.get_mut(&fun_name) {
+        Some(Entry::Fun {
+            defined: _,
+            bytes_required: _,
+            callee_saved_regs,
+        }) => *callee_saved_regs = regs,
+        _ => panic!("内部错误：{}不是函数。", fun_name),
+    }
+}
+
+pub fn get_callee_saved_regs_used(fun_name: String) -> Vec<assembly::Reg> {
+    let mut _map = SYMBOL_TABLE.lock().unwrap();
+    match _map.get(&fun_name) {
+        Some(Entry::Fun {
+            defined: _,
+            bytes_required: _,
+            callee_saved_regs,
+        }) => callee_saved_regs.clone(),
+        _ => panic!("内部错误：{}不是函数。", fun_name),
+    }
+}
+
 pub fn is_defined(fun_name: String) -> bool {
     let mut _map = SYMBOL_TABLE.lock().unwrap();
     match _map.get(&fun_name).unwrap() {
         Entry::Fun {
             defined,
             bytes_required: _,
+            callee_saved_regs: _,
         } => *defined,
         _ => panic!("内部错误：不是函数。"),
     }
@@ -101,6 +148,23 @@ pub fn is_static(var_name: String) -> bool {
         Entry::Fun {
             defined: _,
             bytes_required: _,
+            callee_saved_regs: _,
         } => panic!("内部错误：函数没有storage duration。"),
     }
 }
+
+pub fn bindings() -> Vec<(String, Entry)> {
+    let _map = SYMBOL_TABLE.lock().unwrap();
+    let mut bindings = vec![];
+    for key in _map.keys() {
+        bindings.push(((*key).clone(), (*_map.get(key).unwrap()).clone()));
+    }
+    bindings
+}
+
+/// 见`symbols::clear`。
+pub fn clear() {
+    let mut _map = SYMBOL_TABLE.lock().unwrap_or_else(|e| e.into_inner());
+    _map.clear();
+    SYMBOL_TABLE.clear_poison();
+}
@@ -0,0 +1,332 @@
+use std::fmt::Display;
+
+use crate::{assembly, assembly_symbols};
+
+/// 不同阶段的汇编代码要满足的约束不一样：codegen之后还可以有伪寄存器，
+/// 操作数的组合也可以不合法；instruction_fixup之后每条指令都必须能被汇编器接受，
+/// 窥孔优化改写出来的指令也一样。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Codegen,
+    ReplacePseudos,
+    Fixup,
+    Peephole,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub function: String,
+    pub instruction: Option<assembly::Instruction>,
+    pub message: String,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.instruction {
+            Some(i) => write!(f, "函数{}中的指令{:?}：{}", self.function, i, self.message),
+            None => write!(f, "函数{}：{}", self.function, self.message),
+        }
+    }
+}
+
+fn size(t: assembly::AsmType) -> i64 {
+    match t {
+        assembly::AsmType::Byte => 1,
+        assembly::AsmType::Word => 2,
+        assembly::AsmType::Longword => 4,
+        assembly::AsmType::Quadword => 8,
+    }
+}
+
+fn is_memory(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Stack(_) | assembly::Operand::Data(_) | assembly::Operand::Pseudo(_) => {
+            true
+        }
+        _ => false,
+    }
+}
+
+fn is_imm(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Imm(_) => true,
+        _ => false,
+    }
+}
+
+fn is_reg(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Reg(_) => true,
+        _ => false,
+    }
+}
+
+fn fits_in_i32(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Imm(i) => *i >= i32::MIN as i64 && *i <= i32::MAX as i64,
+        _ => true,
+    }
+}
+
+/// 检查单个操作数能不能按`t`的宽度访问。
+fn check_operand(stage: Stage, t: assembly::AsmType, operand: &assembly::Operand) -> Vec<String> {
+    let mut errors = vec![];
+    match operand {
+        assembly::Operand::Pseudo(name) if stage != Stage::Codegen => {
+            errors.push(format!("伪寄存器{}没有被替换", name))
+        }
+        assembly::Operand::Reg(assembly::Reg::SP | assembly::Reg::BP)
+            if t != assembly::AsmType::Quadword =>
+        {
+            errors.push("RSP和RBP只能按64位访问".to_string())
+        }
+        assembly::Operand::Imm(i) => {
+            let (min, max) = match t {
+                assembly::AsmType::Byte => (i8::MIN as i64, u8::MAX as i64),
+                assembly::AsmType::Word => (i16::MIN as i64, u16::MAX as i64),
+                assembly::AsmType::Longword => (i32::MIN as i64, u32::MAX as i64),
+                assembly::AsmType::Quadword => (i64::MIN, i64::MAX),
+            };
+            if *i < min || *i > max {
+                errors.push(format!("立即数{}超出了{}字节操作数的范围", i, size(t)))
+            }
+        }
+        // 按比对象本身更宽的宽度读写会越界
+        assembly::Operand::Pseudo(name) | assembly::Operand::Data(name) => {
+            let object_size = assembly_symbols::get_size(name.clone());
+            if object_size < size(t) {
+                errors.push(format!(
+                    "{}只有{}字节，不能按{}字节访问",
+                    name,
+                    object_size,
+                    size(t)
+                ))
+            }
+        }
+        _ => (),
+    }
+    errors
+}
+
+/// instruction_fixup之后必须满足的x86-64操作数组合规则。
+fn check_legality(instruction: &assembly::Instruction) -> Vec<String> {
+    let mut errors = vec![];
+    let mut require = |ok: bool, message: &str| {
+        if !ok {
+            errors.push(message.to_string())
+        }
+    };
+    match instruction {
+        assembly::Instruction::Mov(t, src, dst) => {
+            require(
+                !(is_memory(src) && is_memory(dst)),
+                "mov的两个操作数不能都在内存里",
+            );
+            require(!is_imm(dst), "mov的目的操作数不能是立即数");
+            require(
+                *t != assembly::AsmType::Quadword || is_reg(dst) || fits_in_i32(src),
+                "只有寄存器才能接收64位立即数",
+            );
+        }
+        assembly::Instruction::Movsx {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        }
+        | assembly::Instruction::MovZeroExtend {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => {
+            require(!is_imm(src), "movsx和movzx的源操作数不能是立即数");
+            require(is_reg(dst), "movsx和movzx的目的操作数必须是寄存器");
+            require(
+                size(*src_t) < size(*dst_t),
+                "扩展指令的源类型必须比目的类型窄",
+            );
+        }
+        assembly::Instruction::Binary {
+            op: assembly::BinaryOperator::Mult,
+            t: _,
+            src,
+            dst,
+        } => {
+            require(is_reg(dst), "imul的目的操作数必须是寄存器");
+            require(fits_in_i32(src), "imul的立即数必须能用32位表示");
+        }
+        assembly::Instruction::Binary {
+            op: assembly::BinaryOperator::Shl,
+            t: _,
+            src,
+            dst,
+        } => {
+            require(is_imm(src), "sal的移位数必须是立即数");
+            require(!is_imm(dst), "sal的目的操作数不能是立即数");
+        }
+        assembly::Instruction::Binary {
+            op: _,
+            t: _,
+            src,
+            dst,
+        } => {
+            require(
+                !(is_memory(src) && is_memory(dst)),
+                "二元运算的两个操作数不能都在内存里",
+            );
+            require(!is_imm(dst), "二元运算的目的操作数不能是立即数");
+            require(fits_in_i32(src), "二元运算的立即数必须能用32位表示");
+        }
+        assembly::Instruction::Cmp(_, src, dst) | assembly::Instruction::Test(_, src, dst) => {
+            require(
+                !(is_memory(src) && is_memory(dst)),
+                "比较的两个操作数不能都在内存里",
+            );
+            require(!is_imm(dst), "比较的第二个操作数不能是立即数");
+            require(fits_in_i32(src), "比较的立即数必须能用32位表示");
+        }
+        assembly::Instruction::Idiv(_, op) => require(!is_imm(op), "idiv的操作数不能是立即数"),
+        assembly::Instruction::Unary(_, _, op) | assembly::Instruction::SetCC(_, op) => {
+            require(!is_imm(op), "目的操作数不能是立即数")
+        }
+        assembly::Instruction::Push(op) => require(fits_in_i32(op), "push的立即数必须能用32位表示"),
+        _ => (),
+    }
+    errors
+}
+
+fn check_instruction(
+    stage: Stage,
+    instruction: &assembly::Instruction,
+    labels: &Vec<String>,
+) -> Vec<String> {
+    let mut errors = vec![];
+    match instruction {
+        assembly::Instruction::Mov(t, src, dst)
+        | assembly::Instruction::Binary { op: _, t, src, dst }
+        | assembly::Instruction::Cmp(t, src, dst)
+        | assembly::Instruction::Test(t, src, dst) => {
+            errors.append(&mut check_operand(stage, *t, src));
+            errors.append(&mut check_operand(stage, *t, dst));
+        }
+        assembly::Instruction::Movsx {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        }
+        | assembly::Instruction::MovZeroExtend {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => {
+            errors.append(&mut check_operand(stage, *src_t, src));
+            errors.append(&mut check_operand(stage, *dst_t, dst));
+        }
+        assembly::Instruction::Unary(_, t, op) | assembly::Instruction::Idiv(t, op) => {
+            errors.append(&mut check_operand(stage, *t, op))
+        }
+        assembly::Instruction::SetCC(_, op) => {
+            errors.append(&mut check_operand(stage, assembly::AsmType::Byte, op))
+        }
+        assembly::Instruction::Push(op) => {
+            errors.append(&mut check_operand(stage, assembly::AsmType::Quadword, op))
+        }
+        assembly::Instruction::Cdq(assembly::AsmType::Byte | assembly::AsmType::Word) => {
+            errors.push("cdq只有32位和64位的形式".to_string())
+        }
+        assembly::Instruction::Pop(assembly::Reg::SP) => errors.push("不能pop到RSP".to_string()),
+        assembly::Instruction::Jmp(target) | assembly::Instruction::JmpCC(_, target) => {
+            if !labels.contains(target) {
+                errors.push(format!("跳转目标{}不存在", target))
+            }
+        }
+        _ => (),
+    }
+    if stage == Stage::Fixup || stage == Stage::Peephole {
+        errors.append(&mut check_legality(instruction));
+    }
+    errors
+}
+
+fn verify_function(
+    stage: Stage,
+    name: &String,
+    instructions: &Vec<assembly::Instruction>,
+) -> Vec<Error> {
+    let mut errors = vec![];
+    let mut labels = vec![];
+    for instruction in instructions {
+        if let assembly::Instruction::Label(l) = instruction {
+            if labels.contains(l) {
+                errors.push(Error {
+                    function: name.clone(),
+                    instruction: Some(instruction.clone()),
+                    message: "标签重复定义".to_string(),
+                });
+            }
+            labels.push(l.clone());
+        }
+    }
+    for instruction in instructions {
+        for message in check_instruction(stage, instruction, &labels) {
+            errors.push(Error {
+                function: name.clone(),
+                instruction: Some(instruction.clone()),
+                message: message,
+            });
+        }
+    }
+    errors
+}
+
+pub fn verify(program: &assembly::T, stage: Stage) -> Result<(), Vec<Error>> {
+    let mut errors = vec![];
+    match program {
+        assembly::T::Program(tls) => {
+            for tl in tls {
+                if let assembly::TopLevel::Function {
+                    name,
+                    global: _,
+                    instructions,
+                } = tl
+                {
+                    errors.append(&mut verify_function(stage, name, instructions));
+                }
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[test]
+fn test_reject_illegal_forms() {
+    let program = assembly::T::Program(vec![assembly::TopLevel::Function {
+        name: "f".to_string(),
+        global: true,
+        instructions: vec![
+            assembly::Instruction::Movsx {
+                src_t: assembly::AsmType::Longword,
+                dst_t: assembly::AsmType::Quadword,
+                src: assembly::Operand::Imm(1),
+                dst: assembly::Operand::Stack(-8),
+            },
+            assembly::Instruction::Mov(
+                assembly::AsmType::Longword,
+                assembly::Operand::Reg(assembly::Reg::SP),
+                assembly::Operand::Reg(assembly::Reg::AX),
+            ),
+            assembly::Instruction::Ret,
+        ],
+    }]);
+    // codegen之后这些形式还没有被修正，只有RSP的宽度不对
+    assert_eq!(verify(&program, Stage::Codegen).unwrap_err().len(), 1);
+    assert_eq!(verify(&program, Stage::Fixup).unwrap_err().len(), 3);
+    assert_eq!(verify(&program, Stage::Peephole).unwrap_err().len(), 3);
+}
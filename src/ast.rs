@@ -72,6 +72,9 @@ pub enum ProgType<ExpType> {
     Program(Vec<Declaration<ExpType>>),
 }
 
+pub type UntypedProgType = ProgType<UnTypedExp>;
+pub type TypedProgType = ProgType<TypedExp>;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum StorageClass {
     Static,
@@ -99,12 +102,20 @@ pub struct FunctionDeclaration<ExpType> {
     pub params: Vec<String>,
     pub body: Option<Block<ExpType>>,
     pub storage_class: Option<StorageClass>,
+    pub inline: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaticAssertDeclaration<ExpType> {
+    pub condition: ExpType,
+    pub message: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Declaration<ExpType> {
     FunDecl(FunctionDeclaration<ExpType>),
     VarDecl(VariableDeclaration<ExpType>),
+    StaticAssert(StaticAssertDeclaration<ExpType>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -127,6 +138,8 @@ pub enum UnTypedExp {
         f: String,
         args: Vec<UnTypedExp>,
     },
+    SizeOf(Box<UnTypedExp>),
+    SizeOfT(types::Type),
 }
 
 #[derive(Clone, Debug, PartialEq)]
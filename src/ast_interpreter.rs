@@ -0,0 +1,626 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    ast, const_convert, const_eval, constants, initializers, ir_interpreter, symbols, types,
+};
+
+// 递归太深时报错，而不是让解释器自己的栈溢出
+const MAX_CALL_DEPTH: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub function: String,
+    pub message: String,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "函数{}：{}", self.function, self.message)
+    }
+}
+
+/// 求值被打断：程序调用了`exit`，或者遇到了未定义行为。
+enum Stop {
+    Exit(i32),
+    Error(Error),
+}
+
+/// 语句执行完之后控制流往哪里走。`Break`和`Continue`带着label_loops给循环起的名字。
+enum Flow {
+    Normal,
+    Break(String),
+    Continue(String),
+    Return(constants::T),
+}
+
+pub struct Interpreter {
+    functions: HashMap<String, ast::FunctionDeclaration<ast::TypedExp>>,
+    statics: HashMap<String, constants::T>,
+    // 当前函数的作用域，最里层在最后。声明了但还没有赋值的变量对应`None`
+    scopes: Vec<HashMap<String, Option<constants::T>>>,
+    current_function: String,
+    output: Vec<u8>,
+    depth: usize,
+}
+
+fn is_zero(c: &constants::T) -> bool {
+    const_eval::is_zero(c.clone())
+}
+
+fn to_int(b: bool) -> constants::T {
+    if b {
+        constants::INT_ONE
+    } else {
+        constants::INT_ZERO
+    }
+}
+
+impl Interpreter {
+    /// 静态变量的初始值在类型检查之后已经记在符号表里了。
+    pub fn new(program: &ast::ProgType<ast::TypedExp>) -> Self {
+        let mut statics = HashMap::new();
+        for (name, entry) in symbols::bindings() {
+            if let symbols::IdentifierAttrs::StaticAttr { init, global: _ } = entry.attrs {
+                let value = match init {
+                    symbols::InitialValue::Initial(i) => Some(static_init_to_const(&i)),
+                    symbols::InitialValue::Tentative => {
+                        Some(static_init_to_const(&initializers::zero(entry.t)))
+                    }
+                    // 在别的文件里定义，这里读不到它的值
+                    symbols::InitialValue::NoInitializer => None,
+                };
+                if let Some(v) = value {
+                    statics.insert(name, v);
+                }
+            }
+        }
+        let mut functions = HashMap::new();
+        match program {
+            ast::ProgType::Program(decls) => {
+                for decl in decls {
+                    if let ast::Declaration::FunDecl(fd) = decl {
+                        if fd.body.is_some() {
+                            functions.insert(fd.name.clone(), fd.clone());
+                        }
+                    }
+                }
+            }
+        }
+        Interpreter {
+            functions: functions,
+            statics: statics,
+            scopes: vec![],
+            current_function: String::new(),
+            output: vec![],
+            depth: 0,
+        }
+    }
+
+    fn error(&self, message: String) -> Stop {
+        Stop::Error(Error {
+            function: self.current_function.clone(),
+            message: message,
+        })
+    }
+
+    fn lookup(&self, name: &String) -> Result<constants::T, Stop> {
+        for scope in self.scopes.iter().rev() {
+            match scope.get(name) {
+                Some(Some(v)) => return Ok(v.clone()),
+                Some(None) => return Err(self.error(format!("读取未初始化的变量{}", name))),
+                None => (),
+            }
+        }
+        match self.statics.get(name) {
+            Some(v) => Ok(v.clone()),
+            None => Err(self.error(format!("读取未初始化的变量{}", name))),
+        }
+    }
+
+    fn assign(&mut self, name: &String, v: constants::T) {
+        if symbols::is_static(name.clone()) {
+            self.statics.insert(name.clone(), v);
+            return;
+        }
+        for scope in self.scopes.iter_mut().rev() {
+            if scope.contains_key(name) {
+                scope.insert(name.clone(), Some(v));
+                return;
+            }
+        }
+        panic!("内部错误：变量{}没有声明。", name);
+    }
+
+    fn eval_binary(
+        &mut self,
+        t: &types::Type,
+        op: &ast::BinaryOperator,
+        e1: &ast::TypedExp,
+        e2: &ast::TypedExp,
+    ) -> Result<constants::T, Stop> {
+        match op {
+            ast::BinaryOperator::And => {
+                let result = !is_zero(&self.eval(e1)?) && !is_zero(&self.eval(e2)?);
+                return Ok(to_int(result));
+            }
+            ast::BinaryOperator::Or => {
+                let result = !is_zero(&self.eval(e1)?) || !is_zero(&self.eval(e2)?);
+                return Ok(to_int(result));
+            }
+            _ => (),
+        }
+        let v1 = const_convert::const_to_i64(self.eval(e1)?);
+        let v2 = const_convert::const_to_i64(self.eval(e2)?);
+        match op {
+            ast::BinaryOperator::Add
+            | ast::BinaryOperator::Subtract
+            | ast::BinaryOperator::Multiply => {
+                Ok(const_eval::fold_arith(t.clone(), v1, v2, op.clone()).unwrap())
+            }
+            ast::BinaryOperator::Divide | ast::BinaryOperator::Mod => {
+                // 和idiv一样，除以0和INT_MIN / -1都是错误
+                let overflow = match t {
+                    types::Type::Int => v1 == i32::MIN as i64 && v2 == -1,
+                    types::Type::Long => v1 == i64::MIN && v2 == -1,
+                    _ => false,
+                };
+                if overflow {
+                    return Err(self.error("有符号除法溢出".to_string()));
+                }
+                match const_eval::fold_arith(t.clone(), v1, v2, op.clone()) {
+                    Some(c) => Ok(c),
+                    None => Err(self.error("除以零".to_string())),
+                }
+            }
+            _ => Ok(const_eval::fold_comparison(v1, v2, op.clone())),
+        }
+    }
+
+    fn eval(&mut self, exp: &ast::TypedExp) -> Result<constants::T, Stop> {
+        match &*exp.e {
+            ast::TypedInnerExp::Constant(c) => Ok(c.clone()),
+            ast::TypedInnerExp::Var(name) => self.lookup(name),
+            ast::TypedInnerExp::Cast { target_type, e } => {
+                let c = self.eval(e)?;
+                Ok(const_convert::const_convert(target_type.clone(), c))
+            }
+            ast::TypedInnerExp::Unary(op, inner) => {
+                let v = const_convert::const_to_i64(self.eval(inner)?);
+                let result = match op {
+                    ast::UnaryOperator::Not => return Ok(to_int(v == 0)),
+                    ast::UnaryOperator::Negate => v.wrapping_neg(),
+                    ast::UnaryOperator::Complement => !v,
+                };
+                Ok(const_convert::const_convert(
+                    exp.t.clone(),
+                    constants::T::ConstLong(result),
+                ))
+            }
+            ast::TypedInnerExp::Binary(op, e1, e2) => self.eval_binary(&exp.t, op, e1, e2),
+            ast::TypedInnerExp::Assignment(lhs, rhs) => {
+                let name = match &*lhs.e {
+                    ast::TypedInnerExp::Var(name) => name.clone(),
+                    _ => return Err(self.error("赋值号左边不是变量".to_string())),
+                };
+                let v = const_convert::const_convert(lhs.t.clone(), self.eval(rhs)?);
+                self.assign(&name, v.clone());
+                Ok(v)
+            }
+            ast::TypedInnerExp::Conditional {
+                condition,
+                then_result,
+                else_result,
+            } => {
+                if is_zero(&self.eval(condition)?) {
+                    self.eval(else_result)
+                } else {
+                    self.eval(then_result)
+                }
+            }
+            ast::TypedInnerExp::Funcall { f, args } => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                self.call(f, values)
+            }
+        }
+    }
+
+    /// 内置的外部函数，和ir_interpreter里的保持一致。
+    fn call_builtin(
+        &mut self,
+        name: &str,
+        args: &Vec<constants::T>,
+    ) -> Option<Result<constants::T, Stop>> {
+        match (name, args.as_slice()) {
+            ("putchar", [c]) => {
+                self.output
+                    .push(const_convert::const_to_i64(c.clone()) as u8);
+                Some(Ok(c.clone()))
+            }
+            ("exit", [code]) => Some(Err(Stop::Exit(
+                const_convert::const_to_i64(code.clone()) as i32
+            ))),
+            _ => None,
+        }
+    }
+
+    fn call(&mut self, name: &String, args: Vec<constants::T>) -> Result<constants::T, Stop> {
+        let fd = match self.functions.get(name) {
+            Some(fd) => fd.clone(),
+            None => {
+                return match self.call_builtin(name, &args) {
+                    Some(result) => result,
+                    None => Err(self.error(format!("函数{}没有定义，也不是内置函数", name))),
+                }
+            }
+        };
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(self.error("调用层数太深".to_string()));
+        }
+        if fd.params.len() != args.len() {
+            return Err(self.error(format!(
+                "调用{}时需要{}个参数，实际传了{}个",
+                name,
+                fd.params.len(),
+                args.len()
+            )));
+        }
+        let ret_type = match &fd.fun_type {
+            types::Type::FunType {
+                param_types: _,
+                ret_type,
+                has_prototype: _,
+            } => (**ret_type).clone(),
+            _ => panic!("内部错误：函数{}不是函数类型。", name),
+        };
+        let mut frame = HashMap::new();
+        for (param, arg) in fd.params.iter().zip(args) {
+            frame.insert(param.clone(), Some(arg));
+        }
+        let saved_scopes = std::mem::replace(&mut self.scopes, vec![frame]);
+        let saved_function = std::mem::replace(&mut self.current_function, name.clone());
+        self.depth += 1;
+        let result = self.exec_block(fd.body.as_ref().unwrap());
+        self.depth -= 1;
+        self.scopes = saved_scopes;
+        self.current_function = saved_function;
+        match result? {
+            Flow::Return(v) => Ok(v),
+            // 和生成的代码一样，没有return时返回0
+            _ => Ok(const_convert::const_convert(ret_type, constants::INT_ZERO)),
+        }
+    }
+
+    fn exec_declaration(&mut self, d: &ast::Declaration<ast::TypedExp>) -> Result<(), Stop> {
+        match d {
+            ast::Declaration::VarDecl(ast::VariableDeclaration {
+                name,
+                var_type,
+                init,
+                storage_class: None,
+            }) => {
+                let v = match init {
+                    Some(e) => Some(const_convert::const_convert(
+                        var_type.clone(),
+                        self.eval(e)?,
+                    )),
+                    None => None,
+                };
+                self.scopes.last_mut().unwrap().insert(name.clone(), v);
+            }
+            // 静态变量和extern声明在程序开始时就初始化好了，函数声明和静态断言在运行时什么也不做
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn exec_block(&mut self, block: &ast::Block<ast::TypedExp>) -> Result<Flow, Stop> {
+        self.scopes.push(HashMap::new());
+        let result = self.exec_block_items(block);
+        self.scopes.pop();
+        result
+    }
+
+    fn exec_block_items(&mut self, block: &ast::Block<ast::TypedExp>) -> Result<Flow, Stop> {
+        match block {
+            ast::Block::Block(items) => {
+                for item in items {
+                    let flow = match item {
+                        ast::BlockItem::S(s) => self.exec(s)?,
+                        ast::BlockItem::D(d) => {
+                            self.exec_declaration(d)?;
+                            Flow::Normal
+                        }
+                    };
+                    if let Flow::Normal = flow {
+                        continue;
+                    }
+                    return Ok(flow);
+                }
+                Ok(Flow::Normal)
+            }
+        }
+    }
+
+    /// 执行一次循环体，返回`None`表示继续循环，`Some`表示离开循环。
+    fn exec_loop_body(
+        &mut self,
+        body: &ast::Statement<ast::TypedExp>,
+        id: &String,
+    ) -> Result<Option<Flow>, Stop> {
+        match self.exec(body)? {
+            Flow::Break(l) if l == *id => Ok(Some(Flow::Normal)),
+            Flow::Continue(l) if l == *id => Ok(None),
+            Flow::Normal => Ok(None),
+            other => Ok(Some(other)),
+        }
+    }
+
+    fn exec(&mut self, statement: &ast::Statement<ast::TypedExp>) -> Result<Flow, Stop> {
+        match statement {
+            ast::Statement::Return(e) => Ok(Flow::Return(self.eval(e)?)),
+            ast::Statement::Expression(e) => {
+                self.eval(e)?;
+                Ok(Flow::Normal)
+            }
+            ast::Statement::If {
+                condition,
+                then_clause,
+                else_clause,
+            } => {
+                if !is_zero(&self.eval(condition)?) {
+                    self.exec(then_clause)
+                } else {
+                    match else_clause {
+                        Some(s) => self.exec(s),
+                        None => Ok(Flow::Normal),
+                    }
+                }
+            }
+            ast::Statement::Compound(block) => self.exec_block(block),
+            ast::Statement::Break(id) => Ok(Flow::Break(id.clone())),
+            ast::Statement::Continue(id) => Ok(Flow::Continue(id.clone())),
+            ast::Statement::While {
+                condition,
+                body,
+                id,
+            } => {
+                while !is_zero(&self.eval(condition)?) {
+                    if let Some(flow) = self.exec_loop_body(body, id)? {
+                        return Ok(flow);
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            ast::Statement::DoWhile {
+                body,
+                condition,
+                id,
+            } => loop {
+                if let Some(flow) = self.exec_loop_body(body, id)? {
+                    return Ok(flow);
+                }
+                if is_zero(&self.eval(condition)?) {
+                    return Ok(Flow::Normal);
+                }
+            },
+            ast::Statement::For {
+                init,
+                condition,
+                post,
+                body,
+                id,
+            } => {
+                // for的头部是一个单独的作用域
+                self.scopes.push(HashMap::new());
+                let result = self.exec_for(init, condition, post, body, id);
+                self.scopes.pop();
+                result
+            }
+            ast::Statement::Null => Ok(Flow::Normal),
+        }
+    }
+
+    fn exec_for(
+        &mut self,
+        init: &ast::ForInit<ast::TypedExp>,
+        condition: &Option<ast::TypedExp>,
+        post: &Option<ast::TypedExp>,
+        body: &ast::Statement<ast::TypedExp>,
+        id: &String,
+    ) -> Result<Flow, Stop> {
+        match init {
+            ast::ForInit::InitDecl(vd) => {
+                self.exec_declaration(&ast::Declaration::VarDecl(vd.clone()))?
+            }
+            ast::ForInit::InitExp(Some(e)) => {
+                self.eval(e)?;
+            }
+            ast::ForInit::InitExp(None) => (),
+        }
+        loop {
+            if let Some(c) = condition {
+                if is_zero(&self.eval(c)?) {
+                    return Ok(Flow::Normal);
+                }
+            }
+            if let Some(flow) = self.exec_loop_body(body, id)? {
+                return Ok(flow);
+            }
+            if let Some(p) = post {
+                self.eval(p)?;
+            }
+        }
+    }
+
+    pub fn run(&mut self, entry: &str) -> Result<ir_interpreter::Outcome, Error> {
+        let exit_code = match self.call(&entry.to_string(), vec![]) {
+            Ok(v) => const_convert::const_to_i64(v) as i32,
+            Err(Stop::Exit(code)) => code,
+            Err(Stop::Error(e)) => return Err(e),
+        };
+        Ok(ir_interpreter::Outcome {
+            exit_code: exit_code,
+            output: self.output.clone(),
+        })
+    }
+}
+
+fn static_init_to_const(init: &initializers::StaticInit) -> constants::T {
+    match init {
+        initializers::StaticInit::BoolInit(b) => constants::T::ConstBool(*b),
+        initializers::StaticInit::ShortInit(s) => constants::T::ConstShort(*s),
+        initializers::StaticInit::UShortInit(u) => constants::T::ConstUShort(*u),
+        initializers::StaticInit::IntInit(i) => constants::T::ConstInt(*i),
+        initializers::StaticInit::LongInit(l) => constants::T::ConstLong(*l),
+    }
+}
+
+/// 从`main`开始解释执行类型检查之后的程序，结果可以直接和`ir_interpreter::run`的结果比较。
+pub fn run(program: &ast::ProgType<ast::TypedExp>) -> Result<ir_interpreter::Outcome, Error> {
+    Interpreter::new(program).run("main")
+}
+
+#[test]
+fn test_programs_agree_with_ir_interpreter() {
+    fn typecheck_source(source: &str) -> ast::ProgType<ast::TypedExp> {
+        let tokens = crate::lexer::Lexer::new(source.as_bytes()).lex();
+        let program = crate::parser::Parser::new(tokens).parse();
+        let resolved = crate::identifier_resolution::resolve(program);
+        let labeled = crate::label_loops::label_loops(resolved);
+        crate::typecheck::typecheck(labeled)
+    }
+
+    // 两个解释器的结果必须一致。符号表是全局的，所以每个程序的函数名都不一样，
+    // 入口也不叫main
+    fn run_both(source: &str, entry: &str) -> ir_interpreter::Outcome {
+        let program = typecheck_source(source);
+        let outcome = Interpreter::new(&program).run(entry).unwrap();
+        let ir = crate::ir_gen::gen(program);
+        assert_eq!(
+            ir_interpreter::Interpreter::new(&ir).run(entry),
+            Ok(outcome.clone())
+        );
+        outcome
+    }
+
+    let outcome = |exit_code: i32, output: &str| ir_interpreter::Outcome {
+        exit_code: exit_code,
+        output: output.as_bytes().to_vec(),
+    };
+
+    // 静态局部变量只初始化一次，在多次调用之间保持它的值
+    assert_eq!(
+        run_both(
+            "int ast_counter(void) {
+                 static int count = 10;
+                 count = count + 1;
+                 return count;
+             }
+             int ast_static_locals(void) {
+                 ast_counter();
+                 ast_counter();
+                 return ast_counter();
+             }",
+            "ast_static_locals"
+        ),
+        outcome(13, "")
+    );
+
+    // exit立即结束整个程序，退出码是它的参数
+    assert_eq!(
+        run_both(
+            "int putchar(int c);
+             int exit(int code);
+             int ast_exit_helper(void) {
+                 putchar(65);
+                 exit(7);
+                 return putchar(66);
+             }
+             int ast_exit(void) {
+                 ast_exit_helper();
+                 return 0;
+             }",
+            "ast_exit"
+        ),
+        outcome(7, "A")
+    );
+
+    assert_eq!(
+        run_both(
+            "int ast_fib(int n) {
+                 if (n < 2)
+                     return n;
+                 return ast_fib(n - 1) + ast_fib(n - 2);
+             }
+             int ast_recursion(void) {
+                 return ast_fib(10);
+             }",
+            "ast_recursion"
+        ),
+        outcome(55, "")
+    );
+
+    // 内层do-while里的continue跳到条件判断，break只跳出最内层的循环
+    assert_eq!(
+        run_both(
+            "int putchar(int c);
+             int ast_nested_loops(void) {
+                 int total = 0;
+                 for (int i = 0; i < 4; i = i + 1) {
+                     if (i == 1)
+                         continue;
+                     int j = 0;
+                     do {
+                         j = j + 1;
+                         if (j == 2)
+                             continue;
+                         if (j > i)
+                             break;
+                         putchar(48 + j);
+                         total = total + 10 * i + j;
+                     } while (j < 5);
+                     if (i == 3)
+                         break;
+                 }
+                 return total;
+             }",
+            "ast_nested_loops"
+        ),
+        outcome(85, "113")
+    );
+
+    // 赋值和初始化都先转换成变量的类型：_Bool只有0和1，short和unsigned short回绕
+    assert_eq!(
+        run_both(
+            "int ast_truncation(void) {
+                 _Bool b = 256;
+                 short s = 0;
+                 unsigned short u = 0;
+                 s = 98304;
+                 u = -1;
+                 return b + (s == -32768) * 10 + (u == 65535) * 100 + (b == 1) * 1000;
+             }",
+            "ast_truncation"
+        ),
+        outcome(1111, "")
+    );
+
+    let program = typecheck_source(
+        "int ast_divide(int d) {
+             return 1 / d;
+         }
+         int ast_division_by_zero(void) {
+             return ast_divide(0);
+         }",
+    );
+    assert_eq!(
+        Interpreter::new(&program).run("ast_division_by_zero"),
+        Err(Error {
+            function: "ast_divide".to_string(),
+            message: "除以零".to_string(),
+        })
+    );
+}
@@ -0,0 +1,631 @@
+use crate::{ast, constants, types};
+
+/// 把AST重新打印成C代码。默认输出可以直接交给C编译器；打开注释选项之后，
+/// 每个有类型的表达式后面会跟一个`/* 类型 */`，每个被重命名的变量后面会跟一个`/* 唯一名 */`。
+pub struct Options {
+    pub annotate_types: bool,
+    pub annotate_names: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Options {
+            annotate_types: false,
+            annotate_names: false,
+        }
+    }
+}
+
+// 和parser里的优先级一致，一元运算和类型转换比所有二元运算都高
+const PRIMARY: u8 = 100;
+const UNARY: u8 = 60;
+const CONDITIONAL: u8 = 3;
+const ASSIGNMENT: u8 = 1;
+
+/// 无类型和有类型的AST共用语句和声明的打印逻辑，只有表达式的打印不一样。
+/// 返回表达式的文本和它最外层运算符的优先级，外层根据优先级决定要不要加括号。
+pub trait Exp {
+    fn to_c(&self, options: &Options) -> (String, u8);
+}
+
+fn type_to_c(t: &types::Type) -> String {
+    match t {
+        types::Type::Bool => "_Bool".to_string(),
+        types::Type::Short => "short".to_string(),
+        types::Type::UShort => "unsigned short".to_string(),
+        types::Type::Int => "int".to_string(),
+        types::Type::Long => "long".to_string(),
+        types::Type::FunType {
+            param_types: _,
+            ret_type: _,
+            has_prototype: _,
+        } => panic!("内部错误：函数类型不能出现在表达式里"),
+    }
+}
+
+fn const_to_c(c: &constants::T) -> (String, u8) {
+    let signed = |text: String| {
+        if text.starts_with('-') {
+            (text, UNARY)
+        } else {
+            (text, PRIMARY)
+        }
+    };
+    match c {
+        // C没有这几种类型的字面量，只能写成强制转换
+        constants::T::ConstBool(b) => (format!("(_Bool){}", *b as i32), UNARY),
+        constants::T::ConstShort(s) => (format!("(short){}", s), UNARY),
+        constants::T::ConstUShort(u) => (format!("(unsigned short){}", u), UNARY),
+        // 2147483648本身不是int字面量，最小值只能用减法得到
+        constants::T::ConstInt(i32::MIN) => (format!("({} - 1)", i32::MIN + 1), PRIMARY),
+        constants::T::ConstInt(i) => signed(i.to_string()),
+        constants::T::ConstLong(i64::MIN) => (format!("({}L - 1)", i64::MIN + 1), PRIMARY),
+        constants::T::ConstLong(l) => signed(format!("{}L", l)),
+    }
+}
+
+/// identifier_resolution把局部变量改名成`x.3`这样的形式，打印时还原成源代码里的名字。
+fn name_to_c(name: &String, options: &Options) -> String {
+    let original = match name.find('.') {
+        Some(i) => &name[..i],
+        None => name.as_str(),
+    };
+    if options.annotate_names && original != name {
+        format!("{} /* {} */", original, name)
+    } else {
+        original.to_string()
+    }
+}
+
+fn unary_op_to_c(op: &ast::UnaryOperator) -> &'static str {
+    match op {
+        ast::UnaryOperator::Complement => "~",
+        ast::UnaryOperator::Negate => "-",
+        ast::UnaryOperator::Not => "!",
+    }
+}
+
+fn binary_op_to_c(op: &ast::BinaryOperator) -> (&'static str, u8) {
+    match op {
+        ast::BinaryOperator::Multiply => ("*", 50),
+        ast::BinaryOperator::Divide => ("/", 50),
+        ast::BinaryOperator::Mod => ("%", 50),
+        ast::BinaryOperator::Add => ("+", 45),
+        ast::BinaryOperator::Subtract => ("-", 45),
+        ast::BinaryOperator::LessThan => ("<", 35),
+        ast::BinaryOperator::LessOrEqual => ("<=", 35),
+        ast::BinaryOperator::GreaterThan => (">", 35),
+        ast::BinaryOperator::GreaterOrEqual => (">=", 35),
+        ast::BinaryOperator::Equal => ("==", 30),
+        ast::BinaryOperator::NotEqual => ("!=", 30),
+        ast::BinaryOperator::And => ("&&", 10),
+        ast::BinaryOperator::Or => ("||", 5),
+    }
+}
+
+/// 优先级低于`min_prec`的子表达式要加括号。
+fn operand<E: Exp>(e: &E, min_prec: u8, options: &Options) -> String {
+    let (text, prec) = e.to_c(options);
+    if prec < min_prec {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+fn unary_to_c<E: Exp>(op: &ast::UnaryOperator, e: &E, options: &Options) -> (String, u8) {
+    let inner = operand(e, UNARY, options);
+    // 避免`- -x`被打印成`--x`
+    if inner.starts_with(unary_op_to_c(op)) {
+        (format!("{}({})", unary_op_to_c(op), inner), UNARY)
+    } else {
+        (format!("{}{}", unary_op_to_c(op), inner), UNARY)
+    }
+}
+
+fn binary_to_c<E: Exp>(
+    op: &ast::BinaryOperator,
+    e1: &E,
+    e2: &E,
+    options: &Options,
+) -> (String, u8) {
+    let (symbol, prec) = binary_op_to_c(op);
+    // 二元运算都是左结合的，右边优先级相同也要加括号
+    let left = operand(e1, prec, options);
+    let right = operand(e2, prec + 1, options);
+    (format!("{} {} {}", left, symbol, right), prec)
+}
+
+fn assignment_to_c<E: Exp>(lhs: &E, rhs: &E, options: &Options) -> (String, u8) {
+    let left = operand(lhs, ASSIGNMENT + 1, options);
+    let right = operand(rhs, ASSIGNMENT, options);
+    (format!("{} = {}", left, right), ASSIGNMENT)
+}
+
+fn conditional_to_c<E: Exp>(
+    condition: &E,
+    then_result: &E,
+    else_result: &E,
+    options: &Options,
+) -> (String, u8) {
+    let condition = operand(condition, CONDITIONAL + 1, options);
+    let then_result = operand(then_result, 0, options);
+    let else_result = operand(else_result, CONDITIONAL, options);
+    (
+        format!("{} ? {} : {}", condition, then_result, else_result),
+        CONDITIONAL,
+    )
+}
+
+fn fun_call_to_c<E: Exp>(f: &String, args: &Vec<E>, options: &Options) -> (String, u8) {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| operand(arg, ASSIGNMENT, options))
+        .collect();
+    (format!("{}({})", f, args.join(", ")), PRIMARY)
+}
+
+impl Exp for ast::UnTypedExp {
+    fn to_c(&self, options: &Options) -> (String, u8) {
+        match self {
+            ast::UnTypedExp::Constant(c) => const_to_c(c),
+            ast::UnTypedExp::Var(v) => (name_to_c(v, options), PRIMARY),
+            ast::UnTypedExp::Cast { target_type, e } => (
+                format!(
+                    "({}){}",
+                    type_to_c(target_type),
+                    operand(&**e, UNARY, options)
+                ),
+                UNARY,
+            ),
+            ast::UnTypedExp::Unary(op, e) => unary_to_c(op, &**e, options),
+            ast::UnTypedExp::Binary(op, e1, e2) => binary_to_c(op, &**e1, &**e2, options),
+            ast::UnTypedExp::Assignment(lhs, rhs) => assignment_to_c(&**lhs, &**rhs, options),
+            ast::UnTypedExp::Conditional {
+                condition,
+                then_result,
+                else_result,
+            } => conditional_to_c(&**condition, &**then_result, &**else_result, options),
+            ast::UnTypedExp::FunCall { f, args } => fun_call_to_c(f, args, options),
+            ast::UnTypedExp::SizeOf(e) => (format!("sizeof({})", operand(&**e, 0, options)), UNARY),
+            ast::UnTypedExp::SizeOfT(t) => (format!("sizeof({})", type_to_c(t)), UNARY),
+        }
+    }
+}
+
+impl Exp for ast::TypedExp {
+    fn to_c(&self, options: &Options) -> (String, u8) {
+        let (text, prec) = match &*self.e {
+            ast::TypedInnerExp::Constant(c) => const_to_c(c),
+            ast::TypedInnerExp::Var(v) => (name_to_c(v, options), PRIMARY),
+            ast::TypedInnerExp::Cast { target_type, e } => (
+                format!("({}){}", type_to_c(target_type), operand(e, UNARY, options)),
+                UNARY,
+            ),
+            ast::TypedInnerExp::Unary(op, e) => unary_to_c(op, e, options),
+            ast::TypedInnerExp::Binary(op, e1, e2) => binary_to_c(op, e1, e2, options),
+            ast::TypedInnerExp::Assignment(lhs, rhs) => assignment_to_c(lhs, rhs, options),
+            ast::TypedInnerExp::Conditional {
+                condition,
+                then_result,
+                else_result,
+            } => conditional_to_c(condition, then_result, else_result, options),
+            ast::TypedInnerExp::Funcall { f, args } => fun_call_to_c(f, args, options),
+        };
+        if !options.annotate_types {
+            (text, prec)
+        } else if prec == PRIMARY {
+            (format!("{} /* {} */", text, self.t), PRIMARY)
+        } else {
+            // 加上括号，注释才能明确地对应整个表达式
+            (format!("({}) /* {} */", text, self.t), PRIMARY)
+        }
+    }
+}
+
+fn exp_to_c<E: Exp>(e: &E, options: &Options) -> String {
+    e.to_c(options).0
+}
+
+fn push_line(out: &mut String, indent: usize, line: &str) {
+    for _ in 0..indent {
+        out.push_str("    ");
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+fn storage_class_to_c(storage_class: &Option<ast::StorageClass>) -> &'static str {
+    match storage_class {
+        Some(ast::StorageClass::Static) => "static ",
+        Some(ast::StorageClass::Extern) => "extern ",
+        None => "",
+    }
+}
+
+fn var_decl_to_c<E: Exp>(decl: &ast::VariableDeclaration<E>, options: &Options) -> String {
+    let mut text = format!(
+        "{}{} {}",
+        storage_class_to_c(&decl.storage_class),
+        type_to_c(&decl.var_type),
+        name_to_c(&decl.name, options)
+    );
+    if let Some(init) = &decl.init {
+        text.push_str(&format!(" = {}", operand(init, ASSIGNMENT, options)));
+    }
+    text
+}
+
+fn escape_string(s: &String) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\0' => escaped.push_str("\\0"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn static_assert_to_c<E: Exp>(decl: &ast::StaticAssertDeclaration<E>, options: &Options) -> String {
+    match &decl.message {
+        Some(message) => format!(
+            "_Static_assert({}, \"{}\");",
+            operand(&decl.condition, ASSIGNMENT, options),
+            escape_string(message)
+        ),
+        None => format!(
+            "_Static_assert({});",
+            operand(&decl.condition, ASSIGNMENT, options)
+        ),
+    }
+}
+
+/// `if (a) if (b) x; else y;`里的else会和内层的if配对，
+/// 所以外层if有else时，如果then分支最后是一个没有else的if，就要加上花括号。
+fn ends_with_dangling_if<E>(s: &ast::Statement<E>) -> bool {
+    match s {
+        ast::Statement::If {
+            condition: _,
+            then_clause: _,
+            else_clause: None,
+        } => true,
+        ast::Statement::If {
+            condition: _,
+            then_clause: _,
+            else_clause: Some(else_clause),
+        } => ends_with_dangling_if(else_clause),
+        ast::Statement::While {
+            condition: _,
+            body,
+            id: _,
+        }
+        | ast::Statement::For {
+            init: _,
+            condition: _,
+            post: _,
+            body,
+            id: _,
+        } => ends_with_dangling_if(body),
+        _ => false,
+    }
+}
+
+/// 打印`header`后面跟着的子语句：复合语句的左花括号和header在同一行，其他语句缩进一层另起一行。
+/// 返回子语句是不是复合语句，调用者据此决定后面的`else`或`while`怎么接。
+fn clause_to_c<E: Exp>(
+    header: String,
+    body: &ast::Statement<E>,
+    braced: bool,
+    indent: usize,
+    out: &mut String,
+    options: &Options,
+) -> bool {
+    match body {
+        ast::Statement::Compound(block) => {
+            push_line(out, indent, &format!("{} {{", header));
+            block_items_to_c(block, indent + 1, out, options);
+            true
+        }
+        _ if braced => {
+            push_line(out, indent, &format!("{} {{", header));
+            statement_to_c(body, indent + 1, out, options);
+            true
+        }
+        _ => {
+            push_line(out, indent, &header);
+            statement_to_c(body, indent + 1, out, options);
+            false
+        }
+    }
+}
+
+fn statement_to_c<E: Exp>(
+    s: &ast::Statement<E>,
+    indent: usize,
+    out: &mut String,
+    options: &Options,
+) {
+    match s {
+        ast::Statement::Return(e) => {
+            push_line(out, indent, &format!("return {};", exp_to_c(e, options)))
+        }
+        ast::Statement::Expression(e) => {
+            push_line(out, indent, &format!("{};", exp_to_c(e, options)))
+        }
+        ast::Statement::If {
+            condition,
+            then_clause,
+            else_clause,
+        } => {
+            let header = format!("if ({})", exp_to_c(condition, options));
+            if_to_c(header, then_clause, else_clause, indent, out, options);
+        }
+        ast::Statement::Compound(block) => {
+            push_line(out, indent, "{");
+            block_items_to_c(block, indent + 1, out, options);
+            push_line(out, indent, "}");
+        }
+        ast::Statement::Break(_) => push_line(out, indent, "break;"),
+        ast::Statement::Continue(_) => push_line(out, indent, "continue;"),
+        ast::Statement::While {
+            condition,
+            body,
+            id: _,
+        } => {
+            let header = format!("while ({})", exp_to_c(condition, options));
+            if clause_to_c(header, body, false, indent, out, options) {
+                push_line(out, indent, "}");
+            }
+        }
+        ast::Statement::DoWhile {
+            body,
+            condition,
+            id: _,
+        } => {
+            let footer = format!("while ({});", exp_to_c(condition, options));
+            if clause_to_c("do".to_string(), body, false, indent, out, options) {
+                push_line(out, indent, &format!("}} {}", footer));
+            } else {
+                push_line(out, indent, &footer);
+            }
+        }
+        ast::Statement::For {
+            init,
+            condition,
+            post,
+            body,
+            id: _,
+        } => {
+            let init = match init {
+                ast::ForInit::InitDecl(d) => var_decl_to_c(d, options),
+                ast::ForInit::InitExp(Some(e)) => exp_to_c(e, options),
+                ast::ForInit::InitExp(None) => "".to_string(),
+            };
+            let condition = match condition {
+                Some(e) => format!(" {}", exp_to_c(e, options)),
+                None => "".to_string(),
+            };
+            let post = match post {
+                Some(e) => format!(" {}", exp_to_c(e, options)),
+                None => "".to_string(),
+            };
+            let header = format!("for ({};{};{})", init, condition, post);
+            if clause_to_c(header, body, false, indent, out, options) {
+                push_line(out, indent, "}");
+            }
+        }
+        ast::Statement::Null => push_line(out, indent, ";"),
+    }
+}
+
+fn if_to_c<E: Exp>(
+    header: String,
+    then_clause: &ast::Statement<E>,
+    else_clause: &Option<Box<ast::Statement<E>>>,
+    indent: usize,
+    out: &mut String,
+    options: &Options,
+) {
+    let braced = else_clause.is_some() && ends_with_dangling_if(then_clause);
+    let then_braced = clause_to_c(header, then_clause, braced, indent, out, options);
+    match else_clause {
+        None => {
+            if then_braced {
+                push_line(out, indent, "}");
+            }
+        }
+        Some(else_clause) => {
+            let prefix = if then_braced { "} else" } else { "else" };
+            match &**else_clause {
+                // else if链保持在同一个缩进层次
+                ast::Statement::If {
+                    condition,
+                    then_clause,
+                    else_clause,
+                } => {
+                    let header = format!("{} if ({})", prefix, exp_to_c(condition, options));
+                    if_to_c(header, then_clause, else_clause, indent, out, options);
+                }
+                other => {
+                    if clause_to_c(prefix.to_string(), other, false, indent, out, options) {
+                        push_line(out, indent, "}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn block_items_to_c<E: Exp>(
+    block: &ast::Block<E>,
+    indent: usize,
+    out: &mut String,
+    options: &Options,
+) {
+    match block {
+        ast::Block::Block(items) => {
+            for item in items {
+                match item {
+                    ast::BlockItem::S(s) => statement_to_c(s, indent, out, options),
+                    ast::BlockItem::D(d) => declaration_to_c(d, indent, out, options),
+                }
+            }
+        }
+    }
+}
+
+fn fun_decl_to_c<E: Exp>(
+    decl: &ast::FunctionDeclaration<E>,
+    indent: usize,
+    out: &mut String,
+    options: &Options,
+) {
+    let (param_types, ret_type, has_prototype) = match &decl.fun_type {
+        types::Type::FunType {
+            param_types,
+            ret_type,
+            has_prototype,
+        } => (param_types, ret_type, *has_prototype),
+        other => panic!("内部错误：函数{}的类型是{:?}", decl.name, other),
+    };
+    let params = if !has_prototype {
+        "".to_string()
+    } else if param_types.is_empty() {
+        "void".to_string()
+    } else {
+        let params: Vec<String> = param_types
+            .iter()
+            .enumerate()
+            .map(|(i, t)| match decl.params.get(i) {
+                Some(name) => format!("{} {}", type_to_c(t), name_to_c(name, options)),
+                None => type_to_c(t),
+            })
+            .collect();
+        params.join(", ")
+    };
+    let signature = format!(
+        "{}{}{} {}({})",
+        storage_class_to_c(&decl.storage_class),
+        if decl.inline { "inline " } else { "" },
+        type_to_c(ret_type),
+        decl.name,
+        params
+    );
+    match &decl.body {
+        Some(body) => {
+            push_line(out, indent, &format!("{} {{", signature));
+            block_items_to_c(body, indent + 1, out, options);
+            push_line(out, indent, "}");
+        }
+        None => push_line(out, indent, &format!("{};", signature)),
+    }
+}
+
+fn declaration_to_c<E: Exp>(
+    d: &ast::Declaration<E>,
+    indent: usize,
+    out: &mut String,
+    options: &Options,
+) {
+    match d {
+        ast::Declaration::FunDecl(decl) => fun_decl_to_c(decl, indent, out, options),
+        ast::Declaration::VarDecl(decl) => {
+            push_line(out, indent, &format!("{};", var_decl_to_c(decl, options)))
+        }
+        ast::Declaration::StaticAssert(decl) => {
+            push_line(out, indent, &static_assert_to_c(decl, options))
+        }
+    }
+}
+
+pub fn to_string<E: Exp>(program: &ast::ProgType<E>, options: &Options) -> String {
+    let mut out = String::new();
+    match program {
+        ast::ProgType::Program(decls) => {
+            for (i, d) in decls.iter().enumerate() {
+                // 函数定义前后空一行
+                let is_definition = |d: &ast::Declaration<E>| match d {
+                    ast::Declaration::FunDecl(f) => f.body.is_some(),
+                    _ => false,
+                };
+                if i > 0 && (is_definition(d) || is_definition(&decls[i - 1])) {
+                    out.push('\n');
+                }
+                declaration_to_c(d, 0, &mut out, options);
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_print_untyped_program() {
+    fn parse_source(source: &str) -> ast::ProgType<ast::UnTypedExp> {
+        let tokens = crate::lexer::Lexer::new(source.as_bytes()).lex();
+        crate::parser::Parser::new(tokens).parse()
+    }
+
+    let source = "static long counter = -3L;
+        int c_printer_f(int a, int b) {
+            if (a) { if (b) return 1; }
+            else return (a - b) * -(a + b) - (a = b ? 2 : 3);
+        }";
+    let expected = "static long counter = -3L;
+
+int c_printer_f(int a, int b) {
+    if (a) {
+        if (b)
+            return 1;
+    } else
+        return (a - b) * -(a + b) - (a = b ? 2 : 3);
+}
+";
+    let printed = to_string(&parse_source(source), &Options::new());
+    assert_eq!(printed, expected);
+    // 打印出来的代码再解析一遍，结果不变
+    assert_eq!(
+        to_string(&parse_source(&printed), &Options::new()),
+        expected
+    );
+
+    let resolved = crate::identifier_resolution::resolve(parse_source(source));
+    let mut options = Options::new();
+    options.annotate_names = true;
+    let annotated = to_string(&resolved, &options);
+    assert!(annotated.contains("int c_printer_f(int a /* a."));
+    assert!(annotated.contains("static long counter = -3L;"));
+}
+
+#[test]
+fn test_annotate_types() {
+    fn typecheck_source(source: &str) -> ast::ProgType<ast::TypedExp> {
+        let tokens = crate::lexer::Lexer::new(source.as_bytes()).lex();
+        let program = crate::parser::Parser::new(tokens).parse();
+        let resolved = crate::identifier_resolution::resolve(program);
+        let labeled = crate::label_loops::label_loops(resolved);
+        crate::typecheck::typecheck(labeled)
+    }
+
+    let program = typecheck_source("long c_printer_g(int x) { return x + 1L; }");
+    // 类型检查插入的隐式转换也打印出来，convert_to即使类型相同也会插入转换
+    assert_eq!(
+        to_string(&program, &Options::new()),
+        "long c_printer_g(int x) {\n    return (long)((long)x + (long)1L);\n}\n"
+    );
+    let mut options = Options::new();
+    options.annotate_types = true;
+    assert!(to_string(&program, &options).contains(
+        "return ((long)(((long)x /* int */) /* long */ + ((long)1L /* long */) /* long */) /* long */) /* long */;"
+    ));
+}
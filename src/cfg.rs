@@ -0,0 +1,454 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{assembly, ir};
+
+/// 构建控制流图只需要知道每条指令是不是标签、跳转或者返回，
+/// 所以IR和汇编指令都先归约成这几种情况。
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimpleInstr {
+    Label(String),
+    ConditionalJump(String),
+    UnconditionalJump(String),
+    Return,
+    Other,
+}
+
+pub trait Instr: Clone {
+    fn simplify(&self) -> SimpleInstr;
+
+    /// 条件跳转是不是在条件为真时跳到目标，画控制流图时用来标注边。
+    fn jumps_when_true(&self) -> bool {
+        true
+    }
+}
+
+impl Instr for ir::Instruction {
+    fn simplify(&self) -> SimpleInstr {
+        match self {
+            ir::Instruction::Label(l) => SimpleInstr::Label(l.clone()),
+            ir::Instruction::Jump(target) => SimpleInstr::UnconditionalJump(target.clone()),
+            ir::Instruction::JumpIfZero(_, target) | ir::Instruction::JumpIfNotZero(_, target) => {
+                SimpleInstr::ConditionalJump(target.clone())
+            }
+            ir::Instruction::Return(_) => SimpleInstr::Return,
+            _ => SimpleInstr::Other,
+        }
+    }
+
+    fn jumps_when_true(&self) -> bool {
+        match self {
+            ir::Instruction::JumpIfZero(_, _) => false,
+            _ => true,
+        }
+    }
+}
+
+impl Instr for assembly::Instruction {
+    fn simplify(&self) -> SimpleInstr {
+        match self {
+            assembly::Instruction::Label(l) => SimpleInstr::Label(l.clone()),
+            assembly::Instruction::Jmp(target) => SimpleInstr::UnconditionalJump(target.clone()),
+            assembly::Instruction::JmpCC(_, target) => SimpleInstr::ConditionalJump(target.clone()),
+            assembly::Instruction::Ret => SimpleInstr::Return,
+            _ => SimpleInstr::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NodeId {
+    Entry,
+    Block(usize),
+    Exit,
+}
+
+/// 每个基本块和其中的每条指令都带一个注解`V`，数据流分析把分析结果存在这里。
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock<V, I> {
+    pub id: NodeId,
+    pub instructions: Vec<(V, I)>,
+    pub preds: Vec<NodeId>,
+    pub succs: Vec<NodeId>,
+    pub value: V,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Graph<V, I> {
+    pub basic_blocks: BTreeMap<usize, BasicBlock<V, I>>,
+    pub entry_succs: Vec<NodeId>,
+    pub exit_preds: Vec<NodeId>,
+    pub debug_label: String,
+}
+
+fn partition_into_basic_blocks<I: Instr>(instructions: Vec<I>) -> Vec<Vec<I>> {
+    let mut finished_blocks = vec![];
+    let mut current_block = vec![];
+    for instruction in instructions {
+        match instruction.simplify() {
+            SimpleInstr::Label(_) => {
+                if !current_block.is_empty() {
+                    finished_blocks.push(current_block);
+                }
+                current_block = vec![instruction];
+            }
+            SimpleInstr::ConditionalJump(_)
+            | SimpleInstr::UnconditionalJump(_)
+            | SimpleInstr::Return => {
+                current_block.push(instruction);
+                finished_blocks.push(current_block);
+                current_block = vec![];
+            }
+            SimpleInstr::Other => current_block.push(instruction),
+        }
+    }
+    if !current_block.is_empty() {
+        finished_blocks.push(current_block);
+    }
+    finished_blocks
+}
+
+pub fn get_succs<V, I>(node_id: &NodeId, cfg: &Graph<V, I>) -> Vec<NodeId> {
+    match node_id {
+        NodeId::Entry => cfg.entry_succs.clone(),
+        NodeId::Block(n) => cfg.basic_blocks[n].succs.clone(),
+        NodeId::Exit => vec![],
+    }
+}
+
+pub fn get_preds<V, I>(node_id: &NodeId, cfg: &Graph<V, I>) -> Vec<NodeId> {
+    match node_id {
+        NodeId::Entry => vec![],
+        NodeId::Block(n) => cfg.basic_blocks[n].preds.clone(),
+        NodeId::Exit => cfg.exit_preds.clone(),
+    }
+}
+
+pub fn get_block_value<V: Clone, I>(block_num: usize, cfg: &Graph<V, I>) -> V {
+    cfg.basic_blocks[&block_num].value.clone()
+}
+
+pub fn add_edge<V, I>(pred: NodeId, succ: NodeId, cfg: &mut Graph<V, I>) {
+    let succs = match &pred {
+        NodeId::Entry => &mut cfg.entry_succs,
+        NodeId::Block(n) => &mut cfg.basic_blocks.get_mut(n).unwrap().succs,
+        NodeId::Exit => panic!("内部错误：出口节点不能有后继。"),
+    };
+    if !succs.contains(&succ) {
+        succs.push(succ.clone());
+    }
+    let preds = match &succ {
+        NodeId::Entry => panic!("内部错误：入口节点不能有前驱。"),
+        NodeId::Block(n) => &mut cfg.basic_blocks.get_mut(n).unwrap().preds,
+        NodeId::Exit => &mut cfg.exit_preds,
+    };
+    if !preds.contains(&pred) {
+        preds.push(pred);
+    }
+}
+
+pub fn remove_edge<V, I>(pred: NodeId, succ: NodeId, cfg: &mut Graph<V, I>) {
+    match &pred {
+        NodeId::Entry => cfg.entry_succs.retain(|n| *n != succ),
+        NodeId::Block(n) => {
+            if let Some(block) = cfg.basic_blocks.get_mut(n) {
+                block.succs.retain(|s| *s != succ);
+            }
+        }
+        NodeId::Exit => panic!("内部错误：出口节点没有后继。"),
+    }
+    match &succ {
+        NodeId::Entry => panic!("内部错误：入口节点没有前驱。"),
+        NodeId::Block(n) => {
+            if let Some(block) = cfg.basic_blocks.get_mut(n) {
+                block.preds.retain(|p| *p != pred);
+            }
+        }
+        NodeId::Exit => cfg.exit_preds.retain(|p| *p != pred),
+    }
+}
+
+pub fn update_basic_block<V, I>(
+    block_idx: usize,
+    new_block: BasicBlock<V, I>,
+    cfg: &mut Graph<V, I>,
+) {
+    cfg.basic_blocks.insert(block_idx, new_block);
+}
+
+fn add_all_edges<I: Instr>(cfg: &mut Graph<(), I>) {
+    let mut label_map = HashMap::new();
+    for (id, block) in cfg.basic_blocks.iter() {
+        if let Some((_, first)) = block.instructions.first() {
+            if let SimpleInstr::Label(l) = first.simplify() {
+                label_map.insert(l, NodeId::Block(*id));
+            }
+        }
+    }
+    let block_ids: Vec<usize> = cfg.basic_blocks.keys().cloned().collect();
+    for (i, id) in block_ids.iter().enumerate() {
+        let next_block = if i + 1 == block_ids.len() {
+            NodeId::Exit
+        } else {
+            NodeId::Block(block_ids[i + 1])
+        };
+        let last = cfg.basic_blocks[id]
+            .instructions
+            .last()
+            .unwrap()
+            .1
+            .simplify();
+        let lookup = |target: &String| match label_map.get(target) {
+            Some(n) => n.clone(),
+            None => panic!("内部错误：跳转目标{}不存在。", target),
+        };
+        match last {
+            SimpleInstr::Return => add_edge(NodeId::Block(*id), NodeId::Exit, cfg),
+            SimpleInstr::UnconditionalJump(target) => {
+                let target_id = lookup(&target);
+                add_edge(NodeId::Block(*id), target_id, cfg)
+            }
+            SimpleInstr::ConditionalJump(target) => {
+                let target_id = lookup(&target);
+                add_edge(NodeId::Block(*id), next_block, cfg);
+                add_edge(NodeId::Block(*id), target_id, cfg)
+            }
+            _ => add_edge(NodeId::Block(*id), next_block, cfg),
+        }
+    }
+    match block_ids.first() {
+        Some(first) => add_edge(NodeId::Entry, NodeId::Block(*first), cfg),
+        None => add_edge(NodeId::Entry, NodeId::Exit, cfg),
+    }
+}
+
+/// 把线性的指令列表切分成基本块：标签开始一个新块，跳转和返回结束当前块。
+pub fn instructions_to_cfg<I: Instr>(debug_label: String, instructions: Vec<I>) -> Graph<(), I> {
+    let mut basic_blocks = BTreeMap::new();
+    for (idx, instructions) in partition_into_basic_blocks(instructions)
+        .into_iter()
+        .enumerate()
+    {
+        basic_blocks.insert(
+            idx,
+            BasicBlock {
+                id: NodeId::Block(idx),
+                instructions: instructions.into_iter().map(|i| ((), i)).collect(),
+                preds: vec![],
+                succs: vec![],
+                value: (),
+            },
+        );
+    }
+    let mut cfg = Graph {
+        basic_blocks: basic_blocks,
+        entry_succs: vec![],
+        exit_preds: vec![],
+        debug_label: debug_label,
+    };
+    add_all_edges(&mut cfg);
+    cfg
+}
+
+/// 按照块编号的顺序把基本块重新拼成线性的指令列表。
+pub fn cfg_to_instructions<V, I>(cfg: Graph<V, I>) -> Vec<I> {
+    let mut instructions = vec![];
+    for (_, block) in cfg.basic_blocks {
+        for (_, i) in block.instructions {
+            instructions.push(i);
+        }
+    }
+    instructions
+}
+
+pub fn initialize_annotation<V: Clone, W: Clone, I>(cfg: Graph<V, I>, dummy: W) -> Graph<W, I> {
+    let mut basic_blocks = BTreeMap::new();
+    for (idx, block) in cfg.basic_blocks {
+        basic_blocks.insert(
+            idx,
+            BasicBlock {
+                id: block.id,
+                instructions: block
+                    .instructions
+                    .into_iter()
+                    .map(|(_, i)| (dummy.clone(), i))
+                    .collect(),
+                preds: block.preds,
+                succs: block.succs,
+                value: dummy.clone(),
+            },
+        );
+    }
+    Graph {
+        basic_blocks: basic_blocks,
+        entry_succs: cfg.entry_succs,
+        exit_preds: cfg.exit_preds,
+        debug_label: cfg.debug_label,
+    }
+}
+
+pub fn strip_annotations<V: Clone, I>(cfg: Graph<V, I>) -> Graph<(), I> {
+    initialize_annotation(cfg, ())
+}
+
+/// 一条汇编指令可能打印成多行（比如`ret`前面的恢复栈帧），换行换成`\l`，
+/// 行首的制表符去掉，这样每一行都左对齐。
+fn escape_dot(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\l"),
+            '\t' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn dot_node(node_id: &NodeId) -> String {
+    match node_id {
+        NodeId::Entry => "entry".to_string(),
+        NodeId::Block(n) => format!("block{}", n),
+        NodeId::Exit => "exit".to_string(),
+    }
+}
+
+/// 把控制流图输出成Graphviz的dot格式。每个基本块是一个节点，里面逐行列出指令，
+/// `show_annotation`返回非空字符串时写在对应指令的后面；条件跳转的两条出边标注true和false。
+pub fn to_graphviz<V, I: Instr>(
+    cfg: &Graph<V, I>,
+    show_instruction: fn(&I) -> String,
+    show_annotation: fn(&V) -> String,
+) -> String {
+    let mut label_map = HashMap::new();
+    for (id, block) in cfg.basic_blocks.iter() {
+        if let Some((_, first)) = block.instructions.first() {
+            if let SimpleInstr::Label(l) = first.simplify() {
+                label_map.insert(l, NodeId::Block(*id));
+            }
+        }
+    }
+    let mut out = String::new();
+    out.push_str(&format!(
+        "digraph \"{}\" {{\n",
+        escape_dot(&cfg.debug_label)
+    ));
+    out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+    out.push_str("    entry [shape=ellipse];\n");
+    out.push_str("    exit [shape=ellipse];\n");
+    for (id, block) in cfg.basic_blocks.iter() {
+        // dot标签里的\l表示换行并且让这一行左对齐
+        let mut label = format!("block {}\\l", id);
+        for (annotation, instruction) in block.instructions.iter() {
+            let annotation = show_annotation(annotation);
+            let line = if annotation.is_empty() {
+                show_instruction(instruction)
+            } else {
+                format!("{}    // {}", show_instruction(instruction), annotation)
+            };
+            label.push_str(&escape_dot(&line));
+            label.push_str("\\l");
+        }
+        out.push_str(&format!("    block{} [label=\"{}\"];\n", id, label));
+    }
+    for succ in cfg.entry_succs.iter() {
+        out.push_str(&format!("    entry -> {};\n", dot_node(succ)));
+    }
+    for (id, block) in cfg.basic_blocks.iter() {
+        let last = &block.instructions.last().unwrap().1;
+        let target = match last.simplify() {
+            SimpleInstr::ConditionalJump(target) => label_map.get(&target).cloned(),
+            _ => None,
+        };
+        for succ in block.succs.iter() {
+            let edge_label = match &target {
+                // 跳转目标就是下一个块时两条边重合
+                Some(_) if block.succs.len() == 1 => " [label=\"true/false\"]",
+                Some(t) if (t == succ) == last.jumps_when_true() => " [label=\"true\"]",
+                Some(_) => " [label=\"false\"]",
+                None => "",
+            };
+            out.push_str(&format!(
+                "    block{} -> {}{};\n",
+                id,
+                dot_node(succ),
+                edge_label
+            ));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[test]
+fn test_round_trip() {
+    use crate::constants;
+    let instructions = vec![
+        ir::Instruction::JumpIfZero(ir::IrValue::Var("x".to_string()), "else".to_string()),
+        ir::Instruction::Return(ir::IrValue::Constant(constants::INT_ONE)),
+        ir::Instruction::Label("else".to_string()),
+        ir::Instruction::Return(ir::IrValue::Constant(constants::INT_ZERO)),
+    ];
+    let cfg = instructions_to_cfg("f".to_string(), instructions.clone());
+    assert_eq!(cfg.basic_blocks.len(), 3);
+    assert_eq!(
+        get_succs(&NodeId::Block(0), &cfg),
+        vec![NodeId::Block(1), NodeId::Block(2)]
+    );
+    assert_eq!(
+        get_preds(&NodeId::Exit, &cfg),
+        vec![NodeId::Block(1), NodeId::Block(2)]
+    );
+    assert_eq!(cfg_to_instructions(cfg), instructions);
+}
+
+#[test]
+fn test_graphviz_branch_labels() {
+    let instructions = vec![
+        ir::Instruction::JumpIfZero(ir::IrValue::Var("x".to_string()), "else".to_string()),
+        ir::Instruction::Return(ir::IrValue::Constant(crate::constants::INT_ONE)),
+        ir::Instruction::Label("else".to_string()),
+        ir::Instruction::Return(ir::IrValue::Var("x".to_string())),
+    ];
+    let cfg = instructions_to_cfg("f".to_string(), instructions);
+    let dot = to_graphviz(&cfg, |i| i.to_string(), |_| "".to_string());
+    assert!(dot.starts_with("digraph \"f\" {"));
+    assert!(dot.contains("block0 -> block1 [label=\"true\"];"));
+    assert!(dot.contains("block0 -> block2 [label=\"false\"];"));
+    assert!(dot.contains("block2 -> exit;"));
+    assert!(dot.contains("block 2\\lelse:\\lreturn x\\l"));
+}
+
+#[test]
+fn test_graphviz_assembly() {
+    use crate::{assembly, emit};
+    let instructions = vec![
+        assembly::Instruction::Cmp(
+            assembly::AsmType::Longword,
+            assembly::Operand::Imm(0),
+            assembly::Operand::Reg(assembly::Reg::DI),
+        ),
+        assembly::Instruction::JmpCC(assembly::CondCode::E, "cfg.zero".to_string()),
+        assembly::Instruction::Mov(
+            assembly::AsmType::Longword,
+            assembly::Operand::Imm(1),
+            assembly::Operand::Reg(assembly::Reg::AX),
+        ),
+        assembly::Instruction::Ret,
+        assembly::Instruction::Label("cfg.zero".to_string()),
+        assembly::Instruction::Mov(
+            assembly::AsmType::Longword,
+            assembly::Operand::Imm(0),
+            assembly::Operand::Reg(assembly::Reg::AX),
+        ),
+        assembly::Instruction::Ret,
+    ];
+    let cfg = instructions_to_cfg("g".to_string(), instructions);
+    let dot = to_graphviz(&cfg, emit::show_instruction, |_| "".to_string());
+    assert!(dot.contains("block0 -> block2 [label=\"true\"];"));
+    assert!(dot.contains("block0 -> block1 [label=\"false\"];"));
+    // ret打印成三行，每一行都单独左对齐，标签里不能有真正的换行和制表符
+    assert!(dot.contains("block 1\\lmovl $1, %eax\\lmovq %rbp, %rsp\\lpopq %rbp\\lret\\l\"];"));
+    assert!(!dot.contains('\t'));
+}
@@ -9,10 +9,11 @@ const PARAM_PASSING_REGS: [assembly::Reg; 6] = [
     assembly::Reg::R9,
 ];
 
-const ZERO: assembly::Operand = assembly::Operand::Imm(0 as i64);
-
 fn convert_val(ir_value: ir::IrValue) -> assembly::Operand {
     match ir_value {
+        ir::IrValue::Constant(constants::T::ConstBool(b)) => assembly::Operand::Imm(b as i64),
+        ir::IrValue::Constant(constants::T::ConstShort(s)) => assembly::Operand::Imm(s as i64),
+        ir::IrValue::Constant(constants::T::ConstUShort(u)) => assembly::Operand::Imm(u as i64),
         ir::IrValue::Constant(constants::T::ConstInt(i)) => assembly::Operand::Imm(i as i64),
         ir::IrValue::Constant(constants::T::ConstLong(i)) => assembly::Operand::Imm(i),
         ir::IrValue::Var(v) => assembly::Operand::Pseudo(v),
@@ -21,22 +22,29 @@ fn convert_val(ir_value: ir::IrValue) -> assembly::Operand {
 
 fn convert_type(t: types::Type) -> assembly::AsmType {
     match t {
+        types::Type::Bool => assembly::AsmType::Byte,
+        types::Type::Short | types::Type::UShort => assembly::AsmType::Word,
         types::Type::Int => assembly::AsmType::Longword,
         types::Type::Long => assembly::AsmType::Quadword,
         types::Type::FunType {
             param_types: _,
             ret_type: _,
+            has_prototype: _,
         } => {
             panic!("内部错误，无法将函数类型转换成汇编代码。")
         }
     }
 }
 
-fn asm_type(t: ir::IrValue) -> assembly::AsmType {
+fn asm_type(t: &ir::IrValue) -> assembly::AsmType {
     match t {
         ir::IrValue::Constant(constants::T::ConstLong(_)) => assembly::AsmType::Quadword,
         ir::IrValue::Constant(constants::T::ConstInt(_)) => assembly::AsmType::Longword,
-        ir::IrValue::Var(v) => convert_type(symbols::get(v).t),
+        ir::IrValue::Constant(
+            constants::T::ConstShort(_) | constants::T::ConstUShort(_),
+        ) => assembly::AsmType::Word,
+        ir::IrValue::Constant(constants::T::ConstBool(_)) => assembly::AsmType::Byte,
+        ir::IrValue::Var(v) => convert_type(symbols::get(v.clone()).t),
     }
 }
 
@@ -97,7 +105,7 @@ fn convert_function_call(
         vec![assembly::Instruction::Binary {
             op: assembly::BinaryOperator::Sub,
             t: assembly::AsmType::Quadword,
-            src: assembly::Operand::Imm(stack_padding as i64),
+            src: assembly::Operand::Imm(stack_padding),
             dst: assembly::Operand::Reg(assembly::Reg::SP),
         }]
     };
@@ -110,7 +118,7 @@ fn convert_function_call(
             assembly::Operand::Reg(r),
         ));
     }
-    for (_, stack_arg) in stack_args.iter().rev().enumerate() {
+    for stack_arg in stack_args.iter().rev() {
         let assembly_arg = convert_val(stack_arg.clone());
         instructions.append(&mut match assembly_arg {
             assembly::Operand::Imm(_) | assembly::Operand::Reg(_) => {
@@ -119,9 +127,7 @@ fn convert_function_call(
             _ => {
                 let assemby_type = asm_type(stack_arg);
                 if assemby_type == assembly::AsmType::Quadword {
-                    vec![assembly::Instruction::Push(assembly::Operand::Reg(
-                        assembly::Reg::AX,
-                    ))]
+                    vec![assembly::Instruction::Push(assembly_arg)]
                 } else {
                     vec![
                         assembly::Instruction::Mov(
@@ -143,14 +149,14 @@ fn convert_function_call(
         vec![assembly::Instruction::Binary {
             op: assembly::BinaryOperator::Add,
             t: assembly::AsmType::Quadword,
-            src: assembly::Operand::Imm(bytes_to_remove as i64),
+            src: assembly::Operand::Imm(bytes_to_remove),
             dst: assembly::Operand::Reg(assembly::Reg::SP),
         }]
     };
     instructions.append(&mut dealloc);
-    let assembly_dst = convert_val(dst);
+    let assembly_dst = convert_val(dst.clone());
     instructions.push(assembly::Instruction::Mov(
-        asm_type(dst),
+        asm_type(&dst),
         assembly::Operand::Reg(assembly::Reg::AX),
         assembly_dst,
     ));
@@ -160,13 +166,13 @@ fn convert_function_call(
 fn convert_instruction(ir_instruction: ir::Instruction) -> Vec<assembly::Instruction> {
     match ir_instruction {
         ir::Instruction::Copy { src, dst } => {
-            let t = asm_type(src);
+            let t = asm_type(&src);
             let asm_src = convert_val(src);
             let asm_dst = convert_val(dst);
             vec![assembly::Instruction::Mov(t, asm_src, asm_dst)]
         }
         ir::Instruction::Return(ir_value) => {
-            let t = asm_type(ir_value);
+            let t = asm_type(&ir_value);
             let asm_val = convert_val(ir_value);
             vec![
                 assembly::Instruction::Mov(t, asm_val, assembly::Operand::Reg(assembly::Reg::AX)),
@@ -178,8 +184,8 @@ fn convert_instruction(ir_instruction: ir::Instruction) -> Vec<assembly::Instruc
             src,
             dst,
         } => {
-            let src_t = asm_type(src);
-            let dst_t = asm_type(dst);
+            let src_t = asm_type(&src);
+            let dst_t = asm_type(&dst);
             let asm_src = convert_val(src);
             let asm_dst = convert_val(dst);
             vec![
@@ -189,7 +195,7 @@ fn convert_instruction(ir_instruction: ir::Instruction) -> Vec<assembly::Instruc
             ]
         }
         ir::Instruction::Unary { op, src, dst } => {
-            let t = asm_type(src);
+            let t = asm_type(&src);
             let asm_op = convert_unop(op);
             let asm_src = convert_val(src);
             let asm_dst = convert_val(dst);
@@ -204,8 +210,8 @@ fn convert_instruction(ir_instruction: ir::Instruction) -> Vec<assembly::Instruc
             src2,
             dst,
         } => {
-            let src_t = asm_type(src1);
-            let dst_t = asm_type(dst);
+            let src_t = asm_type(&src1);
+            let dst_t = asm_type(&dst);
             let asm_src1 = convert_val(src1);
             let asm_src2 = convert_val(src2);
             let asm_dst = convert_val(dst);
@@ -263,7 +269,7 @@ fn convert_instruction(ir_instruction: ir::Instruction) -> Vec<assembly::Instruc
         }
         ir::Instruction::Jump(target) => vec![assembly::Instruction::Jmp(target)],
         ir::Instruction::JumpIfZero(cond, target) => {
-            let t = asm_type(cond);
+            let t = asm_type(&cond);
             let asm_cond = convert_val(cond);
             vec![
                 assembly::Instruction::Cmp(t, assembly::Operand::Imm(0), asm_cond),
@@ -271,7 +277,7 @@ fn convert_instruction(ir_instruction: ir::Instruction) -> Vec<assembly::Instruc
             ]
         }
         ir::Instruction::JumpIfNotZero(cond, target) => {
-            let t = asm_type(cond);
+            let t = asm_type(&cond);
             let asm_cond = convert_val(cond);
             vec![
                 assembly::Instruction::Cmp(t, assembly::Operand::Imm(0), asm_cond),
@@ -281,18 +287,34 @@ fn convert_instruction(ir_instruction: ir::Instruction) -> Vec<assembly::Instruc
         ir::Instruction::Label(l) => vec![assembly::Instruction::Label(l)],
         ir::Instruction::FunCall { f, args, dst } => convert_function_call(f, args, dst),
         ir::Instruction::SignExtend { src, dst } => {
+            let src_t = asm_type(&src);
+            let dst_t = asm_type(&dst);
+            let asm_src = convert_val(src);
+            let asm_dst = convert_val(dst);
+            vec![assembly::Instruction::Movsx {
+                src_t: src_t,
+                dst_t: dst_t,
+                src: asm_src,
+                dst: asm_dst,
+            }]
+        }
+        ir::Instruction::ZeroExtend { src, dst } => {
+            let src_t = asm_type(&src);
+            let dst_t = asm_type(&dst);
             let asm_src = convert_val(src);
             let asm_dst = convert_val(dst);
-            vec![assembly::Instruction::Movsx(asm_src, asm_dst)]
+            vec![assembly::Instruction::MovZeroExtend {
+                src_t: src_t,
+                dst_t: dst_t,
+                src: asm_src,
+                dst: asm_dst,
+            }]
         }
         ir::Instruction::Truncate { src, dst } => {
+            let dst_t = asm_type(&dst);
             let asm_src = convert_val(src);
             let asm_dst = convert_val(dst);
-            vec![assembly::Instruction::Mov(
-                assembly::AsmType::Longword,
-                asm_src,
-                asm_dst,
-            )]
+            vec![assembly::Instruction::Mov(dst_t, asm_src, asm_dst)]
         }
     }
 }
@@ -301,7 +323,7 @@ fn pass_params(param_list: Vec<String>) -> Vec<assembly::Instruction> {
     let mut register_params = vec![];
     let mut stack_params = vec![];
     for (i, param) in param_list.iter().enumerate() {
-        if i <= 6 {
+        if i < 6 {
             register_params.push(param.clone());
         } else {
             stack_params.push(param.clone());
@@ -310,7 +332,7 @@ fn pass_params(param_list: Vec<String>) -> Vec<assembly::Instruction> {
     let mut instructions = vec![];
     for (i, param) in register_params.iter().enumerate() {
         let r = PARAM_PASSING_REGS[i].clone();
-        let param_t = asm_type(ir::IrValue::Var(param));
+        let param_t = asm_type(&ir::IrValue::Var(param.clone()));
         instructions.push(assembly::Instruction::Mov(
             param_t,
             assembly::Operand::Reg(r),
@@ -319,7 +341,7 @@ fn pass_params(param_list: Vec<String>) -> Vec<assembly::Instruction> {
     }
     for (i, param) in stack_params.iter().enumerate() {
         let stk = assembly::Operand::Stack(16 + (8 * i as i64));
-        let param_t = asm_type(ir::IrValue::Var(param));
+        let param_t = asm_type(&ir::IrValue::Var(param.clone()));
         instructions.push(assembly::Instruction::Mov(
             param_t,
             stk,
@@ -368,10 +390,16 @@ fn convert_symbol(name: String, entry: symbols::Entry) {
             attrs:
                 symbols::IdentifierAttrs::FunAttr {
                     defined,
-                    global: _,
+                    global,
+                    inline,
+                    inline_definition,
                     stack_frame_size: _,
                 },
-        } => assembly_symbols::add_fun(name, defined),
+        } => {
+            // 内联定义不会输出，对汇编来说这个函数在本文件里没有定义
+            let emitted = defined && !(global && inline && inline_definition);
+            assembly_symbols::add_fun(name, emitted)
+        }
         symbols::Entry {
             t,
             attrs: symbols::IdentifierAttrs::StaticAttr { init: _, global: _ },
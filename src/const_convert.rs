@@ -1,20 +1,29 @@
 use crate::{constants, types};
 
-pub fn const_convert(target_type: types::Type, c: constants::T) -> constants::T {
+pub fn const_to_i64(c: constants::T) -> i64 {
     match c {
-        constants::T::ConstInt(i) => {
-            if target_type == types::Type::Int {
-                constants::T::ConstInt(i)
-            } else {
-                constants::T::ConstLong(i as i64)
-            }
-        }
-        constants::T::ConstLong(i) => {
-            if target_type == types::Type::Long {
-                constants::T::ConstLong(i)
-            } else {
-                constants::T::ConstInt(i as i32)
-            }
-        }
+        constants::T::ConstBool(b) => b as i64,
+        constants::T::ConstShort(s) => s as i64,
+        constants::T::ConstUShort(u) => u as i64,
+        constants::T::ConstInt(i) => i as i64,
+        constants::T::ConstLong(l) => l,
+    }
+}
+
+/// 按照C语言的转换规则把常量转换成目标类型：整数之间截断或者扩展，
+/// 转换成`_Bool`时任何非零值都变成1。
+pub fn const_convert(target_type: types::Type, c: constants::T) -> constants::T {
+    let v = const_to_i64(c);
+    match target_type {
+        types::Type::Bool => constants::T::ConstBool(v != 0),
+        types::Type::Short => constants::T::ConstShort(v as i16),
+        types::Type::UShort => constants::T::ConstUShort(v as u16),
+        types::Type::Int => constants::T::ConstInt(v as i32),
+        types::Type::Long => constants::T::ConstLong(v),
+        types::Type::FunType {
+            param_types: _,
+            ret_type: _,
+            has_prototype: _,
+        } => panic!("内部错误：无法将常量转换成函数类型。"),
     }
 }
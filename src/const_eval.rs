@@ -0,0 +1,234 @@
+use crate::{ast, const_convert, constants, types};
+
+pub fn is_zero(c: constants::T) -> bool {
+    const_convert::const_to_i64(c) == 0
+}
+
+pub fn fold_arith(t: types::Type, v1: i64, v2: i64, op: ast::BinaryOperator) -> Option<constants::T> {
+    // 先在64位上计算，再按照结果类型截断，这样int的溢出会按补码回绕
+    let result = match op {
+        ast::BinaryOperator::Add => v1.wrapping_add(v2),
+        ast::BinaryOperator::Subtract => v1.wrapping_sub(v2),
+        ast::BinaryOperator::Multiply => v1.wrapping_mul(v2),
+        ast::BinaryOperator::Divide | ast::BinaryOperator::Mod if v2 == 0 => return None,
+        ast::BinaryOperator::Divide => v1.wrapping_div(v2),
+        ast::BinaryOperator::Mod => v1.wrapping_rem(v2),
+        _ => panic!("内部错误：不是算术运算符。"),
+    };
+    Some(const_convert::const_convert(
+        t,
+        constants::T::ConstLong(result),
+    ))
+}
+
+pub fn fold_comparison(v1: i64, v2: i64, op: ast::BinaryOperator) -> constants::T {
+    let result = match op {
+        ast::BinaryOperator::Equal => v1 == v2,
+        ast::BinaryOperator::NotEqual => v1 != v2,
+        ast::BinaryOperator::LessThan => v1 < v2,
+        ast::BinaryOperator::LessOrEqual => v1 <= v2,
+        ast::BinaryOperator::GreaterThan => v1 > v2,
+        ast::BinaryOperator::GreaterOrEqual => v1 >= v2,
+        _ => panic!("内部错误：不是比较运算符。"),
+    };
+    if result {
+        constants::INT_ONE
+    } else {
+        constants::INT_ZERO
+    }
+}
+
+/// 对已经做过类型检查的表达式求值，结果是表达式类型的常量。
+/// 不是整数常量表达式（引用了变量、赋值、函数调用，或者除以0）时返回`None`。
+pub fn eval(exp: ast::TypedExp) -> Option<constants::T> {
+    let t = exp.t;
+    match *exp.e {
+        ast::TypedInnerExp::Constant(c) => Some(c),
+        ast::TypedInnerExp::Cast { target_type, e } => {
+            let c = eval(e)?;
+            Some(const_convert::const_convert(target_type, c))
+        }
+        ast::TypedInnerExp::Unary(op, inner) => {
+            let v = const_convert::const_to_i64(eval(inner)?);
+            let result = match op {
+                ast::UnaryOperator::Not => {
+                    return Some(fold_comparison(v, 0, ast::BinaryOperator::Equal))
+                }
+                ast::UnaryOperator::Negate => v.wrapping_neg(),
+                ast::UnaryOperator::Complement => !v,
+            };
+            Some(const_convert::const_convert(
+                t,
+                constants::T::ConstLong(result),
+            ))
+        }
+        ast::TypedInnerExp::Binary(ast::BinaryOperator::And, e1, e2) => {
+            if is_zero(eval(e1)?) {
+                Some(constants::INT_ZERO)
+            } else if is_zero(eval(e2)?) {
+                Some(constants::INT_ZERO)
+            } else {
+                Some(constants::INT_ONE)
+            }
+        }
+        ast::TypedInnerExp::Binary(ast::BinaryOperator::Or, e1, e2) => {
+            if !is_zero(eval(e1)?) {
+                Some(constants::INT_ONE)
+            } else if !is_zero(eval(e2)?) {
+                Some(constants::INT_ONE)
+            } else {
+                Some(constants::INT_ZERO)
+            }
+        }
+        ast::TypedInnerExp::Binary(op, e1, e2) => {
+            let v1 = const_convert::const_to_i64(eval(e1)?);
+            let v2 = const_convert::const_to_i64(eval(e2)?);
+            match op {
+                ast::BinaryOperator::Add
+                | ast::BinaryOperator::Subtract
+                | ast::BinaryOperator::Multiply
+                | ast::BinaryOperator::Divide
+                | ast::BinaryOperator::Mod => fold_arith(t, v1, v2, op),
+                _ => Some(fold_comparison(v1, v2, op)),
+            }
+        }
+        ast::TypedInnerExp::Conditional {
+            condition,
+            then_result,
+            else_result,
+        } => {
+            if is_zero(eval(condition)?) {
+                eval(else_result)
+            } else {
+                eval(then_result)
+            }
+        }
+        ast::TypedInnerExp::Var(_)
+        | ast::TypedInnerExp::Assignment(_, _)
+        | ast::TypedInnerExp::Funcall { f: _, args: _ } => None,
+    }
+}
+
+#[test]
+fn test_eval_casts_wraparound_and_division_by_zero() {
+    fn typed(e: ast::TypedInnerExp, t: types::Type) -> ast::TypedExp {
+        ast::TypedExp {
+            e: Box::new(e),
+            t: t,
+        }
+    }
+    fn int(i: i32) -> ast::TypedExp {
+        typed(
+            ast::TypedInnerExp::Constant(constants::T::ConstInt(i)),
+            types::Type::Int,
+        )
+    }
+    fn long(l: i64) -> ast::TypedExp {
+        typed(
+            ast::TypedInnerExp::Constant(constants::T::ConstLong(l)),
+            types::Type::Long,
+        )
+    }
+    fn cast(t: types::Type, e: ast::TypedExp) -> ast::TypedExp {
+        typed(
+            ast::TypedInnerExp::Cast {
+                target_type: t.clone(),
+                e: e,
+            },
+            t,
+        )
+    }
+    fn binary(op: ast::BinaryOperator, e1: ast::TypedExp, e2: ast::TypedExp) -> ast::TypedExp {
+        let t = e1.t.clone();
+        typed(ast::TypedInnerExp::Binary(op, e1, e2), t)
+    }
+    fn conditional(c: ast::TypedExp, e1: ast::TypedExp, e2: ast::TypedExp) -> ast::TypedExp {
+        let t = e1.t.clone();
+        typed(
+            ast::TypedInnerExp::Conditional {
+                condition: c,
+                then_result: e1,
+                else_result: e2,
+            },
+            t,
+        )
+    }
+
+    // 转换：截断、零扩展，转换成_Bool时非零值都是1
+    assert_eq!(
+        eval(cast(types::Type::Short, int(70000))),
+        Some(constants::T::ConstShort(4464))
+    );
+    assert_eq!(
+        eval(cast(types::Type::Long, cast(types::Type::UShort, int(-1)))),
+        Some(constants::T::ConstLong(65535))
+    );
+    assert_eq!(
+        eval(cast(types::Type::Bool, int(256))),
+        Some(constants::T::ConstBool(true))
+    );
+    assert_eq!(
+        eval(cast(types::Type::Bool, long(0))),
+        Some(constants::T::ConstBool(false))
+    );
+
+    // int的溢出按补码回绕，long不受影响
+    assert_eq!(
+        eval(binary(ast::BinaryOperator::Add, int(2147483647), int(1))),
+        Some(constants::T::ConstInt(-2147483648))
+    );
+    assert_eq!(
+        eval(binary(
+            ast::BinaryOperator::Multiply,
+            int(65536),
+            int(65536)
+        )),
+        Some(constants::T::ConstInt(0))
+    );
+    assert_eq!(
+        eval(typed(
+            ast::TypedInnerExp::Unary(ast::UnaryOperator::Negate, int(-2147483648)),
+            types::Type::Int
+        )),
+        Some(constants::T::ConstInt(-2147483648))
+    );
+    assert_eq!(
+        eval(binary(
+            ast::BinaryOperator::Subtract,
+            long(-2147483648),
+            long(1)
+        )),
+        Some(constants::T::ConstLong(-2147483649))
+    );
+
+    // 除以0不是常量表达式，没有选中的分支和短路掉的操作数不求值
+    let div_by_zero = binary(ast::BinaryOperator::Divide, int(1), int(0));
+    assert_eq!(eval(div_by_zero.clone()), None);
+    assert_eq!(eval(binary(ast::BinaryOperator::Mod, int(1), int(0))), None);
+    assert_eq!(
+        eval(conditional(long(2), int(7), div_by_zero.clone())),
+        Some(constants::T::ConstInt(7))
+    );
+    assert_eq!(
+        eval(conditional(int(0), div_by_zero.clone(), int(3))),
+        Some(constants::T::ConstInt(3))
+    );
+    assert_eq!(eval(conditional(int(1), div_by_zero.clone(), int(3))), None);
+    assert_eq!(
+        eval(binary(ast::BinaryOperator::And, int(0), div_by_zero)),
+        Some(constants::INT_ZERO)
+    );
+
+    // 引用变量的表达式不是常量表达式
+    assert_eq!(
+        eval(binary(
+            ast::BinaryOperator::Add,
+            typed(
+                ast::TypedInnerExp::Var("const_eval.x".to_string()),
+                types::Type::Int
+            ),
+            int(1)
+        )),
+        None
+    );
+}
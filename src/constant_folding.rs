@@ -0,0 +1,188 @@
+use crate::{const_convert, constants, ir, symbols, types};
+
+fn get_dst_type(dst: &ir::IrValue) -> types::Type {
+    match dst {
+        ir::IrValue::Var(v) => symbols::get(v.clone()).t,
+        ir::IrValue::Constant(_) => panic!("内部错误：常量不能作为目的操作数。"),
+    }
+}
+
+fn bool_const(b: bool) -> constants::T {
+    if b {
+        constants::INT_ONE
+    } else {
+        constants::INT_ZERO
+    }
+}
+
+fn evaluate_unop(op: &ir::UnaryOperator, c: constants::T) -> constants::T {
+    let t = constants::type_of_const(&c);
+    let v = const_convert::const_to_i64(c);
+    match op {
+        ir::UnaryOperator::Not => bool_const(v == 0),
+        ir::UnaryOperator::Negate => {
+            const_convert::const_convert(t, constants::T::ConstLong(v.wrapping_neg()))
+        }
+        ir::UnaryOperator::Complement => {
+            const_convert::const_convert(t, constants::T::ConstLong(!v))
+        }
+    }
+}
+
+/// 除以0，以及`INT_MIN / -1`这种在idiv上会触发异常的运算不折叠，
+/// 保证优化后的程序和没优化时在运行时的表现一样。
+fn evaluate_binop(
+    op: &ir::BinaryOperator,
+    c1: constants::T,
+    c2: constants::T,
+) -> Option<constants::T> {
+    let t = constants::type_of_const(&c1);
+    let v1 = const_convert::const_to_i64(c1);
+    let v2 = const_convert::const_to_i64(c2);
+    let min_value = match t {
+        types::Type::Long => i64::MIN,
+        _ => i32::MIN as i64,
+    };
+    let arith = |v: i64| {
+        Some(const_convert::const_convert(
+            t.clone(),
+            constants::T::ConstLong(v),
+        ))
+    };
+    match op {
+        ir::BinaryOperator::Add => arith(v1.wrapping_add(v2)),
+        ir::BinaryOperator::Subtract => arith(v1.wrapping_sub(v2)),
+        ir::BinaryOperator::Multiply => arith(v1.wrapping_mul(v2)),
+        ir::BinaryOperator::Divide | ir::BinaryOperator::Mod
+            if v2 == 0 || (v1 == min_value && v2 == -1) =>
+        {
+            None
+        }
+        ir::BinaryOperator::Divide => arith(v1 / v2),
+        ir::BinaryOperator::Mod => arith(v1 % v2),
+        ir::BinaryOperator::Equal => Some(bool_const(v1 == v2)),
+        ir::BinaryOperator::NotEqual => Some(bool_const(v1 != v2)),
+        ir::BinaryOperator::LessThan => Some(bool_const(v1 < v2)),
+        ir::BinaryOperator::LessOrEqual => Some(bool_const(v1 <= v2)),
+        ir::BinaryOperator::GreaterThan => Some(bool_const(v1 > v2)),
+        ir::BinaryOperator::GreaterOrEqual => Some(bool_const(v1 >= v2)),
+    }
+}
+
+fn fold_copy(c: constants::T, dst: ir::IrValue) -> ir::Instruction {
+    // 常量的类型要和目的操作数一致，比如short和unsigned short之间的Copy
+    let folded = const_convert::const_convert(get_dst_type(&dst), c);
+    ir::Instruction::Copy {
+        src: ir::IrValue::Constant(folded),
+        dst: dst,
+    }
+}
+
+fn optimize_instruction(instruction: ir::Instruction) -> Option<ir::Instruction> {
+    match instruction {
+        ir::Instruction::Unary {
+            op,
+            src: ir::IrValue::Constant(c),
+            dst,
+        } => Some(fold_copy(evaluate_unop(&op, c), dst)),
+        ir::Instruction::Binary {
+            op,
+            src1: ir::IrValue::Constant(c1),
+            src2: ir::IrValue::Constant(c2),
+            dst,
+        } => match evaluate_binop(&op, c1.clone(), c2.clone()) {
+            Some(c) => Some(fold_copy(c, dst)),
+            None => Some(ir::Instruction::Binary {
+                op: op,
+                src1: ir::IrValue::Constant(c1),
+                src2: ir::IrValue::Constant(c2),
+                dst: dst,
+            }),
+        },
+        ir::Instruction::SignExtend {
+            src: ir::IrValue::Constant(c),
+            dst,
+        }
+        | ir::Instruction::ZeroExtend {
+            src: ir::IrValue::Constant(c),
+            dst,
+        }
+        | ir::Instruction::Truncate {
+            src: ir::IrValue::Constant(c),
+            dst,
+        }
+        | ir::Instruction::Copy {
+            src: ir::IrValue::Constant(c),
+            dst,
+        } => Some(fold_copy(c, dst)),
+        ir::Instruction::JumpIfZero(ir::IrValue::Constant(c), target) => {
+            if const_convert::const_to_i64(c) == 0 {
+                Some(ir::Instruction::Jump(target))
+            } else {
+                None
+            }
+        }
+        ir::Instruction::JumpIfNotZero(ir::IrValue::Constant(c), target) => {
+            if const_convert::const_to_i64(c) == 0 {
+                None
+            } else {
+                Some(ir::Instruction::Jump(target))
+            }
+        }
+        other => Some(other),
+    }
+}
+
+pub fn optimize(instructions: Vec<ir::Instruction>) -> Vec<ir::Instruction> {
+    instructions
+        .into_iter()
+        .filter_map(optimize_instruction)
+        .collect()
+}
+
+#[test]
+fn test_fold_wraparound() {
+    symbols::add_automatic_var("fold_test.0".to_string(), types::Type::Int);
+    let dst = ir::IrValue::Var("fold_test.0".to_string());
+    let folded = optimize(vec![ir::Instruction::Binary {
+        op: ir::BinaryOperator::Add,
+        src1: ir::IrValue::Constant(constants::T::ConstInt(i32::MAX)),
+        src2: ir::IrValue::Constant(constants::T::ConstInt(1)),
+        dst: dst.clone(),
+    }]);
+    assert_eq!(
+        folded,
+        vec![ir::Instruction::Copy {
+            src: ir::IrValue::Constant(constants::T::ConstInt(i32::MIN)),
+            dst: dst,
+        }]
+    );
+}
+
+#[test]
+fn test_keep_division_by_zero() {
+    symbols::add_automatic_var("fold_test.1".to_string(), types::Type::Long);
+    let division = ir::Instruction::Binary {
+        op: ir::BinaryOperator::Divide,
+        src1: ir::IrValue::Constant(constants::T::ConstLong(10)),
+        src2: ir::IrValue::Constant(constants::T::ConstLong(0)),
+        dst: ir::IrValue::Var("fold_test.1".to_string()),
+    };
+    assert_eq!(optimize(vec![division.clone()]), vec![division]);
+}
+
+#[test]
+fn test_fold_conditional_jumps() {
+    let folded = optimize(vec![
+        ir::Instruction::JumpIfZero(ir::IrValue::Constant(constants::INT_ZERO), "a".to_string()),
+        ir::Instruction::JumpIfZero(ir::IrValue::Constant(constants::INT_ONE), "b".to_string()),
+        ir::Instruction::JumpIfNotZero(ir::IrValue::Constant(constants::INT_ONE), "c".to_string()),
+    ]);
+    assert_eq!(
+        folded,
+        vec![
+            ir::Instruction::Jump("a".to_string()),
+            ir::Instruction::Jump("c".to_string()),
+        ]
+    );
+}
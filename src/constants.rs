@@ -1,8 +1,38 @@
+use std::fmt::Display;
+
+use crate::types;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum T {
+    ConstBool(bool),
+    ConstShort(i16),
+    ConstUShort(u16),
     ConstInt(i32),
     ConstLong(i64),
 }
 
 pub const INT_ZERO: T = T::ConstInt(0 as i32);
 pub const INT_ONE: T = T::ConstInt(1 as i32);
+
+pub fn type_of_const(c: &T) -> types::Type {
+    match c {
+        T::ConstBool(_) => types::Type::Bool,
+        T::ConstShort(_) => types::Type::Short,
+        T::ConstUShort(_) => types::Type::UShort,
+        T::ConstInt(_) => types::Type::Int,
+        T::ConstLong(_) => types::Type::Long,
+    }
+}
+
+/// 常量总是带着类型打印，例如`1:int`、`-3:long`，这样文本形式的IR才能原样读回来。
+impl Display for T {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            T::ConstBool(b) => write!(f, "{}:{}", *b as i32, type_of_const(self)),
+            T::ConstShort(s) => write!(f, "{}:{}", s, type_of_const(self)),
+            T::ConstUShort(u) => write!(f, "{}:{}", u, type_of_const(self)),
+            T::ConstInt(i) => write!(f, "{}:{}", i, type_of_const(self)),
+            T::ConstLong(l) => write!(f, "{}:{}", l, type_of_const(self)),
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::{cfg, constants, ir, symbols, types};
+
+#[derive(Clone, Debug, PartialEq)]
+struct Cp {
+    src: ir::IrValue,
+    dst: ir::IrValue,
+}
+
+// 到达某个程序点的所有Copy指令
+type ReachingCopies = Vec<Cp>;
+
+fn get_type(v: &ir::IrValue) -> types::Type {
+    match v {
+        ir::IrValue::Constant(c) => constants::type_of_const(c),
+        ir::IrValue::Var(v) => symbols::get(v.clone()).t,
+    }
+}
+
+fn is_static(v: &ir::IrValue) -> bool {
+    match v {
+        ir::IrValue::Constant(_) => false,
+        ir::IrValue::Var(v) => symbols::is_static(v.clone()),
+    }
+}
+
+fn same_copies(a: &ReachingCopies, b: &ReachingCopies) -> bool {
+    a.len() == b.len() && a.iter().all(|cp| b.contains(cp))
+}
+
+fn intersect(a: ReachingCopies, b: &ReachingCopies) -> ReachingCopies {
+    a.into_iter().filter(|cp| b.contains(cp)).collect()
+}
+
+fn kill(copies: &mut ReachingCopies, updated: &ir::IrValue) {
+    copies.retain(|cp| cp.src != *updated && cp.dst != *updated);
+}
+
+/// `x = y`之后再执行`x = y`或者`y = x`不会改变任何值，这样的Copy可以直接删掉。
+/// 同样宽度的有符号和无符号类型之间的Copy也算在内，因为它们的位模式完全一样。
+fn is_redundant_copy(src: &ir::IrValue, dst: &ir::IrValue, copies: &ReachingCopies) -> bool {
+    copies
+        .iter()
+        .any(|cp| (cp.src == *src && cp.dst == *dst) || (cp.src == *dst && cp.dst == *src))
+}
+
+fn transfer_instruction(copies: &mut ReachingCopies, instruction: &ir::Instruction) {
+    match instruction {
+        ir::Instruction::Copy { src, dst } => {
+            if is_redundant_copy(src, dst, copies) {
+                return;
+            }
+            kill(copies, dst);
+            copies.push(Cp {
+                src: src.clone(),
+                dst: dst.clone(),
+            });
+        }
+        ir::Instruction::FunCall { f: _, args: _, dst } => {
+            // 被调用的函数可能修改任何静态存储期的变量
+            copies.retain(|cp| !is_static(&cp.src) && !is_static(&cp.dst));
+            kill(copies, dst);
+        }
+        ir::Instruction::Unary { op: _, src: _, dst }
+        | ir::Instruction::Binary {
+            op: _,
+            src1: _,
+            src2: _,
+            dst,
+        }
+        | ir::Instruction::SignExtend { src: _, dst }
+        | ir::Instruction::ZeroExtend { src: _, dst }
+        | ir::Instruction::Truncate { src: _, dst } => kill(copies, dst),
+        _ => (),
+    }
+}
+
+fn transfer(
+    block: &cfg::BasicBlock<ReachingCopies, ir::Instruction>,
+    initial: ReachingCopies,
+) -> cfg::BasicBlock<ReachingCopies, ir::Instruction> {
+    let mut current = initial;
+    let mut instructions = vec![];
+    for (_, instruction) in block.instructions.iter() {
+        instructions.push((current.clone(), instruction.clone()));
+        transfer_instruction(&mut current, instruction);
+    }
+    cfg::BasicBlock {
+        id: block.id.clone(),
+        instructions: instructions,
+        preds: block.preds.clone(),
+        succs: block.succs.clone(),
+        value: current,
+    }
+}
+
+fn meet(
+    block: &cfg::BasicBlock<ReachingCopies, ir::Instruction>,
+    all_copies: &ReachingCopies,
+    cfg: &cfg::Graph<ReachingCopies, ir::Instruction>,
+) -> ReachingCopies {
+    let mut incoming = all_copies.clone();
+    for pred in block.preds.iter() {
+        incoming = match pred {
+            cfg::NodeId::Entry => vec![],
+            cfg::NodeId::Block(n) => intersect(incoming, &cfg::get_block_value(*n, cfg)),
+            cfg::NodeId::Exit => panic!("内部错误：出口节点不能是前驱。"),
+        };
+    }
+    incoming
+}
+
+fn find_reaching_copies(
+    cfg: cfg::Graph<(), ir::Instruction>,
+) -> cfg::Graph<ReachingCopies, ir::Instruction> {
+    let mut all_copies = vec![];
+    for block in cfg.basic_blocks.values() {
+        for (_, instruction) in block.instructions.iter() {
+            if let ir::Instruction::Copy { src, dst } = instruction {
+                let cp = Cp {
+                    src: src.clone(),
+                    dst: dst.clone(),
+                };
+                if !all_copies.contains(&cp) {
+                    all_copies.push(cp);
+                }
+            }
+        }
+    }
+    // 交汇运算是求交集，所以除了入口以外的块一开始都假设所有Copy都能到达
+    let mut annotated = cfg::initialize_annotation(cfg, all_copies.clone());
+    let mut worklist: Vec<usize> = annotated.basic_blocks.keys().cloned().collect();
+    while !worklist.is_empty() {
+        let block_idx = worklist.remove(0);
+        let block = annotated.basic_blocks[&block_idx].clone();
+        let incoming = meet(&block, &all_copies, &annotated);
+        let new_block = transfer(&block, incoming);
+        let changed = !same_copies(&block.value, &new_block.value);
+        cfg::update_basic_block(block_idx, new_block, &mut annotated);
+        if changed {
+            for succ in cfg::get_succs(&cfg::NodeId::Block(block_idx), &annotated) {
+                if let cfg::NodeId::Block(n) = succ {
+                    if !worklist.contains(&n) {
+                        worklist.push(n);
+                    }
+                }
+            }
+        }
+    }
+    annotated
+}
+
+fn replace_operand(op: ir::IrValue, copies: &ReachingCopies) -> ir::IrValue {
+    match op {
+        ir::IrValue::Constant(_) => op,
+        // 有符号和无符号之间的Copy不能用来替换操作数，
+        // 因为比较和除法之类的指令要根据操作数的类型选择有符号或者无符号的版本
+        ir::IrValue::Var(_) => match copies.iter().find(|cp| cp.dst == op) {
+            Some(cp) if get_type(&cp.src) == get_type(&cp.dst) => cp.src.clone(),
+            _ => op,
+        },
+    }
+}
+
+fn rewrite_instruction(
+    copies: &ReachingCopies,
+    instruction: ir::Instruction,
+) -> Option<ir::Instruction> {
+    let replace = |op: ir::IrValue| replace_operand(op, copies);
+    match instruction {
+        ir::Instruction::Copy { src, dst } => {
+            if is_redundant_copy(&src, &dst, copies) {
+                None
+            } else {
+                Some(ir::Instruction::Copy {
+                    src: replace(src),
+                    dst: dst,
+                })
+            }
+        }
+        ir::Instruction::Unary { op, src, dst } => Some(ir::Instruction::Unary {
+            op: op,
+            src: replace(src),
+            dst: dst,
+        }),
+        ir::Instruction::Binary {
+            op,
+            src1,
+            src2,
+            dst,
+        } => Some(ir::Instruction::Binary {
+            op: op,
+            src1: replace(src1),
+            src2: replace(src2),
+            dst: dst,
+        }),
+        ir::Instruction::SignExtend { src, dst } => Some(ir::Instruction::SignExtend {
+            src: replace(src),
+            dst: dst,
+        }),
+        ir::Instruction::ZeroExtend { src, dst } => Some(ir::Instruction::ZeroExtend {
+            src: replace(src),
+            dst: dst,
+        }),
+        ir::Instruction::Truncate { src, dst } => Some(ir::Instruction::Truncate {
+            src: replace(src),
+            dst: dst,
+        }),
+        ir::Instruction::JumpIfZero(v, target) => {
+            Some(ir::Instruction::JumpIfZero(replace(v), target))
+        }
+        ir::Instruction::JumpIfNotZero(v, target) => {
+            Some(ir::Instruction::JumpIfNotZero(replace(v), target))
+        }
+        ir::Instruction::Return(v) => Some(ir::Instruction::Return(replace(v))),
+        ir::Instruction::FunCall { f, args, dst } => Some(ir::Instruction::FunCall {
+            f: f,
+            args: args.into_iter().map(replace).collect(),
+            dst: dst,
+        }),
+        other => Some(other),
+    }
+}
+
+pub fn optimize(instructions: Vec<ir::Instruction>) -> Vec<ir::Instruction> {
+    let cfg = cfg::instructions_to_cfg("".to_string(), instructions);
+    let annotated = find_reaching_copies(cfg);
+    let mut optimized = vec![];
+    for (_, block) in annotated.basic_blocks {
+        for (copies, instruction) in block.instructions {
+            if let Some(i) = rewrite_instruction(&copies, instruction) {
+                optimized.push(i);
+            }
+        }
+    }
+    optimized
+}
+
+/// 画出函数的控制流图，每条指令后面标注到达这条指令之前的Copy。
+pub fn to_graphviz(name: &String, instructions: Vec<ir::Instruction>) -> String {
+    let cfg = cfg::instructions_to_cfg(name.clone(), instructions);
+    let annotated = find_reaching_copies(cfg);
+    cfg::to_graphviz(
+        &annotated,
+        |i| i.to_string(),
+        |copies| {
+            let copies: Vec<String> = copies
+                .iter()
+                .map(|cp| format!("{} = {}", cp.dst, cp.src))
+                .collect();
+            format!("{{{}}}", copies.join(", "))
+        },
+    )
+}
+
+#[test]
+fn test_propagate_through_branches() {
+    symbols::add_automatic_var("copy_test.0".to_string(), types::Type::Int);
+    symbols::add_automatic_var("copy_test.1".to_string(), types::Type::Int);
+    let x = ir::IrValue::Var("copy_test.0".to_string());
+    let y = ir::IrValue::Var("copy_test.1".to_string());
+    let three = ir::IrValue::Constant(constants::T::ConstInt(3));
+    let optimized = optimize(vec![
+        ir::Instruction::Copy {
+            src: three.clone(),
+            dst: x.clone(),
+        },
+        ir::Instruction::JumpIfZero(y.clone(), "else".to_string()),
+        ir::Instruction::Copy {
+            src: x.clone(),
+            dst: y.clone(),
+        },
+        ir::Instruction::Label("else".to_string()),
+        ir::Instruction::Return(x.clone()),
+    ]);
+    assert_eq!(
+        optimized,
+        vec![
+            ir::Instruction::Copy {
+                src: three.clone(),
+                dst: x.clone(),
+            },
+            ir::Instruction::JumpIfZero(y.clone(), "else".to_string()),
+            ir::Instruction::Copy {
+                src: three.clone(),
+                dst: y,
+            },
+            ir::Instruction::Label("else".to_string()),
+            ir::Instruction::Return(three),
+        ]
+    );
+}
+
+#[test]
+fn test_keep_signedness_changing_copy() {
+    symbols::add_automatic_var("copy_test.2".to_string(), types::Type::Short);
+    symbols::add_automatic_var("copy_test.3".to_string(), types::Type::UShort);
+    let s = ir::IrValue::Var("copy_test.2".to_string());
+    let u = ir::IrValue::Var("copy_test.3".to_string());
+    let copy = ir::Instruction::Copy {
+        src: s.clone(),
+        dst: u.clone(),
+    };
+    // 反方向的Copy是多余的，但是Return不能改成返回有符号的变量
+    let optimized = optimize(vec![
+        copy.clone(),
+        ir::Instruction::Copy {
+            src: u.clone(),
+            dst: s,
+        },
+        ir::Instruction::Return(u.clone()),
+    ]);
+    assert_eq!(optimized, vec![copy, ir::Instruction::Return(u)]);
+}
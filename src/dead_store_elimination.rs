@@ -0,0 +1,237 @@
+use crate::{cfg, ir, symbols};
+
+// 某个程序点之后还会被读取的变量
+type LiveVars = Vec<String>;
+
+fn static_vars() -> LiveVars {
+    symbols::bindings()
+        .into_iter()
+        .filter_map(|(name, entry)| match entry.attrs {
+            symbols::IdentifierAttrs::StaticAttr { init: _, global: _ } => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+fn same_vars(a: &LiveVars, b: &LiveVars) -> bool {
+    a.len() == b.len() && a.iter().all(|v| b.contains(v))
+}
+
+fn add_var(live: &mut LiveVars, v: &ir::IrValue) {
+    if let ir::IrValue::Var(name) = v {
+        if !live.contains(name) {
+            live.push(name.clone());
+        }
+    }
+}
+
+fn remove_var(live: &mut LiveVars, v: &ir::IrValue) {
+    if let ir::IrValue::Var(name) = v {
+        live.retain(|l| l != name);
+    }
+}
+
+fn transfer_instruction(live: &mut LiveVars, instruction: &ir::Instruction, statics: &LiveVars) {
+    match instruction {
+        ir::Instruction::Unary { op: _, src, dst }
+        | ir::Instruction::Copy { src, dst }
+        | ir::Instruction::SignExtend { src, dst }
+        | ir::Instruction::ZeroExtend { src, dst }
+        | ir::Instruction::Truncate { src, dst } => {
+            remove_var(live, dst);
+            add_var(live, src);
+        }
+        ir::Instruction::Binary {
+            op: _,
+            src1,
+            src2,
+            dst,
+        } => {
+            remove_var(live, dst);
+            add_var(live, src1);
+            add_var(live, src2);
+        }
+        ir::Instruction::JumpIfZero(v, _)
+        | ir::Instruction::JumpIfNotZero(v, _)
+        | ir::Instruction::Return(v) => add_var(live, v),
+        ir::Instruction::FunCall { f: _, args, dst } => {
+            remove_var(live, dst);
+            for arg in args {
+                add_var(live, arg);
+            }
+            // 被调用的函数可能读取任何静态存储期的变量
+            for s in statics {
+                if !live.contains(s) {
+                    live.push(s.clone());
+                }
+            }
+        }
+        ir::Instruction::Jump(_) | ir::Instruction::Label(_) => (),
+    }
+}
+
+/// 反向遍历基本块，每条指令上的注解是紧跟在它之后活跃的变量，块上的注解是块入口处活跃的变量。
+fn transfer(
+    block: &cfg::BasicBlock<LiveVars, ir::Instruction>,
+    end_live_vars: LiveVars,
+    statics: &LiveVars,
+) -> cfg::BasicBlock<LiveVars, ir::Instruction> {
+    let mut current = end_live_vars;
+    let mut instructions = vec![];
+    for (_, instruction) in block.instructions.iter().rev() {
+        instructions.push((current.clone(), instruction.clone()));
+        transfer_instruction(&mut current, instruction, statics);
+    }
+    instructions.reverse();
+    cfg::BasicBlock {
+        id: block.id.clone(),
+        instructions: instructions,
+        preds: block.preds.clone(),
+        succs: block.succs.clone(),
+        value: current,
+    }
+}
+
+fn meet(
+    block: &cfg::BasicBlock<LiveVars, ir::Instruction>,
+    cfg: &cfg::Graph<LiveVars, ir::Instruction>,
+    statics: &LiveVars,
+) -> LiveVars {
+    let mut live = vec![];
+    for succ in block.succs.iter() {
+        let succ_live = match succ {
+            cfg::NodeId::Entry => panic!("内部错误：入口节点不能是后继。"),
+            // 函数返回以后静态变量还可能被读取
+            cfg::NodeId::Exit => statics.clone(),
+            cfg::NodeId::Block(n) => cfg::get_block_value(*n, cfg),
+        };
+        for v in succ_live {
+            if !live.contains(&v) {
+                live.push(v);
+            }
+        }
+    }
+    live
+}
+
+fn find_live_variables(
+    cfg: cfg::Graph<(), ir::Instruction>,
+    statics: &LiveVars,
+) -> cfg::Graph<LiveVars, ir::Instruction> {
+    let mut annotated = cfg::initialize_annotation(cfg, vec![]);
+    let mut worklist: Vec<usize> = annotated.basic_blocks.keys().rev().cloned().collect();
+    while !worklist.is_empty() {
+        let block_idx = worklist.remove(0);
+        let block = annotated.basic_blocks[&block_idx].clone();
+        let end_live_vars = meet(&block, &annotated, statics);
+        let new_block = transfer(&block, end_live_vars, statics);
+        let changed = !same_vars(&block.value, &new_block.value);
+        cfg::update_basic_block(block_idx, new_block, &mut annotated);
+        if changed {
+            for pred in cfg::get_preds(&cfg::NodeId::Block(block_idx), &annotated) {
+                if let cfg::NodeId::Block(n) = pred {
+                    if !worklist.contains(&n) {
+                        worklist.push(n);
+                    }
+                }
+            }
+        }
+    }
+    annotated
+}
+
+/// 函数调用有副作用，即使返回值没人用也不能删。
+fn is_dead_store(live: &LiveVars, instruction: &ir::Instruction) -> bool {
+    match instruction {
+        ir::Instruction::Unary {
+            op: _,
+            src: _,
+            dst: ir::IrValue::Var(v),
+        }
+        | ir::Instruction::Binary {
+            op: _,
+            src1: _,
+            src2: _,
+            dst: ir::IrValue::Var(v),
+        }
+        | ir::Instruction::Copy {
+            src: _,
+            dst: ir::IrValue::Var(v),
+        }
+        | ir::Instruction::SignExtend {
+            src: _,
+            dst: ir::IrValue::Var(v),
+        }
+        | ir::Instruction::ZeroExtend {
+            src: _,
+            dst: ir::IrValue::Var(v),
+        }
+        | ir::Instruction::Truncate {
+            src: _,
+            dst: ir::IrValue::Var(v),
+        } => !live.contains(v),
+        _ => false,
+    }
+}
+
+pub fn optimize(instructions: Vec<ir::Instruction>) -> Vec<ir::Instruction> {
+    let statics = static_vars();
+    let cfg = cfg::instructions_to_cfg("".to_string(), instructions);
+    let annotated = find_live_variables(cfg, &statics);
+    let mut optimized = vec![];
+    for (_, block) in annotated.basic_blocks {
+        for (live, instruction) in block.instructions {
+            if !is_dead_store(&live, &instruction) {
+                optimized.push(instruction);
+            }
+        }
+    }
+    optimized
+}
+
+/// 画出函数的控制流图，每条指令后面标注执行完这条指令之后还活跃的变量。
+pub fn to_graphviz(name: &String, instructions: Vec<ir::Instruction>) -> String {
+    let statics = static_vars();
+    let cfg = cfg::instructions_to_cfg(name.clone(), instructions);
+    let annotated = find_live_variables(cfg, &statics);
+    cfg::to_graphviz(
+        &annotated,
+        |i| i.to_string(),
+        |live| format!("{{{}}}", live.join(", ")),
+    )
+}
+
+#[test]
+fn test_remove_dead_temporaries() {
+    use crate::{constants, types};
+    symbols::add_automatic_var("dead_test.0".to_string(), types::Type::Int);
+    symbols::add_static_var(
+        "dead_test.counter".to_string(),
+        types::Type::Int,
+        false,
+        symbols::InitialValue::Tentative,
+    );
+    let tmp = ir::IrValue::Var("dead_test.0".to_string());
+    let counter = ir::IrValue::Var("dead_test.counter".to_string());
+    let one = ir::IrValue::Constant(constants::INT_ONE);
+    let store_static = ir::Instruction::Copy {
+        src: one.clone(),
+        dst: counter,
+    };
+    let call = ir::Instruction::FunCall {
+        f: "foo".to_string(),
+        args: vec![],
+        dst: tmp.clone(),
+    };
+    let ret = ir::Instruction::Return(one.clone());
+    let optimized = optimize(vec![
+        ir::Instruction::Copy {
+            src: one,
+            dst: tmp.clone(),
+        },
+        store_static.clone(),
+        call.clone(),
+        ret.clone(),
+    ]);
+    assert_eq!(optimized, vec![store_static, call, ret]);
+}
@@ -1,7 +1,11 @@
-use crate::{assembly, initializers, symbols};
+use std::io::{self, Write};
+
+use crate::{assembly, assembly_symbols, initializers};
 
 fn suffix(t: assembly::AsmType) -> String {
     match t {
+        assembly::AsmType::Byte => "b".to_string(),
+        assembly::AsmType::Word => "w".to_string(),
         assembly::AsmType::Longword => "l".to_string(),
         assembly::AsmType::Quadword => "q".to_string(),
     }
@@ -20,7 +24,7 @@ fn show_local_label(label: String) -> String {
 }
 
 fn show_fun_name(f: String) -> String {
-    if symbols::is_defined(f.clone()) {
+    if assembly_symbols::is_defined(f.clone()) {
         f
     } else {
         format!("{}@PLT", f)
@@ -30,6 +34,7 @@ fn show_fun_name(f: String) -> String {
 fn show_long_reg(r: assembly::Reg) -> String {
     match r {
         assembly::Reg::AX => "%eax".to_string(),
+        assembly::Reg::BX => "%ebx".to_string(),
         assembly::Reg::CX => "%ecx".to_string(),
         assembly::Reg::DX => "%edx".to_string(),
         assembly::Reg::DI => "%edi".to_string(),
@@ -38,13 +43,40 @@ fn show_long_reg(r: assembly::Reg) -> String {
         assembly::Reg::R9 => "%r9d".to_string(),
         assembly::Reg::R10 => "%r10d".to_string(),
         assembly::Reg::R11 => "%r11d".to_string(),
+        assembly::Reg::R12 => "%r12d".to_string(),
+        assembly::Reg::R13 => "%r13d".to_string(),
+        assembly::Reg::R14 => "%r14d".to_string(),
+        assembly::Reg::R15 => "%r15d".to_string(),
         assembly::Reg::SP => panic!("内部错误：没有32位的RSP"),
+        assembly::Reg::BP => panic!("内部错误：没有32位的RBP"),
+    }
+}
+
+fn show_word_reg(r: assembly::Reg) -> String {
+    match r {
+        assembly::Reg::AX => "%ax".to_string(),
+        assembly::Reg::BX => "%bx".to_string(),
+        assembly::Reg::CX => "%cx".to_string(),
+        assembly::Reg::DX => "%dx".to_string(),
+        assembly::Reg::DI => "%di".to_string(),
+        assembly::Reg::SI => "%si".to_string(),
+        assembly::Reg::R8 => "%r8w".to_string(),
+        assembly::Reg::R9 => "%r9w".to_string(),
+        assembly::Reg::R10 => "%r10w".to_string(),
+        assembly::Reg::R11 => "%r11w".to_string(),
+        assembly::Reg::R12 => "%r12w".to_string(),
+        assembly::Reg::R13 => "%r13w".to_string(),
+        assembly::Reg::R14 => "%r14w".to_string(),
+        assembly::Reg::R15 => "%r15w".to_string(),
+        assembly::Reg::SP => panic!("内部错误：没有16位的RSP"),
+        assembly::Reg::BP => panic!("内部错误：没有16位的RBP"),
     }
 }
 
 fn show_quadword_reg(r: assembly::Reg) -> String {
     match r {
         assembly::Reg::AX => "%rax".to_string(),
+        assembly::Reg::BX => "%rbx".to_string(),
         assembly::Reg::CX => "%rcx".to_string(),
         assembly::Reg::DX => "%rdx".to_string(),
         assembly::Reg::DI => "%rdi".to_string(),
@@ -53,13 +85,20 @@ fn show_quadword_reg(r: assembly::Reg) -> String {
         assembly::Reg::R9 => "%r9".to_string(),
         assembly::Reg::R10 => "%r10".to_string(),
         assembly::Reg::R11 => "%r11".to_string(),
-        assembly::Reg::SP => "rsp".to_string(),
+        assembly::Reg::R12 => "%r12".to_string(),
+        assembly::Reg::R13 => "%r13".to_string(),
+        assembly::Reg::R14 => "%r14".to_string(),
+        assembly::Reg::R15 => "%r15".to_string(),
+        assembly::Reg::SP => "%rsp".to_string(),
+        assembly::Reg::BP => "%rbp".to_string(),
     }
 }
 
 fn show_operand(t: assembly::AsmType, operand: assembly::Operand) -> String {
     match operand {
         assembly::Operand::Reg(r) => match t {
+            assembly::AsmType::Byte => show_byte_reg(r),
+            assembly::AsmType::Word => show_word_reg(r),
             assembly::AsmType::Longword => show_long_reg(r),
             assembly::AsmType::Quadword => show_quadword_reg(r),
         },
@@ -73,15 +112,21 @@ fn show_operand(t: assembly::AsmType, operand: assembly::Operand) -> String {
 fn show_byte_reg(r: assembly::Reg) -> String {
     match r {
         assembly::Reg::AX => "%al".to_string(),
+        assembly::Reg::BX => "%bl".to_string(),
         assembly::Reg::CX => "%cl".to_string(),
         assembly::Reg::DX => "%dl".to_string(),
         assembly::Reg::DI => "%dil".to_string(),
         assembly::Reg::SI => "%sil".to_string(),
         assembly::Reg::R8 => "%r8b".to_string(),
         assembly::Reg::R9 => "%r9b".to_string(),
-        assembly::Reg::R10 => "r10b".to_string(),
-        assembly::Reg::R11 => "r11b".to_string(),
+        assembly::Reg::R10 => "%r10b".to_string(),
+        assembly::Reg::R11 => "%r11b".to_string(),
+        assembly::Reg::R12 => "%r12b".to_string(),
+        assembly::Reg::R13 => "%r13b".to_string(),
+        assembly::Reg::R14 => "%r14b".to_string(),
+        assembly::Reg::R15 => "%r15b".to_string(),
         assembly::Reg::SP => panic!("内部错误：没有一个字节的RSP寄存器。"),
+        assembly::Reg::BP => panic!("内部错误：没有一个字节的RBP寄存器。"),
     }
 }
 
@@ -104,6 +149,8 @@ fn show_binary_instruction(op: assembly::BinaryOperator) -> String {
         assembly::BinaryOperator::Add => "add".to_string(),
         assembly::BinaryOperator::Mult => "imul".to_string(),
         assembly::BinaryOperator::Sub => "sub".to_string(),
+        assembly::BinaryOperator::Xor => "xor".to_string(),
+        assembly::BinaryOperator::Shl => "sal".to_string(),
     }
 }
 
@@ -153,11 +200,20 @@ fn emit_instruction(instruction: assembly::Instruction) -> String {
                 show_operand(t, dst)
             )
         }
+        assembly::Instruction::Test(t, src, dst) => {
+            format!(
+                "\ttest{} {}, {}\n",
+                suffix(t),
+                show_operand(t, src),
+                show_operand(t, dst)
+            )
+        }
         assembly::Instruction::Idiv(t, operand) => {
             format!("\tidiv{} {}\n", suffix(t), show_operand(t, operand))
         }
         assembly::Instruction::Cdq(assembly::AsmType::Longword) => "\tcdq\n".to_string(),
-        assembly::Instruction::Cdq(assembly::AsmType::Quadword) => "\tcdo\n".to_string(),
+        assembly::Instruction::Cdq(assembly::AsmType::Quadword) => "\tcqo\n".to_string(),
+        assembly::Instruction::Cdq(_) => panic!("内部错误：只有32位和64位的cdq。"),
         assembly::Instruction::Jmp(lbl) => {
             format!("\tjmp {}\n", show_local_label(lbl))
         }
@@ -186,14 +242,51 @@ fn emit_instruction(instruction: assembly::Instruction) -> String {
                 show_operand(assembly::AsmType::Quadword, op)
             )
         }
+        assembly::Instruction::Pop(r) => {
+            format!("\tpopq {}\n", show_quadword_reg(r))
+        }
         assembly::Instruction::Call(f) => {
             format!("\tcall {}\n", show_fun_name(f))
         }
-        assembly::Instruction::Movsx(src, dst) => {
+        assembly::Instruction::Movsx {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => {
+            format!(
+                "\tmovs{}{} {}, {}\n",
+                suffix(src_t),
+                suffix(dst_t),
+                show_operand(src_t, src),
+                show_operand(dst_t, dst)
+            )
+        }
+        // 32位寄存器的写入会自动把高32位清零，所以不存在movzlq指令
+        assembly::Instruction::MovZeroExtend {
+            src_t: assembly::AsmType::Longword,
+            dst_t: _,
+            src,
+            dst,
+        } => {
             format!(
-                "\tmovslq {}, {}\n",
+                "\tmovl {}, {}\n",
                 show_operand(assembly::AsmType::Longword, src),
-                show_operand(assembly::AsmType::Quadword, dst)
+                show_operand(assembly::AsmType::Longword, dst)
+            )
+        }
+        assembly::Instruction::MovZeroExtend {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => {
+            format!(
+                "\tmovz{}{} {}, {}\n",
+                suffix(src_t),
+                suffix(dst_t),
+                show_operand(src_t, src),
+                show_operand(dst_t, dst)
             )
         }
         assembly::Instruction::Ret => "
@@ -215,6 +308,10 @@ fn emit_global_directive(global: bool, label: String) -> String {
 
 fn emit_zero_init(ini: initializers::StaticInit) -> String {
     match ini {
+        initializers::StaticInit::BoolInit(_) => "\t.zero 1\n".to_string(),
+        initializers::StaticInit::ShortInit(_) | initializers::StaticInit::UShortInit(_) => {
+            "\t.zero 2\n".to_string()
+        }
         initializers::StaticInit::IntInit(_) => "\t.zero 4\n".to_string(),
         initializers::StaticInit::LongInit(_) => "\t.zero 8\n".to_string(),
     }
@@ -222,7 +319,10 @@ fn emit_zero_init(ini: initializers::StaticInit) -> String {
 
 fn emit_init(ini: initializers::StaticInit) -> String {
     match ini {
-        initializers::StaticInit::IntInit(i) => format!("\t.quad {}\n", i),
+        initializers::StaticInit::BoolInit(b) => format!("\t.byte {}\n", b as u8),
+        initializers::StaticInit::ShortInit(s) => format!("\t.short {}\n", s),
+        initializers::StaticInit::UShortInit(u) => format!("\t.short {}\n", u),
+        initializers::StaticInit::IntInit(i) => format!("\t.long {}\n", i),
         initializers::StaticInit::LongInit(l) => format!("\t.quad {}\n", l),
     }
 }
@@ -259,7 +359,7 @@ fn emit_tl(f: assembly::TopLevel) -> String {
             alignment,
             global,
             init,
-        } if initializers::is_zero(init) => {
+        } if initializers::is_zero(init.clone()) => {
             let mut result = String::new();
             let label = show_label(name);
             result.push_str(&emit_global_directive(global, label.clone()));
@@ -312,14 +412,108 @@ fn emit_stack_note() -> String {
     "\t.section .note.GNU-stack,\"\",@progbits\n".to_string()
 }
 
-pub fn emit(program: assembly::T) {
+/// 把汇编代码写到任意的`Write`里，写入失败时返回I/O错误。
+pub fn emit<W: Write>(out: &mut W, program: assembly::T) -> io::Result<()> {
     match program {
         assembly::T::Program(tls) => {
             for tl in tls {
-                print!("{}", emit_tl(tl));
+                write!(out, "{}", emit_tl(tl))?;
             }
-            println!();
-            print!("{}", emit_stack_note());
+            writeln!(out)?;
+            write!(out, "{}", emit_stack_note())
         }
     }
 }
+
+/// 单独一条指令的汇编文本，不带缩进和换行，画控制流图时用。
+pub fn show_instruction(instruction: &assembly::Instruction) -> String {
+    emit_instruction(instruction.clone()).trim().to_string()
+}
+
+pub fn to_string(program: assembly::T) -> String {
+    let mut buffer = vec![];
+    // 写到内存里不会失败
+    emit(&mut buffer, program).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[test]
+fn test_emit_to_string() {
+    let program = assembly::T::Program(vec![assembly::TopLevel::Function {
+        name: "main".to_string(),
+        global: true,
+        instructions: vec![
+            assembly::Instruction::Mov(
+                assembly::AsmType::Longword,
+                assembly::Operand::Imm(2),
+                assembly::Operand::Reg(assembly::Reg::AX),
+            ),
+            assembly::Instruction::Ret,
+        ],
+    }]);
+    let asm = to_string(program);
+    assert_eq!(
+        asm,
+        "\t.globl main\n\n\t.text\nmain:\n\tpushq %rbp\n\tmovq %rsp, %rbp\n\tmovl $2, %eax\n\n\tmovq %rbp, %rsp\n\tpopq %rbp\n\tret\n\n\t.section .note.GNU-stack,\"\",@progbits\n"
+    );
+}
+
+#[test]
+fn test_emit_word_instructions() {
+    let show = |instruction: assembly::Instruction| show_instruction(&instruction);
+    assert_eq!(
+        show(assembly::Instruction::Mov(
+            assembly::AsmType::Word,
+            assembly::Operand::Imm(7),
+            assembly::Operand::Reg(assembly::Reg::CX),
+        )),
+        "movw $7, %cx"
+    );
+    assert_eq!(
+        show(assembly::Instruction::Movsx {
+            src_t: assembly::AsmType::Word,
+            dst_t: assembly::AsmType::Longword,
+            src: assembly::Operand::Reg(assembly::Reg::AX),
+            dst: assembly::Operand::Reg(assembly::Reg::DX),
+        }),
+        "movswl %ax, %edx"
+    );
+    assert_eq!(
+        show(assembly::Instruction::MovZeroExtend {
+            src_t: assembly::AsmType::Word,
+            dst_t: assembly::AsmType::Quadword,
+            src: assembly::Operand::Reg(assembly::Reg::AX),
+            dst: assembly::Operand::Reg(assembly::Reg::DX),
+        }),
+        "movzwq %ax, %rdx"
+    );
+    assert_eq!(
+        show(assembly::Instruction::MovZeroExtend {
+            src_t: assembly::AsmType::Byte,
+            dst_t: assembly::AsmType::Longword,
+            src: assembly::Operand::Reg(assembly::Reg::AX),
+            dst: assembly::Operand::Reg(assembly::Reg::DX),
+        }),
+        "movzbl %al, %edx"
+    );
+}
+
+#[test]
+fn test_emit_byte_scratch_registers() {
+    let show = |instruction: assembly::Instruction| show_instruction(&instruction);
+    assert_eq!(
+        show(assembly::Instruction::Mov(
+            assembly::AsmType::Byte,
+            assembly::Operand::Reg(assembly::Reg::R10),
+            assembly::Operand::Reg(assembly::Reg::R11),
+        )),
+        "movb %r10b, %r11b"
+    );
+    assert_eq!(
+        show(assembly::Instruction::SetCC(
+            assembly::CondCode::E,
+            assembly::Operand::Reg(assembly::Reg::R11),
+        )),
+        "sete %r11b"
+    );
+}
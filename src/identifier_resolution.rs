@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::{ast, unique_ids};
+use crate::{ast, unique_ids, warnings, Stage};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct VarEntry {
@@ -26,67 +26,90 @@ fn copy_identifier_map(m: HashMap<String, VarEntry>) -> HashMap<String, VarEntry
 
 fn resolve_optional_exp(
     var_map: HashMap<String, VarEntry>,
-    exp: Option<ast::Exp>,
-) -> Option<ast::Exp> {
+    exp: Option<ast::UnTypedExp>,
+) -> Option<ast::UnTypedExp> {
     match exp {
         Some(e) => Some(resolve_exp(var_map, e)),
         None => None,
     }
 }
 
-fn resolve_exp(id_map: HashMap<String, VarEntry>, exp: ast::Exp) -> ast::Exp {
+fn resolve_exp(id_map: HashMap<String, VarEntry>, exp: ast::UnTypedExp) -> ast::UnTypedExp {
     match exp {
-        ast::Exp::Assignment(left, right) => {
+        ast::UnTypedExp::Assignment(left, right) => {
             match *left {
-                ast::Exp::Var(_) => (),
+                ast::UnTypedExp::Var(_) => (),
                 _ => panic!("赋值语句的左边应该是表达式，实际上是：{:?}", left),
             }
-            ast::Exp::Assignment(
+            ast::UnTypedExp::Assignment(
                 Box::new(resolve_exp(id_map.clone(), *left)),
                 Box::new(resolve_exp(id_map, *right)),
             )
         }
-        ast::Exp::Var(v) => {
+        ast::UnTypedExp::Var(v) => {
             if let Some(_v) = id_map.get(&v) {
-                ast::Exp::Var(_v.clone().unique_name)
+                ast::UnTypedExp::Var(_v.clone().unique_name)
             } else {
                 panic!("未声明变量：{:?}", v)
             }
         }
-        ast::Exp::Cast { target_type, e } => ast::Exp::Cast {
+        ast::UnTypedExp::Cast { target_type, e } => ast::UnTypedExp::Cast {
             target_type: target_type,
-            e: resolve_exp(id_map, e),
+            e: Box::new(resolve_exp(id_map, *e)),
         },
-        ast::Exp::Unary(op, e) => ast::Exp::Unary(op, Box::new(resolve_exp(id_map, *e))),
-        ast::Exp::Binary(op, e1, e2) => ast::Exp::Binary(
+        ast::UnTypedExp::Unary(op, e) => {
+            ast::UnTypedExp::Unary(op, Box::new(resolve_exp(id_map, *e)))
+        }
+        ast::UnTypedExp::Binary(op, e1, e2) => ast::UnTypedExp::Binary(
             op,
             Box::new(resolve_exp(id_map.clone(), *e1)),
             Box::new(resolve_exp(id_map, *e2)),
         ),
-        ast::Exp::Conditional {
+        ast::UnTypedExp::Conditional {
             condition,
             then_result,
             else_result,
-        } => ast::Exp::Conditional {
+        } => ast::UnTypedExp::Conditional {
             condition: Box::new(resolve_exp(id_map.clone(), *condition)),
             then_result: Box::new(resolve_exp(id_map.clone(), *then_result)),
             else_result: Box::new(resolve_exp(id_map, *else_result)),
         },
-        ast::Exp::FunCall { f, args } => {
+        ast::UnTypedExp::FunCall { f, args } => {
             if let Some(fn_name) = id_map.get(&f) {
                 let mut resolved_args = vec![];
                 for arg in args {
                     resolved_args.push(resolve_exp(id_map.clone(), arg));
                 }
-                ast::Exp::FunCall {
+                ast::UnTypedExp::FunCall {
                     f: fn_name.clone().unique_name,
                     args: resolved_args,
                 }
             } else {
-                panic!("未声明函数。")
+                // C89的隐式函数声明：当作`extern int f();`，只给出警告
+                warnings::warn(Stage::Resolve, format!("函数{}隐式声明。", f));
+                let mut resolved_args = vec![];
+                for arg in args {
+                    resolved_args.push(resolve_exp(id_map.clone(), arg));
+                }
+                ast::UnTypedExp::FunCall {
+                    f: f,
+                    args: resolved_args,
+                }
             }
         }
-        c @ ast::Exp::Constant(_) => c,
+        ast::UnTypedExp::SizeOf(e) => ast::UnTypedExp::SizeOf(Box::new(resolve_exp(id_map, *e))),
+        s @ ast::UnTypedExp::SizeOfT(_) => s,
+        c @ ast::UnTypedExp::Constant(_) => c,
+    }
+}
+
+fn resolve_static_assert(
+    id_map: HashMap<String, VarEntry>,
+    sa: ast::StaticAssertDeclaration<ast::UnTypedExp>,
+) -> ast::StaticAssertDeclaration<ast::UnTypedExp> {
+    ast::StaticAssertDeclaration {
+        condition: resolve_exp(id_map, sa.condition),
+        message: sa.message,
     }
 }
 
@@ -128,10 +151,10 @@ fn resolve_local_var_helper(
 
 fn resolve_local_var_declaration(
     id_map: HashMap<String, VarEntry>,
-    vd: ast::VariableDeclaration<ast::Exp>,
+    vd: ast::VariableDeclaration<ast::UnTypedExp>,
 ) -> (
     HashMap<String, VarEntry>,
-    ast::VariableDeclaration<ast::Exp>,
+    ast::VariableDeclaration<ast::UnTypedExp>,
 ) {
     let (new_map, unique_name) =
         resolve_local_var_helper(id_map, vd.name, vd.storage_class.clone());
@@ -152,8 +175,8 @@ fn resolve_local_var_declaration(
 
 fn resolve_for_init(
     id_map: HashMap<String, VarEntry>,
-    init: ast::ForInit<ast::Exp>,
-) -> (HashMap<String, VarEntry>, ast::ForInit<ast::Exp>) {
+    init: ast::ForInit<ast::UnTypedExp>,
+) -> (HashMap<String, VarEntry>, ast::ForInit<ast::UnTypedExp>) {
     match init {
         ast::ForInit::InitExp(e) => (
             id_map.clone(),
@@ -168,8 +191,8 @@ fn resolve_for_init(
 
 fn resolve_statement(
     id_map: HashMap<String, VarEntry>,
-    statement: ast::Statement<ast::Exp>,
-) -> ast::Statement<ast::Exp> {
+    statement: ast::Statement<ast::UnTypedExp>,
+) -> ast::Statement<ast::UnTypedExp> {
     match statement {
         ast::Statement::Return(e) => ast::Statement::Return(resolve_exp(id_map, e)),
         ast::Statement::Expression(e) => ast::Statement::Expression(resolve_exp(id_map, e)),
@@ -230,8 +253,8 @@ fn resolve_statement(
 
 fn resolve_block_item(
     id_map: HashMap<String, VarEntry>,
-    block_item: ast::BlockItem<ast::Exp>,
-) -> (HashMap<String, VarEntry>, ast::BlockItem<ast::Exp>) {
+    block_item: ast::BlockItem<ast::UnTypedExp>,
+) -> (HashMap<String, VarEntry>, ast::BlockItem<ast::UnTypedExp>) {
     match block_item {
         ast::BlockItem::S(s) => {
             let resolved_s = resolve_statement(id_map.clone(), s);
@@ -246,8 +269,8 @@ fn resolve_block_item(
 
 fn resolve_block(
     mut id_map: HashMap<String, VarEntry>,
-    block: ast::Block<ast::Exp>,
-) -> ast::Block<ast::Exp> {
+    block: ast::Block<ast::UnTypedExp>,
+) -> ast::Block<ast::UnTypedExp> {
     match block {
         ast::Block::Block(items) => {
             let mut resolved_items = vec![];
@@ -263,19 +286,24 @@ fn resolve_block(
 
 fn resolve_local_declaration(
     id_map: HashMap<String, VarEntry>,
-    declaration: ast::Declaration<ast::Exp>,
-) -> (HashMap<String, VarEntry>, ast::Declaration<ast::Exp>) {
+    declaration: ast::Declaration<ast::UnTypedExp>,
+) -> (HashMap<String, VarEntry>, ast::Declaration<ast::UnTypedExp>) {
     match declaration {
         ast::Declaration::VarDecl(vd) => {
             let (new_map, resolved_vd) = resolve_local_var_declaration(id_map, vd);
             (new_map, ast::Declaration::VarDecl(resolved_vd))
         }
+        ast::Declaration::StaticAssert(sa) => {
+            let resolved_sa = resolve_static_assert(id_map.clone(), sa);
+            (id_map, ast::Declaration::StaticAssert(resolved_sa))
+        }
         ast::Declaration::FunDecl(ast::FunctionDeclaration {
             name: _,
             fun_type: _,
             params: _,
             body: Some(_),
             storage_class: _,
+            inline: _,
         }) => {
             panic!("C语言不允许定义嵌套函数。");
         }
@@ -285,6 +313,7 @@ fn resolve_local_declaration(
             params: _,
             body: _,
             storage_class: Some(ast::StorageClass::Static),
+            inline: _,
         }) => {
             panic!("static keyword not allowed on local function declarations.")
         }
@@ -311,10 +340,10 @@ fn resolve_params(
 
 fn resolve_function_declaration(
     id_map: HashMap<String, VarEntry>,
-    f: ast::FunctionDeclaration<ast::Exp>,
+    f: ast::FunctionDeclaration<ast::UnTypedExp>,
 ) -> (
     HashMap<String, VarEntry>,
-    ast::FunctionDeclaration<ast::Exp>,
+    ast::FunctionDeclaration<ast::UnTypedExp>,
 ) {
     match id_map.get(&f.name) {
         Some(VarEntry {
@@ -346,6 +375,7 @@ fn resolve_function_declaration(
                     params: resolved_params,
                     body: resolved_body,
                     storage_class: f.storage_class,
+                    inline: f.inline,
                 },
             )
         }
@@ -354,10 +384,10 @@ fn resolve_function_declaration(
 
 pub fn resolve_file_scope_variable_declaration(
     id_map: HashMap<String, VarEntry>,
-    vd: ast::VariableDeclaration<ast::Exp>,
+    vd: ast::VariableDeclaration<ast::UnTypedExp>,
 ) -> (
     HashMap<String, VarEntry>,
-    ast::VariableDeclaration<ast::Exp>,
+    ast::VariableDeclaration<ast::UnTypedExp>,
 ) {
     let mut new_map = id_map.clone();
     new_map.insert(
@@ -373,8 +403,8 @@ pub fn resolve_file_scope_variable_declaration(
 
 pub fn resolve_global_declaration(
     id_map: HashMap<String, VarEntry>,
-    d: ast::Declaration<ast::Exp>,
-) -> (HashMap<String, VarEntry>, ast::Declaration<ast::Exp>) {
+    d: ast::Declaration<ast::UnTypedExp>,
+) -> (HashMap<String, VarEntry>, ast::Declaration<ast::UnTypedExp>) {
     match d {
         ast::Declaration::FunDecl(fd) => {
             let (new_map, fd) = resolve_function_declaration(id_map, fd);
@@ -384,6 +414,10 @@ pub fn resolve_global_declaration(
             let (new_map, resolved_vd) = resolve_file_scope_variable_declaration(id_map, vd);
             (new_map, ast::Declaration::VarDecl(resolved_vd))
         }
+        ast::Declaration::StaticAssert(sa) => {
+            let resolved_sa = resolve_static_assert(id_map.clone(), sa);
+            (id_map, ast::Declaration::StaticAssert(resolved_sa))
+        }
     }
 }
 
@@ -1,24 +1,48 @@
+use std::fmt::Display;
+
 use crate::types;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum StaticInit {
+    BoolInit(bool),
+    ShortInit(i16),
+    UShortInit(u16),
     IntInit(i32),
     LongInit(i64),
 }
 
+impl Display for StaticInit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StaticInit::BoolInit(b) => write!(f, "{}", *b as i32),
+            StaticInit::ShortInit(s) => write!(f, "{}", s),
+            StaticInit::UShortInit(u) => write!(f, "{}", u),
+            StaticInit::IntInit(i) => write!(f, "{}", i),
+            StaticInit::LongInit(l) => write!(f, "{}", l),
+        }
+    }
+}
+
 pub fn zero(t: types::Type) -> StaticInit {
     match t {
+        types::Type::Bool => StaticInit::BoolInit(false),
+        types::Type::Short => StaticInit::ShortInit(0 as i16),
+        types::Type::UShort => StaticInit::UShortInit(0 as u16),
         types::Type::Int => StaticInit::IntInit(0 as i32),
         types::Type::Long => StaticInit::LongInit(0 as i64),
         types::Type::FunType {
             param_types: _,
             ret_type: _,
+            has_prototype: _,
         } => panic!("内部错误：0对于函数类型无意义。"),
     }
 }
 
 pub fn is_zero(t: StaticInit) -> bool {
     match t {
+        StaticInit::BoolInit(b) => !b,
+        StaticInit::ShortInit(s) => s == 0 as i16,
+        StaticInit::UShortInit(u) => u == 0 as u16,
         StaticInit::IntInit(i) => i == 0 as i32,
         StaticInit::LongInit(l) => l == 0 as i64,
     }
@@ -1,62 +1,260 @@
-use crate::{assembly, rounding, symbols};
+use crate::{assembly, assembly_symbols, rounding, symbols};
+
+fn is_memory(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Stack(_) | assembly::Operand::Data(_) => true,
+        _ => false,
+    }
+}
+
+fn is_imm(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Imm(_) => true,
+        _ => false,
+    }
+}
+
+// 大多数指令的立即数只能是32位的
+fn is_large(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Imm(i) => *i < i32::MIN as i64 || *i > i32::MAX as i64,
+        _ => false,
+    }
+}
 
 fn fixup_instruction(instruction: assembly::Instruction) -> Vec<assembly::Instruction> {
     match instruction {
         // mov指令不能将一个值从一个内存地址移动到另一个内存地址
         assembly::Instruction::Mov(
+            t,
             src @ (assembly::Operand::Stack(_) | assembly::Operand::Data(_)),
             dst @ (assembly::Operand::Stack(_) | assembly::Operand::Data(_)),
         ) => vec![
-            assembly::Instruction::Mov(src, assembly::Operand::Reg(assembly::Reg::R10)),
-            assembly::Instruction::Mov(assembly::Operand::Reg(assembly::Reg::R10), dst),
+            assembly::Instruction::Mov(t, src, assembly::Operand::Reg(assembly::Reg::R10)),
+            assembly::Instruction::Mov(t, assembly::Operand::Reg(assembly::Reg::R10), dst),
+        ],
+        // 只有mov到寄存器才能用64位的立即数
+        assembly::Instruction::Mov(
+            assembly::AsmType::Quadword,
+            src @ assembly::Operand::Imm(_),
+            dst @ (assembly::Operand::Stack(_) | assembly::Operand::Data(_)),
+        ) if is_large(&src) => vec![
+            assembly::Instruction::Mov(
+                assembly::AsmType::Quadword,
+                src,
+                assembly::Operand::Reg(assembly::Reg::R10),
+            ),
+            assembly::Instruction::Mov(
+                assembly::AsmType::Quadword,
+                assembly::Operand::Reg(assembly::Reg::R10),
+                dst,
+            ),
         ],
         // idiv指令不能以常量作为操作数
-        assembly::Instruction::Idiv(assembly::Operand::Imm(i)) => vec![
+        assembly::Instruction::Idiv(t, assembly::Operand::Imm(i)) => vec![
             assembly::Instruction::Mov(
+                t,
                 assembly::Operand::Imm(i),
                 assembly::Operand::Reg(assembly::Reg::R10),
             ),
-            assembly::Instruction::Idiv(assembly::Operand::Reg(assembly::Reg::R10)),
+            assembly::Instruction::Idiv(t, assembly::Operand::Reg(assembly::Reg::R10)),
         ],
+        // add和sub的立即数不能超过32位，两个操作数也不能都在内存里
         assembly::Instruction::Binary {
             op: op @ (assembly::BinaryOperator::Add | assembly::BinaryOperator::Sub),
-            src: src @ (assembly::Operand::Stack(_) | assembly::Operand::Data(_)),
-            dst: dst @ (assembly::Operand::Stack(_) | assembly::Operand::Data(_)),
-        } => vec![
-            assembly::Instruction::Mov(src, assembly::Operand::Reg(assembly::Reg::R10)),
+            t,
+            src,
+            dst,
+        } if is_large(&src) || (is_memory(&src) && is_memory(&dst)) => vec![
+            assembly::Instruction::Mov(t, src, assembly::Operand::Reg(assembly::Reg::R10)),
             assembly::Instruction::Binary {
                 op: op,
+                t: t,
                 src: assembly::Operand::Reg(assembly::Reg::R10),
                 dst: dst,
             },
         ],
+        // imul的目的操作数必须是寄存器
         assembly::Instruction::Binary {
             op: assembly::BinaryOperator::Mult,
+            t,
             src,
-            dst: dst @ (assembly::Operand::Stack(_) | assembly::Operand::Data(_)),
-        } => vec![
-            assembly::Instruction::Mov(dst.clone(), assembly::Operand::Reg(assembly::Reg::R11)),
-            assembly::Instruction::Binary {
-                op: assembly::BinaryOperator::Mult,
-                src,
-                dst: assembly::Operand::Reg(assembly::Reg::R11),
-            },
-            assembly::Instruction::Mov(assembly::Operand::Reg(assembly::Reg::R11), dst),
-        ],
-        assembly::Instruction::Cmp(
-            src @ (assembly::Operand::Stack(_) | assembly::Operand::Data(_)),
-            dst @ (assembly::Operand::Stack(_) | assembly::Operand::Data(_)),
-        ) => vec![
-            assembly::Instruction::Mov(src.clone(), assembly::Operand::Reg(assembly::Reg::R10)),
-            assembly::Instruction::Cmp(assembly::Operand::Reg(assembly::Reg::R10), dst),
-        ],
-        assembly::Instruction::Cmp(src, assembly::Operand::Imm(i)) => vec![
+            dst,
+        } if is_large(&src) || is_memory(&dst) => {
+            let mut instructions = vec![];
+            let src = if is_large(&src) {
+                instructions.push(assembly::Instruction::Mov(
+                    t,
+                    src,
+                    assembly::Operand::Reg(assembly::Reg::R10),
+                ));
+                assembly::Operand::Reg(assembly::Reg::R10)
+            } else {
+                src
+            };
+            if is_memory(&dst) {
+                instructions.push(assembly::Instruction::Mov(
+                    t,
+                    dst.clone(),
+                    assembly::Operand::Reg(assembly::Reg::R11),
+                ));
+                instructions.push(assembly::Instruction::Binary {
+                    op: assembly::BinaryOperator::Mult,
+                    t: t,
+                    src: src,
+                    dst: assembly::Operand::Reg(assembly::Reg::R11),
+                });
+                instructions.push(assembly::Instruction::Mov(
+                    t,
+                    assembly::Operand::Reg(assembly::Reg::R11),
+                    dst,
+                ));
+            } else {
+                instructions.push(assembly::Instruction::Binary {
+                    op: assembly::BinaryOperator::Mult,
+                    t: t,
+                    src: src,
+                    dst: dst,
+                });
+            }
+            instructions
+        }
+        // cmp的两个操作数不能都在内存里，第一个操作数不能超过32位，第二个操作数不能是常量
+        assembly::Instruction::Cmp(t, src, dst)
+            if is_large(&src) || (is_memory(&src) && is_memory(&dst)) || is_imm(&dst) =>
+        {
+            let mut instructions = vec![];
+            let src = if is_large(&src) || (is_memory(&src) && is_memory(&dst)) {
+                instructions.push(assembly::Instruction::Mov(
+                    t,
+                    src,
+                    assembly::Operand::Reg(assembly::Reg::R10),
+                ));
+                assembly::Operand::Reg(assembly::Reg::R10)
+            } else {
+                src
+            };
+            let dst = if is_imm(&dst) {
+                instructions.push(assembly::Instruction::Mov(
+                    t,
+                    dst,
+                    assembly::Operand::Reg(assembly::Reg::R11),
+                ));
+                assembly::Operand::Reg(assembly::Reg::R11)
+            } else {
+                dst
+            };
+            instructions.push(assembly::Instruction::Cmp(t, src, dst));
+            instructions
+        }
+        assembly::Instruction::Push(src) if is_large(&src) => vec![
             assembly::Instruction::Mov(
-                assembly::Operand::Imm(i),
-                assembly::Operand::Reg(assembly::Reg::R11),
+                assembly::AsmType::Quadword,
+                src,
+                assembly::Operand::Reg(assembly::Reg::R10),
             ),
-            assembly::Instruction::Cmp(src, assembly::Operand::Reg(assembly::Reg::R11)),
+            assembly::Instruction::Push(assembly::Operand::Reg(assembly::Reg::R10)),
         ],
+        // movsx和movzx的源操作数不能是常量，目的操作数必须是寄存器
+        assembly::Instruction::Movsx {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => {
+            let mut instructions = vec![];
+            let src = match src {
+                assembly::Operand::Imm(i) => {
+                    instructions.push(assembly::Instruction::Mov(
+                        src_t,
+                        assembly::Operand::Imm(i),
+                        assembly::Operand::Reg(assembly::Reg::R10),
+                    ));
+                    assembly::Operand::Reg(assembly::Reg::R10)
+                }
+                other => other,
+            };
+            match dst {
+                assembly::Operand::Reg(_) => instructions.push(assembly::Instruction::Movsx {
+                    src_t: src_t,
+                    dst_t: dst_t,
+                    src: src,
+                    dst: dst,
+                }),
+                _ => {
+                    instructions.push(assembly::Instruction::Movsx {
+                        src_t: src_t,
+                        dst_t: dst_t,
+                        src: src,
+                        dst: assembly::Operand::Reg(assembly::Reg::R11),
+                    });
+                    instructions.push(assembly::Instruction::Mov(
+                        dst_t,
+                        assembly::Operand::Reg(assembly::Reg::R11),
+                        dst,
+                    ));
+                }
+            }
+            instructions
+        }
+        assembly::Instruction::MovZeroExtend {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => {
+            let mut instructions = vec![];
+            let src = match src {
+                assembly::Operand::Imm(i) => {
+                    instructions.push(assembly::Instruction::Mov(
+                        src_t,
+                        assembly::Operand::Imm(i),
+                        assembly::Operand::Reg(assembly::Reg::R10),
+                    ));
+                    assembly::Operand::Reg(assembly::Reg::R10)
+                }
+                other => other,
+            };
+            match dst {
+                assembly::Operand::Reg(_) => {
+                    instructions.push(assembly::Instruction::MovZeroExtend {
+                        src_t: src_t,
+                        dst_t: dst_t,
+                        src: src,
+                        dst: dst,
+                    })
+                }
+                _ => {
+                    instructions.push(assembly::Instruction::MovZeroExtend {
+                        src_t: src_t,
+                        dst_t: dst_t,
+                        src: src,
+                        dst: assembly::Operand::Reg(assembly::Reg::R11),
+                    });
+                    instructions.push(assembly::Instruction::Mov(
+                        dst_t,
+                        assembly::Operand::Reg(assembly::Reg::R11),
+                        dst,
+                    ));
+                }
+            }
+            instructions
+        }
+        // 截断到更窄的类型时，立即数也要截断，否则汇编器会报警告
+        assembly::Instruction::Mov(assembly::AsmType::Word, assembly::Operand::Imm(i), dst) => {
+            vec![assembly::Instruction::Mov(
+                assembly::AsmType::Word,
+                assembly::Operand::Imm(i as i16 as i64),
+                dst,
+            )]
+        }
+        assembly::Instruction::Mov(assembly::AsmType::Byte, assembly::Operand::Imm(i), dst) => {
+            vec![assembly::Instruction::Mov(
+                assembly::AsmType::Byte,
+                assembly::Operand::Imm(i as i8 as i64),
+                dst,
+            )]
+        }
         other => vec![other],
     }
 }
@@ -69,11 +267,29 @@ fn fixup_tl(f: assembly::TopLevel) -> assembly::TopLevel {
             instructions,
         } => {
             let stack_bytes = -symbols::get_bytes_required(name.clone());
-            let mut _instructions = vec![assembly::Instruction::AllocateStack(
-                rounding::round_way_from_zero(16, stack_bytes),
-            )];
+            let callee_saved_regs = assembly_symbols::get_callee_saved_regs_used(name.clone());
+            // 压栈的被调用者保存寄存器也占用栈空间，两者加起来要是16的倍数，
+            // 这样每条call执行时栈都是16字节对齐的
+            let callee_saved_bytes = 8 * callee_saved_regs.len() as i64;
+            let stack_adjustment =
+                rounding::round_way_from_zero(16, stack_bytes + callee_saved_bytes)
+                    - callee_saved_bytes;
+            let mut _instructions = vec![assembly::Instruction::AllocateStack(stack_adjustment)];
+            for r in callee_saved_regs.iter() {
+                _instructions.push(assembly::Instruction::Push(assembly::Operand::Reg(
+                    r.clone(),
+                )));
+            }
             for i in instructions {
-                _instructions.append(&mut fixup_instruction(i));
+                match i {
+                    assembly::Instruction::Ret => {
+                        for r in callee_saved_regs.iter().rev() {
+                            _instructions.push(assembly::Instruction::Pop(r.clone()));
+                        }
+                        _instructions.push(assembly::Instruction::Ret);
+                    }
+                    other => _instructions.append(&mut fixup_instruction(other)),
+                }
             }
             assembly::TopLevel::Function {
                 name: name,
@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use crate::{constants, initializers, types};
+use crate::{constants, initializers, symbols, types};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum UnaryOperator {
@@ -12,9 +12,9 @@ pub enum UnaryOperator {
 impl Display for UnaryOperator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            UnaryOperator::Complement => write!(f, "~"),
-            UnaryOperator::Negate => write!(f, "-"),
-            UnaryOperator::Not => write!(f, "!"),
+            UnaryOperator::Complement => write!(f, "complement"),
+            UnaryOperator::Negate => write!(f, "negate"),
+            UnaryOperator::Not => write!(f, "not"),
         }
     }
 }
@@ -37,17 +37,17 @@ pub enum BinaryOperator {
 impl Display for BinaryOperator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            BinaryOperator::Add => write!(f, "+"),
-            BinaryOperator::Subtract => write!(f, "-"),
-            BinaryOperator::Multiply => write!(f, "*"),
-            BinaryOperator::Divide => write!(f, "/"),
-            BinaryOperator::Mod => write!(f, "%"),
-            BinaryOperator::Equal => write!(f, "=="),
-            BinaryOperator::NotEqual => write!(f, "!="),
-            BinaryOperator::LessThan => write!(f, "<"),
-            BinaryOperator::LessOrEqual => write!(f, "<="),
-            BinaryOperator::GreaterThan => write!(f, ">"),
-            BinaryOperator::GreaterOrEqual => write!(f, ">="),
+            BinaryOperator::Add => write!(f, "add"),
+            BinaryOperator::Subtract => write!(f, "sub"),
+            BinaryOperator::Multiply => write!(f, "mul"),
+            BinaryOperator::Divide => write!(f, "div"),
+            BinaryOperator::Mod => write!(f, "mod"),
+            BinaryOperator::Equal => write!(f, "eq"),
+            BinaryOperator::NotEqual => write!(f, "ne"),
+            BinaryOperator::LessThan => write!(f, "lt"),
+            BinaryOperator::LessOrEqual => write!(f, "le"),
+            BinaryOperator::GreaterThan => write!(f, "gt"),
+            BinaryOperator::GreaterOrEqual => write!(f, "ge"),
         }
     }
 }
@@ -60,9 +60,9 @@ pub enum IrValue {
 
 impl Display for IrValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
+        match self {
             IrValue::Constant(c) => write!(f, "{}", c),
-            IrValue::Var(ref v) => write!(f, "{}", v),
+            IrValue::Var(v) => write!(f, "{}", v),
         }
     }
 }
@@ -78,6 +78,10 @@ pub enum Instruction {
         src: IrValue,
         dst: IrValue,
     },
+    ZeroExtend {
+        src: IrValue,
+        dst: IrValue,
+    },
     Unary {
         op: UnaryOperator,
         src: IrValue,
@@ -107,42 +111,33 @@ pub enum Instruction {
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Instruction::Return(ref ir_value) => write!(f, "Return({})", ir_value),
-            Instruction::Unary {
-                ref op,
-                ref src,
-                ref dst,
-            } => write!(f, "{}={}{}", dst, op, src),
+            Instruction::Return(ir_value) => write!(f, "return {}", ir_value),
+            Instruction::SignExtend { src, dst } => write!(f, "{} = sign_extend {}", dst, src),
+            Instruction::Truncate { src, dst } => write!(f, "{} = truncate {}", dst, src),
+            Instruction::ZeroExtend { src, dst } => write!(f, "{} = zero_extend {}", dst, src),
+            Instruction::Unary { op, src, dst } => write!(f, "{} = {} {}", dst, op, src),
             Instruction::Binary {
-                ref op,
-                ref src1,
-                ref src2,
-                ref dst,
-            } => write!(f, "{}={}{}{}", dst, src1, op, src2),
-            Instruction::Copy { ref src, ref dst } => write!(f, "{}={}", dst, src),
-            Instruction::Jump(ref s) => write!(f, "Jump({})", s),
-            Instruction::JumpIfZero(ref cond, ref target) => {
-                write!(f, "JumpIfZero({}, {})", cond, target)
+                op,
+                src1,
+                src2,
+                dst,
+            } => write!(f, "{} = {} {}, {}", dst, op, src1, src2),
+            Instruction::Copy { src, dst } => write!(f, "{} = copy {}", dst, src),
+            Instruction::Jump(target) => write!(f, "jump {}", target),
+            Instruction::JumpIfZero(cond, target) => {
+                write!(f, "jump_if_zero {}, {}", cond, target)
             }
-            Instruction::JumpIfNotZero(ref cond, ref target) => {
-                write!(f, "JumpIfNotZero({}, {})", cond, target)
+            Instruction::JumpIfNotZero(cond, target) => {
+                write!(f, "jump_if_not_zero {}, {}", cond, target)
             }
-            Instruction::Label(ref label) => write!(f, "{}:", label),
+            Instruction::Label(label) => write!(f, "{}:", label),
             Instruction::FunCall {
                 f: fun_name,
                 args,
                 dst,
             } => {
-                let mut result = String::new();
-                result.push_str(format!("{} = {}(", dst, fun_name).as_str());
-                for (i, arg) in args.iter().enumerate() {
-                    if i < args.len() - 1 {
-                        result.push_str(format!("{}, ", arg).as_str());
-                    } else {
-                        result.push_str(format!("{})", arg).as_str());
-                    }
-                }
-                write!(f, "{}", result)
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{} = call {}({})", dst, fun_name, args.join(", "))
             }
         }
     }
@@ -164,41 +159,112 @@ pub enum TopLevel {
     },
 }
 
+fn vars_used(body: &Vec<Instruction>) -> Vec<String> {
+    let mut vars = vec![];
+    for instruction in body {
+        let values = match instruction {
+            Instruction::Return(v)
+            | Instruction::JumpIfZero(v, _)
+            | Instruction::JumpIfNotZero(v, _) => vec![v],
+            Instruction::SignExtend { src, dst }
+            | Instruction::Truncate { src, dst }
+            | Instruction::ZeroExtend { src, dst }
+            | Instruction::Unary { op: _, src, dst }
+            | Instruction::Copy { src, dst } => vec![src, dst],
+            Instruction::Binary {
+                op: _,
+                src1,
+                src2,
+                dst,
+            } => vec![src1, src2, dst],
+            Instruction::FunCall { f: _, args, dst } => {
+                let mut values: Vec<&IrValue> = args.iter().collect();
+                values.push(dst);
+                values
+            }
+            Instruction::Jump(_) | Instruction::Label(_) => vec![],
+        };
+        for v in values {
+            if let IrValue::Var(name) = v {
+                if !vars.contains(name) {
+                    vars.push(name.clone());
+                }
+            }
+        }
+    }
+    vars
+}
+
+/// 类型来自符号表，符号表里没有的变量和函数就不写类型。
 impl Display for TopLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             TopLevel::Function {
-                ref name,
+                name,
                 global,
                 params,
-                ref body,
+                body,
             } => {
                 let mut result = String::new();
+                result.push_str("function ");
                 if *global {
                     result.push_str("global ");
                 }
-                result.push_str(format!("{}(", name).as_str());
-                for (i, param) in params.iter().enumerate() {
-                    if i < params.len() - 1 {
-                        result.push_str(format!("{}, ", param).as_str());
-                    } else {
-                        result.push_str(format!("{}):\n", param).as_str());
-                    }
+                let typed_params: Vec<String> = params
+                    .iter()
+                    .map(|param| match symbols::get_opt(param.clone()) {
+                        Some(entry) => format!("{}: {}", param, entry.t),
+                        None => param.clone(),
+                    })
+                    .collect();
+                result.push_str(format!("{}({})", name, typed_params.join(", ")).as_str());
+                if let Some(symbols::Entry {
+                    t:
+                        types::Type::FunType {
+                            param_types: _,
+                            ret_type,
+                            has_prototype: _,
+                        },
+                    attrs: _,
+                }) = symbols::get_opt(name.clone())
+                {
+                    result.push_str(format!(" -> {}", ret_type).as_str());
                 }
-                if params.len() == 0 {
-                    result.push_str(format!("):\n").as_str());
+                result.push_str(" {\n");
+                // 局部变量和临时变量在函数开头声明类型
+                for var in vars_used(body) {
+                    if params.contains(&var) {
+                        continue;
+                    }
+                    if let Some(symbols::Entry {
+                        t,
+                        attrs: symbols::IdentifierAttrs::LocalAttr,
+                    }) = symbols::get_opt(var.clone())
+                    {
+                        result.push_str(format!("    var {}: {}\n", var, t).as_str());
+                    }
                 }
                 for i in body {
-                    result.push_str(format!("{}\n", i).as_str());
+                    match i {
+                        Instruction::Label(_) => result.push_str(format!("{}\n", i).as_str()),
+                        _ => result.push_str(format!("    {}\n", i).as_str()),
+                    }
                 }
+                result.push('}');
                 write!(f, "{}", result)
             }
-            TopLevel::StaticVariable { name, global, init } => {
+            TopLevel::StaticVariable {
+                name,
+                t,
+                global,
+                init,
+            } => {
                 let mut result = String::new();
+                result.push_str("static ");
                 if *global {
                     result.push_str("global ");
                 }
-                result.push_str(format!("{} = {}", name, init).as_str());
+                result.push_str(format!("{}: {} = {}", name, t, init).as_str());
                 write!(f, "{}", result)
             }
         }
@@ -210,11 +276,81 @@ pub enum T {
     Program(Vec<TopLevel>),
 }
 
+/// 程序里用到、却没有在本文件定义的静态变量和函数，先用`extern`和`declare`声明类型。
 impl Display for T {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             T::Program(top_levels) => {
+                let mut defined = vec![];
+                for top_level in top_levels {
+                    match top_level {
+                        TopLevel::Function {
+                            name,
+                            global: _,
+                            params: _,
+                            body: _,
+                        }
+                        | TopLevel::StaticVariable {
+                            name,
+                            t: _,
+                            global: _,
+                            init: _,
+                        } => defined.push(name.clone()),
+                    }
+                }
+                let mut declarations = vec![];
+                for top_level in top_levels {
+                    if let TopLevel::Function {
+                        name: _,
+                        global: _,
+                        params: _,
+                        body,
+                    } = top_level
+                    {
+                        let mut names = vars_used(body);
+                        for i in body {
+                            if let Instruction::FunCall {
+                                f: fun_name,
+                                args: _,
+                                dst: _,
+                            } = i
+                            {
+                                names.push(fun_name.clone());
+                            }
+                        }
+                        for name in names {
+                            if defined.contains(&name) {
+                                continue;
+                            }
+                            let declaration = match symbols::get_opt(name.clone()) {
+                                Some(symbols::Entry {
+                                    t,
+                                    attrs:
+                                        symbols::IdentifierAttrs::StaticAttr { init: _, global: _ },
+                                }) => format!("extern {}: {}", name, t),
+                                Some(symbols::Entry {
+                                    t,
+                                    attrs:
+                                        symbols::IdentifierAttrs::FunAttr {
+                                            defined: _,
+                                            global: _,
+                                            inline: _,
+                                            inline_definition: _,
+                                            stack_frame_size: _,
+                                        },
+                                }) => format!("declare {}{}", name, t),
+                                _ => continue,
+                            };
+                            if !declarations.contains(&declaration) {
+                                declarations.push(declaration);
+                            }
+                        }
+                    }
+                }
                 let mut result = String::new();
+                for declaration in declarations {
+                    result.push_str(format!("{}\n", declaration).as_str());
+                }
                 for top_level in top_levels {
                     result.push_str(format!("{}\n", top_level).as_str());
                 }
@@ -1,5 +1,5 @@
 use crate::{
-    ast,
+    ast, const_convert, constants, initializers,
     ir::{self, IrValue},
     symbols, type_utils, types, unique_ids,
 };
@@ -14,7 +14,7 @@ fn continue_label(id: String) -> String {
 
 fn create_tmp(t: types::Type) -> String {
     let name = unique_ids::make_temporary();
-    symbols::add_automatic_var(name, t);
+    symbols::add_automatic_var(name.clone(), t);
     name
 }
 
@@ -44,17 +44,17 @@ fn convert_binop(op: ast::BinaryOperator) -> ir::BinaryOperator {
 }
 
 fn emit_ir_for_exp(exp: ast::TypedExp) -> (Vec<ir::Instruction>, ir::IrValue) {
-    match exp.e {
-        ast::Exp::Constant(c) => (vec![], ir::IrValue::Constant(c)),
-        ast::Exp::Var(v) => (vec![], ir::IrValue::Var(v)),
-        ast::Exp::Cast { target_type, e } => emit_cast_expression(target_type, e),
-        ast::Exp::Unary(op, inner) => emit_unary_expression(exp.t, op, inner),
-        ast::Exp::Binary(ast::BinaryOperator::And, e1, e2) => emit_and_expression(e1, e2),
-        ast::Exp::Binary(ast::BinaryOperator::Or, e1, e2) => emit_or_expression(e1, e2),
-        ast::Exp::Binary(op, e1, e2) => emit_binary_expression(exp.t, op, e1, e2),
-        ast::Exp::Assignment(lhs, rhs) => match *lhs {
-            ast::Exp::Var(v) => {
-                let (mut rhs_instructions, rhs_result) = emit_ir_for_exp(*rhs);
+    match *exp.e {
+        ast::TypedInnerExp::Constant(c) => (vec![], ir::IrValue::Constant(c)),
+        ast::TypedInnerExp::Var(v) => (vec![], ir::IrValue::Var(v)),
+        ast::TypedInnerExp::Cast { target_type, e } => emit_cast_expression(target_type, e),
+        ast::TypedInnerExp::Unary(op, inner) => emit_unary_expression(exp.t, op, inner),
+        ast::TypedInnerExp::Binary(ast::BinaryOperator::And, e1, e2) => emit_and_expression(e1, e2),
+        ast::TypedInnerExp::Binary(ast::BinaryOperator::Or, e1, e2) => emit_or_expression(e1, e2),
+        ast::TypedInnerExp::Binary(op, e1, e2) => emit_binary_expression(exp.t, op, e1, e2),
+        ast::TypedInnerExp::Assignment(lhs, rhs) => match *lhs.e {
+            ast::TypedInnerExp::Var(v) => {
+                let (mut rhs_instructions, rhs_result) = emit_ir_for_exp(rhs);
                 rhs_instructions.push(ir::Instruction::Copy {
                     src: rhs_result,
                     dst: ir::IrValue::Var(v.clone()),
@@ -63,21 +63,21 @@ fn emit_ir_for_exp(exp: ast::TypedExp) -> (Vec<ir::Instruction>, ir::IrValue) {
             }
             _ => panic!("错误的左值。"),
         },
-        ast::Exp::Conditional {
+        ast::TypedInnerExp::Conditional {
             condition,
             then_result,
             else_result,
         } => emit_conditional_expression(exp.t, condition, then_result, else_result),
-        ast::Exp::FunCall { f, args } => emit_fun_call(f, args),
+        ast::TypedInnerExp::Funcall { f, args } => emit_fun_call(f, args),
     }
 }
 
 fn emit_unary_expression(
     t: types::Type,
     op: ast::UnaryOperator,
-    inner: Box<ast::Exp>,
+    inner: ast::TypedExp,
 ) -> (Vec<ir::Instruction>, ir::IrValue) {
-    let (mut eval_inner, v) = emit_ir_for_exp(*inner);
+    let (mut eval_inner, v) = emit_ir_for_exp(inner);
     let dst_name = create_tmp(t);
     let dst = ir::IrValue::Var(dst_name);
     let ir_op = convert_op(op);
@@ -91,24 +91,46 @@ fn emit_unary_expression(
 
 fn emit_cast_expression(
     target_type: types::Type,
-    inner: ast::Exp,
+    inner: ast::TypedExp,
 ) -> (Vec<ir::Instruction>, ir::IrValue) {
+    let inner_type = type_utils::get_type(inner.clone());
     let (mut eval_inner, result) = emit_ir_for_exp(inner);
-    if type_utils::get_type(inner) == target_type {
+    if inner_type == target_type {
         (eval_inner, result)
     } else {
-        let dst_name = create_tmp(target_type);
+        let dst_name = create_tmp(target_type.clone());
         let dst = ir::IrValue::Var(dst_name);
-        let cast_instruction = match target_type {
-            types::Type::Long => ir::Instruction::SignExtend {
+        let cast_instruction = if target_type == types::Type::Bool {
+            // 转换成_Bool时，任何非零值都变成1
+            ir::Instruction::Binary {
+                op: ir::BinaryOperator::NotEqual,
+                src1: result,
+                src2: ir::IrValue::Constant(const_convert::const_convert(
+                    inner_type,
+                    constants::INT_ZERO,
+                )),
+                dst: dst.clone(),
+            }
+        } else if type_utils::get_size(target_type.clone()) == type_utils::get_size(inner_type.clone()) {
+            ir::Instruction::Copy {
                 src: result,
-                dst: dst,
-            },
-            types::Type::Int => ir::Instruction::Truncate {
+                dst: dst.clone(),
+            }
+        } else if type_utils::get_size(target_type.clone()) < type_utils::get_size(inner_type.clone()) {
+            ir::Instruction::Truncate {
                 src: result,
-                dst: dst,
-            },
-            _ => panic!("内部错误：cast to function type."),
+                dst: dst.clone(),
+            }
+        } else if type_utils::is_signed(inner_type) {
+            ir::Instruction::SignExtend {
+                src: result,
+                dst: dst.clone(),
+            }
+        } else {
+            ir::Instruction::ZeroExtend {
+                src: result,
+                dst: dst.clone(),
+            }
         };
         let mut instructions = vec![];
         instructions.append(&mut eval_inner);
@@ -120,11 +142,11 @@ fn emit_cast_expression(
 fn emit_binary_expression(
     t: types::Type,
     op: ast::BinaryOperator,
-    e1: Box<ast::Exp>,
-    e2: Box<ast::Exp>,
+    e1: ast::TypedExp,
+    e2: ast::TypedExp,
 ) -> (Vec<ir::Instruction>, ir::IrValue) {
-    let (mut eval_v1, v1) = emit_ir_for_exp(*e1);
-    let (mut eval_v2, v2) = emit_ir_for_exp(*e2);
+    let (mut eval_v1, v1) = emit_ir_for_exp(e1);
+    let (mut eval_v2, v2) = emit_ir_for_exp(e2);
     let dst_name = create_tmp(t);
     let dst = ir::IrValue::Var(dst_name);
     let ir_op = convert_binop(op);
@@ -141,14 +163,14 @@ fn emit_binary_expression(
 }
 
 fn emit_and_expression(
-    e1: Box<ast::Exp>,
-    e2: Box<ast::Exp>,
+    e1: ast::TypedExp,
+    e2: ast::TypedExp,
 ) -> (Vec<ir::Instruction>, ir::IrValue) {
-    let (mut eval_v1, v1) = emit_ir_for_exp(*e1);
-    let (mut eval_v2, v2) = emit_ir_for_exp(*e2);
+    let (mut eval_v1, v1) = emit_ir_for_exp(e1);
+    let (mut eval_v2, v2) = emit_ir_for_exp(e2);
     let false_label = unique_ids::make_label("and_false".to_string());
     let end_label = unique_ids::make_label("and_end".to_string());
-    let dst_name = unique_ids::make_temporary();
+    let dst_name = create_tmp(types::Type::Int);
     let dst = ir::IrValue::Var(dst_name);
     let mut instructions = vec![];
     instructions.append(&mut eval_v1);
@@ -156,25 +178,25 @@ fn emit_and_expression(
     instructions.append(&mut eval_v2);
     instructions.push(ir::Instruction::JumpIfZero(v2, false_label.clone()));
     instructions.push(ir::Instruction::Copy {
-        src: ir::IrValue::Constant(1),
+        src: ir::IrValue::Constant(constants::INT_ONE),
         dst: dst.clone(),
     });
     instructions.push(ir::Instruction::Jump(end_label.clone()));
     instructions.push(ir::Instruction::Label(false_label));
     instructions.push(ir::Instruction::Copy {
-        src: ir::IrValue::Constant(0),
+        src: ir::IrValue::Constant(constants::INT_ZERO),
         dst: dst.clone(),
     });
     instructions.push(ir::Instruction::Label(end_label));
     (instructions, dst)
 }
 
-fn emit_or_expression(e1: Box<ast::Exp>, e2: Box<ast::Exp>) -> (Vec<ir::Instruction>, ir::IrValue) {
-    let (mut eval_v1, v1) = emit_ir_for_exp(*e1);
-    let (mut eval_v2, v2) = emit_ir_for_exp(*e2);
+fn emit_or_expression(e1: ast::TypedExp, e2: ast::TypedExp) -> (Vec<ir::Instruction>, ir::IrValue) {
+    let (mut eval_v1, v1) = emit_ir_for_exp(e1);
+    let (mut eval_v2, v2) = emit_ir_for_exp(e2);
     let true_label = unique_ids::make_label("or_true".to_string());
     let end_label = unique_ids::make_label("or_end".to_string());
-    let dst_name = unique_ids::make_temporary();
+    let dst_name = create_tmp(types::Type::Int);
     let dst = ir::IrValue::Var(dst_name);
     let mut instructions = vec![];
     instructions.append(&mut eval_v1);
@@ -182,13 +204,13 @@ fn emit_or_expression(e1: Box<ast::Exp>, e2: Box<ast::Exp>) -> (Vec<ir::Instruct
     instructions.append(&mut eval_v2);
     instructions.push(ir::Instruction::JumpIfNotZero(v2, true_label.clone()));
     instructions.push(ir::Instruction::Copy {
-        src: ir::IrValue::Constant(0),
+        src: ir::IrValue::Constant(constants::INT_ZERO),
         dst: dst.clone(),
     });
     instructions.push(ir::Instruction::Jump(end_label.clone()));
     instructions.push(ir::Instruction::Label(true_label));
     instructions.push(ir::Instruction::Copy {
-        src: ir::IrValue::Constant(1),
+        src: ir::IrValue::Constant(constants::INT_ONE),
         dst: dst.clone(),
     });
     instructions.push(ir::Instruction::Label(end_label));
@@ -197,16 +219,16 @@ fn emit_or_expression(e1: Box<ast::Exp>, e2: Box<ast::Exp>) -> (Vec<ir::Instruct
 
 fn emit_conditional_expression(
     t: types::Type,
-    condition: Box<ast::Exp>,
-    then_result: Box<ast::Exp>,
-    else_result: Box<ast::Exp>,
+    condition: ast::TypedExp,
+    then_result: ast::TypedExp,
+    else_result: ast::TypedExp,
 ) -> (Vec<ir::Instruction>, ir::IrValue) {
-    let (mut eval_cond, c) = emit_ir_for_exp(*condition);
-    let (mut eval_v1, v1) = emit_ir_for_exp(*then_result);
-    let (mut eval_v2, v2) = emit_ir_for_exp(*else_result);
+    let (mut eval_cond, c) = emit_ir_for_exp(condition);
+    let (mut eval_v1, v1) = emit_ir_for_exp(then_result);
+    let (mut eval_v2, v2) = emit_ir_for_exp(else_result);
     let else_label = unique_ids::make_label("conditional_else".to_string());
     let end_label = unique_ids::make_label("conditional_end".to_string());
-    let dst_name = unique_ids::make_temporary();
+    let dst_name = create_tmp(t);
     let dst = ir::IrValue::Var(dst_name);
     let mut instructions = vec![];
     instructions.append(&mut eval_cond);
@@ -283,35 +305,36 @@ fn emit_ir_for_block_item(declaration: ast::BlockItem<ast::TypedExp>) -> Vec<ir:
 fn emit_local_declaration(d: ast::Declaration<ast::TypedExp>) -> Vec<ir::Instruction> {
     match d {
         ast::Declaration::VarDecl(vd) => emit_var_declaration(vd),
-        ast::Declaration::FunDecl(_) => vec![],
+        ast::Declaration::FunDecl(_) | ast::Declaration::StaticAssert(_) => vec![],
     }
 }
 
 fn emit_var_declaration(vd: ast::VariableDeclaration<ast::TypedExp>) -> Vec<ir::Instruction> {
     match vd {
+        // 静态变量的初始值已经记在符号表里，不能每次执行到声明时再赋值一遍
         ast::VariableDeclaration {
             name,
             var_type: _,
             init: Some(e),
-            storage_class: _,
+            storage_class: None,
         } => {
-            let (eval_assignment, _) = emit_ir_for_exp(ast::Exp::Assignment(
-                Box::new(ast::Exp::Var(name)),
-                Box::new(e),
-            ));
+            let t = e.t.clone();
+            let lhs = type_utils::set_type(ast::TypedInnerExp::Var(name), t.clone());
+            let assignment = type_utils::set_type(ast::TypedInnerExp::Assignment(lhs, e), t);
+            let (eval_assignment, _) = emit_ir_for_exp(assignment);
             eval_assignment
         }
         ast::VariableDeclaration {
             name: _,
             var_type: _,
-            init: None,
+            init: _,
             storage_class: _,
         } => vec![],
     }
 }
 
 fn emit_ir_for_if_statement(
-    condition: ast::Exp,
+    condition: ast::TypedExp,
     then_clause: Box<ast::Statement<ast::TypedExp>>,
     else_clause: Option<Box<ast::Statement<ast::TypedExp>>>,
 ) -> Vec<ir::Instruction> {
@@ -345,7 +368,7 @@ fn emit_ir_for_if_statement(
 
 fn emit_ir_for_do_loop(
     body: Box<ast::Statement<ast::TypedExp>>,
-    condition: ast::Exp,
+    condition: ast::TypedExp,
     id: String,
 ) -> Vec<ir::Instruction> {
     let start_label = unique_ids::make_label("do_loop_start".to_string());
@@ -363,7 +386,7 @@ fn emit_ir_for_do_loop(
 }
 
 fn emit_ir_for_while_loop(
-    condition: ast::Exp,
+    condition: ast::TypedExp,
     body: Box<ast::Statement<ast::TypedExp>>,
     id: String,
 ) -> Vec<ir::Instruction> {
@@ -382,8 +405,8 @@ fn emit_ir_for_while_loop(
 
 fn emit_ir_for_for_loop(
     init: ast::ForInit<ast::TypedExp>,
-    condition: Option<ast::Exp>,
-    post: Option<ast::Exp>,
+    condition: Option<ast::TypedExp>,
+    post: Option<ast::TypedExp>,
     body: Box<ast::Statement<ast::TypedExp>>,
     id: String,
 ) -> Vec<ir::Instruction> {
@@ -425,8 +448,16 @@ fn emit_ir_for_for_loop(
     for_init_instructions
 }
 
-fn emit_fun_call(f: String, args: Vec<ast::Exp>) -> (Vec<ir::Instruction>, IrValue) {
-    let dst_name = unique_ids::make_temporary();
+fn emit_fun_call(f: String, args: Vec<ast::TypedExp>) -> (Vec<ir::Instruction>, IrValue) {
+    let ret_type = match symbols::get(f.clone()).t {
+        types::Type::FunType {
+            param_types: _,
+            ret_type,
+            has_prototype: _,
+        } => *ret_type,
+        _ => panic!("内部错误：{}不是函数。", f),
+    };
+    let dst_name = create_tmp(ret_type);
     let dst = ir::IrValue::Var(dst_name);
     let mut arg_instructions = vec![];
     let mut arg_vals = vec![];
@@ -451,13 +482,14 @@ fn emit_fun_declaration(fd: ast::Declaration<ast::TypedExp>) -> Option<ir::TopLe
             params,
             body: Some(ast::Block::Block(block_items)),
             storage_class: _,
+            inline: _,
         }) => {
             let global = symbols::is_global(name.clone());
             let mut body_instructions = vec![];
             for i in block_items {
                 body_instructions.append(&mut emit_ir_for_block_item(i));
             }
-            let extra_return = ir::Instruction::Return(ir::IrValue::Constant(0));
+            let extra_return = ir::Instruction::Return(ir::IrValue::Constant(constants::INT_ZERO));
             body_instructions.push(extra_return);
             Some(ir::TopLevel::Function {
                 name: name,
@@ -483,9 +515,9 @@ fn convert_symbols_to_ir(all_symbols: Vec<(String, symbols::Entry)>) -> Vec<ir::
                 }),
                 symbols::InitialValue::Tentative => Some(ir::TopLevel::StaticVariable {
                     name: name,
-                    t: entry.t,
+                    t: entry.t.clone(),
                     global: global,
-                    init: 0,
+                    init: initializers::zero(entry.t),
                 }),
                 symbols::InitialValue::NoInitializer => None,
             },
@@ -498,6 +530,53 @@ fn convert_symbols_to_ir(all_symbols: Vec<(String, symbols::Entry)>) -> Vec<ir::
     arr
 }
 
+fn called_functions(body: &Vec<ir::Instruction>) -> Vec<String> {
+    let mut result = vec![];
+    for instruction in body {
+        if let ir::Instruction::FunCall { f, args: _, dst: _ } = instruction {
+            result.push(f.clone());
+        }
+    }
+    result
+}
+
+/// 内联定义不生成外部定义，static inline函数只有在被（间接）引用时才输出。
+fn remove_unemitted_functions(fn_defs: Vec<ir::TopLevel>) -> Vec<ir::TopLevel> {
+    let mut referenced: Vec<String> = vec![];
+    let mut worklist = vec![];
+    for fn_def in fn_defs.iter() {
+        if let ir::TopLevel::Function { name, .. } = fn_def {
+            if !symbols::is_static_inline(name.clone()) && !symbols::is_inline_definition(name.clone()) {
+                worklist.push(name.clone());
+            }
+        }
+    }
+    while let Some(name) = worklist.pop() {
+        if referenced.contains(&name) {
+            continue;
+        }
+        referenced.push(name.clone());
+        for fn_def in fn_defs.iter() {
+            if let ir::TopLevel::Function { name: fn_name, body, .. } = fn_def {
+                if *fn_name == name {
+                    for callee in called_functions(body) {
+                        if symbols::is_static_inline(callee.clone()) {
+                            worklist.push(callee);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    fn_defs
+        .into_iter()
+        .filter(|fn_def| match fn_def {
+            ir::TopLevel::Function { name, .. } => referenced.contains(name),
+            _ => true,
+        })
+        .collect()
+}
+
 pub fn gen(program: ast::TypedProgType) -> ir::T {
     match program {
         ast::TypedProgType::Program(decls) => {
@@ -507,6 +586,7 @@ pub fn gen(program: ast::TypedProgType) -> ir::T {
                     ir_fn_defs.push(fn_def_ir);
                 }
             }
+            let mut ir_fn_defs = remove_unemitted_functions(ir_fn_defs);
             let mut ir_var_defs = convert_symbols_to_ir(symbols::bindings());
             let mut result = vec![];
             result.append(&mut ir_fn_defs);
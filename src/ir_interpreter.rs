@@ -0,0 +1,538 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{const_convert, constants, initializers, ir, symbols, types};
+
+// 递归太深时报错，而不是让解释器自己的栈溢出
+const MAX_CALL_DEPTH: usize = 2000;
+
+// `run`最多执行的指令条数，正常的测试程序远远用不完
+pub const DEFAULT_FUEL: u64 = 100_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub function: String,
+    pub instruction: Option<Box<ir::Instruction>>,
+    pub message: String,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.instruction {
+            Some(i) => write!(f, "函数{}中的指令{}：{}", self.function, i, self.message),
+            None => write!(f, "函数{}：{}", self.function, self.message),
+        }
+    }
+}
+
+/// 程序正常结束（从入口函数返回或者调用了`exit`）时的退出码和写到标准输出的内容。
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub exit_code: i32,
+    pub output: Vec<u8>,
+}
+
+enum Flow {
+    Return(constants::T),
+    Exit(i32),
+}
+
+struct Function {
+    params: Vec<String>,
+    body: Vec<ir::Instruction>,
+    labels: HashMap<String, usize>,
+}
+
+pub struct Interpreter {
+    functions: HashMap<String, Function>,
+    statics: HashMap<String, constants::T>,
+    output: Vec<u8>,
+    depth: usize,
+    // 剩余可以执行的指令条数，用来发现死循环
+    fuel: Option<u64>,
+}
+
+fn is_zero(c: &constants::T) -> bool {
+    const_convert::const_to_i64(c.clone()) == 0
+}
+
+fn bool_to_int(b: bool) -> constants::T {
+    if b {
+        constants::INT_ONE
+    } else {
+        constants::INT_ZERO
+    }
+}
+
+fn is_signed(t: &types::Type) -> bool {
+    match t {
+        types::Type::Short | types::Type::Int | types::Type::Long => true,
+        _ => false,
+    }
+}
+
+/// 先在64位上计算，再截断回操作数的类型，这样32位的运算也会按补码回绕。
+fn binary(
+    op: &ir::BinaryOperator,
+    c1: constants::T,
+    c2: constants::T,
+) -> Result<constants::T, String> {
+    let t = constants::type_of_const(&c1);
+    let v1 = const_convert::const_to_i64(c1);
+    let v2 = const_convert::const_to_i64(c2);
+    let wrap = |v: i64| const_convert::const_convert(t.clone(), constants::T::ConstLong(v));
+    match op {
+        ir::BinaryOperator::Add => Ok(wrap(v1.wrapping_add(v2))),
+        ir::BinaryOperator::Subtract => Ok(wrap(v1.wrapping_sub(v2))),
+        ir::BinaryOperator::Multiply => Ok(wrap(v1.wrapping_mul(v2))),
+        ir::BinaryOperator::Divide | ir::BinaryOperator::Mod => {
+            if v2 == 0 {
+                return Err("除以零".to_string());
+            }
+            // 结果在操作数类型里放不下，例如INT_MIN / -1
+            let overflow = match t {
+                types::Type::Int => v1 == i32::MIN as i64 && v2 == -1,
+                types::Type::Long => v1 == i64::MIN && v2 == -1,
+                _ => false,
+            };
+            if overflow && is_signed(&t) {
+                return Err("有符号除法溢出".to_string());
+            }
+            match op {
+                ir::BinaryOperator::Divide => Ok(wrap(v1 / v2)),
+                _ => Ok(wrap(v1 % v2)),
+            }
+        }
+        ir::BinaryOperator::Equal => Ok(bool_to_int(v1 == v2)),
+        ir::BinaryOperator::NotEqual => Ok(bool_to_int(v1 != v2)),
+        ir::BinaryOperator::LessThan => Ok(bool_to_int(v1 < v2)),
+        ir::BinaryOperator::LessOrEqual => Ok(bool_to_int(v1 <= v2)),
+        ir::BinaryOperator::GreaterThan => Ok(bool_to_int(v1 > v2)),
+        ir::BinaryOperator::GreaterOrEqual => Ok(bool_to_int(v1 >= v2)),
+    }
+}
+
+fn unary(op: &ir::UnaryOperator, c: constants::T) -> constants::T {
+    let t = constants::type_of_const(&c);
+    let v = const_convert::const_to_i64(c);
+    match op {
+        ir::UnaryOperator::Complement => {
+            const_convert::const_convert(t, constants::T::ConstLong(!v))
+        }
+        ir::UnaryOperator::Negate => {
+            const_convert::const_convert(t, constants::T::ConstLong(v.wrapping_neg()))
+        }
+        ir::UnaryOperator::Not => bool_to_int(v == 0),
+    }
+}
+
+/// 目的操作数的类型来自符号表；符号表里没有的变量保持源操作数的类型。
+fn convert_to_dst(dst: &ir::IrValue, c: constants::T) -> constants::T {
+    match dst {
+        ir::IrValue::Var(name) => match symbols::get_opt(name.clone()) {
+            Some(entry) => const_convert::const_convert(entry.t, c),
+            None => c,
+        },
+        ir::IrValue::Constant(_) => panic!("内部错误：常量不能作为目的操作数。"),
+    }
+}
+
+impl Interpreter {
+    pub fn new(program: &ir::T) -> Self {
+        let mut functions = HashMap::new();
+        let mut statics = HashMap::new();
+        match program {
+            ir::T::Program(top_levels) => {
+                for top_level in top_levels {
+                    match top_level {
+                        ir::TopLevel::Function {
+                            name,
+                            global: _,
+                            params,
+                            body,
+                        } => {
+                            let mut labels = HashMap::new();
+                            for (i, instruction) in body.iter().enumerate() {
+                                if let ir::Instruction::Label(l) = instruction {
+                                    labels.insert(l.clone(), i);
+                                }
+                            }
+                            functions.insert(
+                                name.clone(),
+                                Function {
+                                    params: params.clone(),
+                                    body: body.clone(),
+                                    labels: labels,
+                                },
+                            );
+                        }
+                        ir::TopLevel::StaticVariable {
+                            name,
+                            t,
+                            global: _,
+                            init,
+                        } => {
+                            let value = const_convert::const_convert(
+                                t.clone(),
+                                constants::T::ConstLong(init_to_i64(init)),
+                            );
+                            statics.insert(name.clone(), value);
+                        }
+                    }
+                }
+            }
+        }
+        Interpreter {
+            functions: functions,
+            statics: statics,
+            output: vec![],
+            depth: 0,
+            fuel: None,
+        }
+    }
+
+    /// 最多执行`steps`条指令，超过时报错。
+    pub fn set_fuel(&mut self, steps: u64) {
+        self.fuel = Some(steps);
+    }
+
+    /// 内置的外部函数。以后有了指针再加上malloc。
+    fn call_builtin(&mut self, name: &str, args: &Vec<constants::T>) -> Option<Flow> {
+        match (name, args.as_slice()) {
+            ("putchar", [c]) => {
+                self.output
+                    .push(const_convert::const_to_i64(c.clone()) as u8);
+                Some(Flow::Return(c.clone()))
+            }
+            ("exit", [code]) => Some(Flow::Exit(const_convert::const_to_i64(code.clone()) as i32)),
+            _ => None,
+        }
+    }
+
+    fn call(&mut self, name: &String, args: Vec<constants::T>) -> Result<Flow, Error> {
+        let error = |message: String| Error {
+            function: name.clone(),
+            instruction: None,
+            message: message,
+        };
+        if !self.functions.contains_key(name) {
+            return match self.call_builtin(name, &args) {
+                Some(flow) => Ok(flow),
+                None => Err(error("函数没有定义，也不是内置函数".to_string())),
+            };
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(error("调用层数太深".to_string()));
+        }
+        let function = &self.functions[name];
+        if function.params.len() != args.len() {
+            return Err(error(format!(
+                "需要{}个参数，实际传了{}个",
+                function.params.len(),
+                args.len()
+            )));
+        }
+        let mut frame = HashMap::new();
+        for (param, arg) in function.params.iter().zip(args) {
+            frame.insert(param.clone(), arg);
+        }
+        self.depth += 1;
+        let result = self.run_body(name, &mut frame);
+        self.depth -= 1;
+        result
+    }
+
+    fn read(
+        &self,
+        frame: &HashMap<String, constants::T>,
+        v: &ir::IrValue,
+    ) -> Result<constants::T, String> {
+        match v {
+            ir::IrValue::Constant(c) => Ok(c.clone()),
+            ir::IrValue::Var(name) => match frame.get(name).or(self.statics.get(name)) {
+                Some(c) => Ok(c.clone()),
+                None => Err(format!("读取未初始化的变量{}", name)),
+            },
+        }
+    }
+
+    fn write(
+        &mut self,
+        frame: &mut HashMap<String, constants::T>,
+        dst: &ir::IrValue,
+        c: constants::T,
+    ) {
+        let c = convert_to_dst(dst, c);
+        match dst {
+            ir::IrValue::Var(name) => {
+                if self.statics.contains_key(name) || symbols::is_static(name.clone()) {
+                    self.statics.insert(name.clone(), c);
+                } else {
+                    frame.insert(name.clone(), c);
+                }
+            }
+            ir::IrValue::Constant(_) => panic!("内部错误：常量不能作为目的操作数。"),
+        }
+    }
+
+    fn label_position(&self, function: &String, label: &String) -> Result<usize, String> {
+        match self.functions[function].labels.get(label) {
+            Some(pc) => Ok(*pc),
+            None => Err(format!("跳转到不存在的标签{}", label)),
+        }
+    }
+
+    fn run_body(
+        &mut self,
+        name: &String,
+        frame: &mut HashMap<String, constants::T>,
+    ) -> Result<Flow, Error> {
+        let mut pc = 0;
+        loop {
+            let instruction = match self.functions[name].body.get(pc) {
+                Some(i) => i.clone(),
+                None => {
+                    return Err(Error {
+                        function: name.clone(),
+                        instruction: None,
+                        message: "没有执行return就到了函数末尾".to_string(),
+                    })
+                }
+            };
+            let error = |message: String| Error {
+                function: name.clone(),
+                instruction: Some(Box::new(instruction.clone())),
+                message: message,
+            };
+            if let Some(fuel) = self.fuel {
+                if fuel == 0 {
+                    return Err(error("执行的指令太多，可能是死循环".to_string()));
+                }
+                self.fuel = Some(fuel - 1);
+            }
+            pc += 1;
+            match &instruction {
+                ir::Instruction::Return(v) => {
+                    return Ok(Flow::Return(self.read(frame, v).map_err(error)?))
+                }
+                ir::Instruction::SignExtend { src, dst }
+                | ir::Instruction::ZeroExtend { src, dst }
+                | ir::Instruction::Truncate { src, dst }
+                | ir::Instruction::Copy { src, dst } => {
+                    let c = self.read(frame, src).map_err(error)?;
+                    self.write(frame, dst, c);
+                }
+                ir::Instruction::Unary { op, src, dst } => {
+                    let c = self.read(frame, src).map_err(error)?;
+                    self.write(frame, dst, unary(op, c));
+                }
+                ir::Instruction::Binary {
+                    op,
+                    src1,
+                    src2,
+                    dst,
+                } => {
+                    let c1 = self.read(frame, src1).map_err(error)?;
+                    let c2 = self.read(frame, src2).map_err(error)?;
+                    let result = binary(op, c1, c2).map_err(error)?;
+                    self.write(frame, dst, result);
+                }
+                ir::Instruction::Jump(target) => {
+                    pc = self.label_position(name, target).map_err(error)?
+                }
+                ir::Instruction::JumpIfZero(v, target) => {
+                    if is_zero(&self.read(frame, v).map_err(error)?) {
+                        pc = self.label_position(name, target).map_err(error)?;
+                    }
+                }
+                ir::Instruction::JumpIfNotZero(v, target) => {
+                    if !is_zero(&self.read(frame, v).map_err(error)?) {
+                        pc = self.label_position(name, target).map_err(error)?;
+                    }
+                }
+                ir::Instruction::Label(_) => (),
+                ir::Instruction::FunCall { f, args, dst } => {
+                    let mut values = vec![];
+                    for arg in args {
+                        values.push(self.read(frame, arg).map_err(error)?);
+                    }
+                    match self.call(f, values)? {
+                        Flow::Return(c) => self.write(frame, dst, c),
+                        exit => return Ok(exit),
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn run(&mut self, entry: &str) -> Result<Outcome, Error> {
+        let exit_code = match self.call(&entry.to_string(), vec![])? {
+            Flow::Return(c) => const_convert::const_to_i64(c) as i32,
+            Flow::Exit(code) => code,
+        };
+        Ok(Outcome {
+            exit_code: exit_code,
+            output: self.output.clone(),
+        })
+    }
+}
+
+fn init_to_i64(init: &initializers::StaticInit) -> i64 {
+    match init {
+        initializers::StaticInit::BoolInit(b) => *b as i64,
+        initializers::StaticInit::ShortInit(s) => *s as i64,
+        initializers::StaticInit::UShortInit(u) => *u as i64,
+        initializers::StaticInit::IntInit(i) => *i as i64,
+        initializers::StaticInit::LongInit(l) => *l,
+    }
+}
+
+/// 从`main`开始执行整个程序。最多执行`DEFAULT_FUEL`条指令，这样死循环的程序也会停下来。
+pub fn run(program: &ir::T) -> Result<Outcome, Error> {
+    let mut interpreter = Interpreter::new(program);
+    interpreter.set_fuel(DEFAULT_FUEL);
+    interpreter.run("main")
+}
+
+#[test]
+fn test_wraparound_and_calls() {
+    let program = crate::ir_parser::parse(
+        "\
+declare putchar(int) -> int
+static ir_interpreter.max: int = 2147483647
+function ir_interpreter.emit(ir_interpreter.c: int) -> int {
+    var ir_interpreter.r: int
+    ir_interpreter.r = call putchar(ir_interpreter.c)
+    return ir_interpreter.r
+}
+function global ir_interpreter.main() -> int {
+    var ir_interpreter.x: int
+    var ir_interpreter.y: long
+    ir_interpreter.x = add ir_interpreter.max, 1:int
+    ir_interpreter.y = sign_extend ir_interpreter.x
+    ir_interpreter.x = call ir_interpreter.emit(72:int)
+    ir_interpreter.x = call ir_interpreter.emit(105:int)
+    ir_interpreter.x = lt ir_interpreter.y, 0:long
+    return ir_interpreter.x
+}
+",
+    )
+    .unwrap();
+    let outcome = Interpreter::new(&program)
+        .run("ir_interpreter.main")
+        .unwrap();
+    assert_eq!(
+        outcome,
+        Outcome {
+            exit_code: 1,
+            output: b"Hi".to_vec(),
+        }
+    );
+}
+
+#[test]
+fn test_detect_undefined_behavior() {
+    let program = crate::ir_parser::parse(
+        "\
+function global ir_interpreter.divide(ir_interpreter.d: int) -> int {
+    var ir_interpreter.q: int
+    jump_if_zero ir_interpreter.d, ir_interpreter.skip
+    ir_interpreter.q = div 1:int, ir_interpreter.d
+ir_interpreter.skip:
+    return ir_interpreter.q
+}
+function global ir_interpreter.by_zero() -> int {
+    var ir_interpreter.z: int
+    ir_interpreter.z = div 1:int, 0:int
+    return ir_interpreter.z
+}
+",
+    )
+    .unwrap();
+    let mut interpreter = Interpreter::new(&program);
+    let error = interpreter.run("ir_interpreter.by_zero").unwrap_err();
+    assert_eq!(error.message, "除以零");
+    let error = interpreter
+        .call(
+            &"ir_interpreter.divide".to_string(),
+            vec![constants::INT_ZERO],
+        )
+        .err()
+        .unwrap();
+    assert_eq!(error.message, "读取未初始化的变量ir_interpreter.q");
+}
+
+#[test]
+fn test_optimizations_preserve_output() {
+    let program = crate::ir_parser::parse(
+        "\
+declare putchar(int) -> int
+function global ir_interpreter.loop() -> int {
+    var ir_interpreter.i: int
+    var ir_interpreter.c: int
+    var ir_interpreter.t: int
+    ir_interpreter.i = copy 0:int
+ir_interpreter.start:
+    ir_interpreter.t = lt ir_interpreter.i, 3:int
+    jump_if_zero ir_interpreter.t, ir_interpreter.end
+    ir_interpreter.c = add 48:int, ir_interpreter.i
+    ir_interpreter.t = call putchar(ir_interpreter.c)
+    ir_interpreter.i = add ir_interpreter.i, 1:int
+    jump ir_interpreter.start
+ir_interpreter.end:
+    return 0:int
+}
+",
+    )
+    .unwrap();
+    let mut options = crate::optimize::Options::new();
+    options.set_level(2);
+    let optimized = crate::optimize::optimize(program.clone(), &options);
+    let before = Interpreter::new(&program)
+        .run("ir_interpreter.loop")
+        .unwrap();
+    let after = Interpreter::new(&optimized)
+        .run("ir_interpreter.loop")
+        .unwrap();
+    assert_eq!(before.output, b"012".to_vec());
+    assert_eq!(before, after);
+}
+
+#[test]
+fn test_stop_infinite_loop() {
+    let program = crate::ir_parser::parse(
+        "\
+function global ir_interpreter.spin() -> int {
+ir_interpreter.top:
+    jump ir_interpreter.top
+}
+",
+    )
+    .unwrap();
+    let mut interpreter = Interpreter::new(&program);
+    interpreter.set_fuel(100);
+    let error = interpreter.run("ir_interpreter.spin").unwrap_err();
+    assert_eq!(error.message, "执行的指令太多，可能是死循环");
+}
+
+#[test]
+fn test_jump_to_unknown_label() {
+    let program = ir::T::Program(vec![ir::TopLevel::Function {
+        name: "ir_interpreter.lost".to_string(),
+        global: true,
+        params: vec![],
+        body: vec![
+            ir::Instruction::Jump("ir_interpreter.nowhere".to_string()),
+            ir::Instruction::Return(ir::IrValue::Constant(constants::INT_ZERO)),
+        ],
+    }]);
+    let error = Interpreter::new(&program)
+        .run("ir_interpreter.lost")
+        .unwrap_err();
+    assert_eq!(error.message, "跳转到不存在的标签ir_interpreter.nowhere");
+    assert_eq!(
+        error.instruction,
+        Some(Box::new(ir::Instruction::Jump(
+            "ir_interpreter.nowhere".to_string()
+        )))
+    );
+}
@@ -0,0 +1,562 @@
+use crate::{constants, initializers, ir, symbols, types};
+
+// 文本形式的IR，和`ir::T`的Display输出一致，每行一条声明或指令，`#`之后是注释：
+//
+//     extern counter: int
+//     declare putchar(int) -> int
+//     static global limit: long = 10
+//     function global main(a: int) -> int {
+//         var tmp.0: int
+//         tmp.0 = add a, 1:int
+//         jump_if_zero tmp.0, end.1
+//         tmp.0 = call putchar(tmp.0)
+//     end.1:
+//         return tmp.0
+//     }
+//
+// 常量写成`值:类型`，类型是bool、short、ushort、int、long之一。
+// 一元运算是complement、negate、not，二元运算是add、sub、mul、div、mod、eq、ne、lt、le、gt、ge，
+// 另外还有copy、sign_extend、zero_extend、truncate、call、jump、jump_if_zero、jump_if_not_zero和return。
+// 没有写类型的参数和变量不会加到符号表里。
+//
+// 声明直接写进全局的符号表，局部变量也没有作用域，所以同一个名字在整个程序里只能声明一次，
+// 不同函数里的局部变量也不能同名。符号表里已经有的名字（比如上一次解析留下的）必须是同样的类型。
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Colon,
+    Comma,
+    Equal,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Arrow,
+    Ellipsis,
+}
+
+fn tokenize(number: usize, text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            break;
+        } else if c == '-' && i + 1 < chars.len() && chars[i + 1] == '>' {
+            tokens.push(Token::Arrow);
+            i += 2;
+        } else if c == '.' && chars[i..].starts_with(&['.', '.', '.']) {
+            tokens.push(Token::Ellipsis);
+            i += 3;
+        } else if c.is_ascii_digit()
+            || (c == '-' && i + 1 < chars.len() && chars[i + 1].is_ascii_digit())
+        {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            match literal.parse::<i64>() {
+                Ok(n) => tokens.push(Token::Number(n)),
+                Err(_) => return Err(format!("第{}行：{}超出了long的范围", number, literal)),
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            tokens.push(match c {
+                ':' => Token::Colon,
+                ',' => Token::Comma,
+                '=' => Token::Equal,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '{' => Token::LBrace,
+                '}' => Token::RBrace,
+                _ => return Err(format!("第{}行：无法识别的字符{}", number, c)),
+            });
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+struct Line {
+    number: usize,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Line {
+    fn error(&self, message: String) -> String {
+        format!("第{}行：{}", self.number, message)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err(self.error("这一行不完整".to_string())),
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        let token = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(self.error(format!("期望{:?}，实际是{:?}", expected, token)))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            other => Err(self.error(format!("期望标识符，实际是{:?}", other))),
+        }
+    }
+
+    fn skip_if(&mut self, token: Token) -> bool {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn end(&self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(self.error(format!("多余的{:?}", token))),
+        }
+    }
+}
+
+fn parse_type(line: &mut Line) -> Result<types::Type, String> {
+    let name = line.ident()?;
+    match name.as_str() {
+        "bool" => Ok(types::Type::Bool),
+        "short" => Ok(types::Type::Short),
+        "ushort" => Ok(types::Type::UShort),
+        "int" => Ok(types::Type::Int),
+        "long" => Ok(types::Type::Long),
+        _ => Err(line.error(format!("未知类型{}", name))),
+    }
+}
+
+fn parse_constant(line: &Line, n: i64, t: &types::Type) -> Result<constants::T, String> {
+    let out_of_range = || line.error(format!("{}超出了{}的范围", n, t));
+    match t {
+        types::Type::Bool => match n {
+            0 => Ok(constants::T::ConstBool(false)),
+            1 => Ok(constants::T::ConstBool(true)),
+            _ => Err(out_of_range()),
+        },
+        types::Type::Short => i16::try_from(n)
+            .map(constants::T::ConstShort)
+            .map_err(|_| out_of_range()),
+        types::Type::UShort => u16::try_from(n)
+            .map(constants::T::ConstUShort)
+            .map_err(|_| out_of_range()),
+        types::Type::Int => i32::try_from(n)
+            .map(constants::T::ConstInt)
+            .map_err(|_| out_of_range()),
+        types::Type::Long => Ok(constants::T::ConstLong(n)),
+        types::Type::FunType {
+            param_types: _,
+            ret_type: _,
+            has_prototype: _,
+        } => Err(line.error("常量不能是函数类型".to_string())),
+    }
+}
+
+fn to_static_init(c: constants::T) -> initializers::StaticInit {
+    match c {
+        constants::T::ConstBool(b) => initializers::StaticInit::BoolInit(b),
+        constants::T::ConstShort(s) => initializers::StaticInit::ShortInit(s),
+        constants::T::ConstUShort(u) => initializers::StaticInit::UShortInit(u),
+        constants::T::ConstInt(i) => initializers::StaticInit::IntInit(i),
+        constants::T::ConstLong(l) => initializers::StaticInit::LongInit(l),
+    }
+}
+
+fn parse_value(line: &mut Line) -> Result<ir::IrValue, String> {
+    match line.next()? {
+        Token::Ident(name) => Ok(ir::IrValue::Var(name)),
+        Token::Number(n) => {
+            line.expect(Token::Colon)?;
+            let t = parse_type(line)?;
+            Ok(ir::IrValue::Constant(parse_constant(line, n, &t)?))
+        }
+        other => Err(line.error(format!("期望变量或常量，实际是{:?}", other))),
+    }
+}
+
+fn parse_binary_operator(name: &str) -> Option<ir::BinaryOperator> {
+    match name {
+        "add" => Some(ir::BinaryOperator::Add),
+        "sub" => Some(ir::BinaryOperator::Subtract),
+        "mul" => Some(ir::BinaryOperator::Multiply),
+        "div" => Some(ir::BinaryOperator::Divide),
+        "mod" => Some(ir::BinaryOperator::Mod),
+        "eq" => Some(ir::BinaryOperator::Equal),
+        "ne" => Some(ir::BinaryOperator::NotEqual),
+        "lt" => Some(ir::BinaryOperator::LessThan),
+        "le" => Some(ir::BinaryOperator::LessOrEqual),
+        "gt" => Some(ir::BinaryOperator::GreaterThan),
+        "ge" => Some(ir::BinaryOperator::GreaterOrEqual),
+        _ => None,
+    }
+}
+
+fn parse_assignment(line: &mut Line, dst: ir::IrValue) -> Result<ir::Instruction, String> {
+    let op = line.ident()?;
+    let instruction = match op.as_str() {
+        "copy" => ir::Instruction::Copy {
+            src: parse_value(line)?,
+            dst: dst,
+        },
+        "sign_extend" => ir::Instruction::SignExtend {
+            src: parse_value(line)?,
+            dst: dst,
+        },
+        "zero_extend" => ir::Instruction::ZeroExtend {
+            src: parse_value(line)?,
+            dst: dst,
+        },
+        "truncate" => ir::Instruction::Truncate {
+            src: parse_value(line)?,
+            dst: dst,
+        },
+        "complement" | "negate" | "not" => ir::Instruction::Unary {
+            op: match op.as_str() {
+                "complement" => ir::UnaryOperator::Complement,
+                "negate" => ir::UnaryOperator::Negate,
+                _ => ir::UnaryOperator::Not,
+            },
+            src: parse_value(line)?,
+            dst: dst,
+        },
+        "call" => {
+            let f = line.ident()?;
+            line.expect(Token::LParen)?;
+            let mut args = vec![];
+            if !line.skip_if(Token::RParen) {
+                loop {
+                    args.push(parse_value(line)?);
+                    if line.skip_if(Token::RParen) {
+                        break;
+                    }
+                    line.expect(Token::Comma)?;
+                }
+            }
+            ir::Instruction::FunCall {
+                f: f,
+                args: args,
+                dst: dst,
+            }
+        }
+        other => match parse_binary_operator(other) {
+            Some(binary_op) => {
+                let src1 = parse_value(line)?;
+                line.expect(Token::Comma)?;
+                let src2 = parse_value(line)?;
+                ir::Instruction::Binary {
+                    op: binary_op,
+                    src1: src1,
+                    src2: src2,
+                    dst: dst,
+                }
+            }
+            None => return Err(line.error(format!("未知指令{}", other))),
+        },
+    };
+    Ok(instruction)
+}
+
+fn parse_instruction(line: &mut Line) -> Result<ir::Instruction, String> {
+    let first = line.ident()?;
+    let instruction = match first.as_str() {
+        _ if line.peek() == Some(&Token::Colon) && line.tokens.len() == 2 => {
+            line.next()?;
+            ir::Instruction::Label(first)
+        }
+        "return" => ir::Instruction::Return(parse_value(line)?),
+        "jump" => ir::Instruction::Jump(line.ident()?),
+        "jump_if_zero" | "jump_if_not_zero" => {
+            let cond = parse_value(line)?;
+            line.expect(Token::Comma)?;
+            let target = line.ident()?;
+            if first == "jump_if_zero" {
+                ir::Instruction::JumpIfZero(cond, target)
+            } else {
+                ir::Instruction::JumpIfNotZero(cond, target)
+            }
+        }
+        _ => {
+            line.expect(Token::Equal)?;
+            parse_assignment(line, ir::IrValue::Var(first))?
+        }
+    };
+    line.end()?;
+    Ok(instruction)
+}
+
+/// `(int, long) -> int`或者没有原型的`(...) -> int`
+fn declare(
+    line: &Line,
+    declared: &mut Vec<String>,
+    name: &String,
+    t: Option<&types::Type>,
+) -> Result<(), String> {
+    if declared.contains(name) {
+        return Err(line.error(format!("{}重复声明", name)));
+    }
+    if let (Some(t), Some(entry)) = (t, symbols::get_opt(name.clone())) {
+        if entry.t != *t {
+            return Err(line.error(format!("{}已经声明为{}，不能再声明为{}", name, entry.t, t)));
+        }
+    }
+    declared.push(name.clone());
+    Ok(())
+}
+
+fn parse_fun_type(line: &mut Line) -> Result<types::Type, String> {
+    line.expect(Token::LParen)?;
+    let mut param_types = vec![];
+    let mut has_prototype = true;
+    if line.skip_if(Token::Ellipsis) {
+        has_prototype = false;
+        line.expect(Token::RParen)?;
+    } else if !line.skip_if(Token::RParen) {
+        loop {
+            param_types.push(Box::new(parse_type(line)?));
+            if line.skip_if(Token::RParen) {
+                break;
+            }
+            line.expect(Token::Comma)?;
+        }
+    }
+    line.expect(Token::Arrow)?;
+    let ret_type = parse_type(line)?;
+    Ok(types::Type::FunType {
+        param_types: param_types,
+        ret_type: Box::new(ret_type),
+        has_prototype: has_prototype,
+    })
+}
+
+fn parse_static_variable(
+    line: &mut Line,
+    declared: &mut Vec<String>,
+) -> Result<ir::TopLevel, String> {
+    let global = line.skip_if(Token::Ident("global".to_string()));
+    let name = line.ident()?;
+    line.expect(Token::Colon)?;
+    let t = parse_type(line)?;
+    declare(line, declared, &name, Some(&t))?;
+    line.expect(Token::Equal)?;
+    let init = match line.next()? {
+        Token::Number(n) => to_static_init(parse_constant(line, n, &t)?),
+        other => return Err(line.error(format!("静态变量的初始值必须是常量，实际是{:?}", other))),
+    };
+    line.end()?;
+    symbols::add_static_var(
+        name.clone(),
+        t.clone(),
+        global,
+        symbols::InitialValue::Initial(init.clone()),
+    );
+    Ok(ir::TopLevel::StaticVariable {
+        name: name,
+        t: t,
+        global: global,
+        init: init,
+    })
+}
+
+fn parse_function(
+    line: &mut Line,
+    body_lines: &mut Vec<Line>,
+    declared: &mut Vec<String>,
+) -> Result<ir::TopLevel, String> {
+    let global = line.skip_if(Token::Ident("global".to_string()));
+    let name = line.ident()?;
+    line.expect(Token::LParen)?;
+    let mut params = vec![];
+    let mut param_types = vec![];
+    if !line.skip_if(Token::RParen) {
+        loop {
+            let param = line.ident()?;
+            if line.skip_if(Token::Colon) {
+                let t = parse_type(line)?;
+                declare(line, declared, &param, Some(&t))?;
+                symbols::add_automatic_var(param.clone(), t.clone());
+                param_types.push(Box::new(t));
+            } else {
+                declare(line, declared, &param, None)?;
+            }
+            params.push(param);
+            if line.skip_if(Token::RParen) {
+                break;
+            }
+            line.expect(Token::Comma)?;
+        }
+    }
+    let mut fun_type = None;
+    if line.skip_if(Token::Arrow) {
+        let ret_type = parse_type(line)?;
+        if param_types.len() == params.len() {
+            fun_type = Some(types::Type::FunType {
+                param_types: param_types,
+                ret_type: Box::new(ret_type),
+                has_prototype: true,
+            });
+        }
+    }
+    declare(line, declared, &name, fun_type.as_ref())?;
+    if let Some(t) = fun_type {
+        symbols::add_fun(name.clone(), t, global, true, false, false);
+    }
+    line.expect(Token::LBrace)?;
+    line.end()?;
+    let mut body = vec![];
+    loop {
+        if body_lines.is_empty() {
+            return Err(line.error(format!("函数{}缺少结尾的}}", name)));
+        }
+        let mut body_line = body_lines.remove(0);
+        if body_line.skip_if(Token::RBrace) {
+            body_line.end()?;
+            break;
+        }
+        if body_line.skip_if(Token::Ident("var".to_string())) {
+            let var = body_line.ident()?;
+            body_line.expect(Token::Colon)?;
+            let t = parse_type(&mut body_line)?;
+            body_line.end()?;
+            declare(&body_line, declared, &var, Some(&t))?;
+            symbols::add_automatic_var(var, t);
+        } else {
+            body.push(parse_instruction(&mut body_line)?);
+        }
+    }
+    Ok(ir::TopLevel::Function {
+        name: name,
+        global: global,
+        params: params,
+        body: body,
+    })
+}
+
+/// 解析文本形式的IR。遇到的类型声明会加到符号表里，后面的优化和代码生成依赖这些信息；
+/// 和符号表里已有的声明冲突时报错，而不是覆盖。
+pub fn parse(text: &str) -> Result<ir::T, String> {
+    let mut lines = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let tokens = tokenize(i + 1, line)?;
+        if !tokens.is_empty() {
+            lines.push(Line {
+                number: i + 1,
+                tokens: tokens,
+                pos: 0,
+            });
+        }
+    }
+    let mut top_levels = vec![];
+    let mut declared = vec![];
+    while !lines.is_empty() {
+        let mut line = lines.remove(0);
+        let keyword = line.ident()?;
+        match keyword.as_str() {
+            "extern" => {
+                let name = line.ident()?;
+                line.expect(Token::Colon)?;
+                let t = parse_type(&mut line)?;
+                line.end()?;
+                declare(&line, &mut declared, &name, Some(&t))?;
+                symbols::add_static_var(name, t, true, symbols::InitialValue::NoInitializer);
+            }
+            "declare" => {
+                let name = line.ident()?;
+                let t = parse_fun_type(&mut line)?;
+                line.end()?;
+                declare(&line, &mut declared, &name, Some(&t))?;
+                symbols::add_fun(name, t, true, false, false, false);
+            }
+            "static" => top_levels.push(parse_static_variable(&mut line, &mut declared)?),
+            "function" => top_levels.push(parse_function(&mut line, &mut lines, &mut declared)?),
+            other => return Err(line.error(format!("期望声明或者函数定义，实际是{}", other))),
+        }
+    }
+    Ok(ir::T::Program(top_levels))
+}
+
+#[test]
+fn test_round_trip() {
+    let text = "\
+declare ir_parser.putchar(int) -> int
+static global ir_parser.limit: long = -10
+function global ir_parser.main(ir_parser.a: int) -> int {
+    var ir_parser.tmp.0: int
+    var ir_parser.tmp.1: long
+    ir_parser.tmp.0 = add ir_parser.a, 1:int
+    jump_if_zero ir_parser.tmp.0, ir_parser.end.1
+    ir_parser.tmp.1 = sign_extend ir_parser.tmp.0
+    ir_parser.tmp.0 = call ir_parser.putchar(ir_parser.tmp.0)
+ir_parser.end.1:
+    return ir_parser.tmp.0
+}
+";
+    let program = parse(text).unwrap();
+    assert_eq!(program.to_string(), text);
+    assert_eq!(parse(&program.to_string()).unwrap(), program);
+    assert_eq!(
+        symbols::get("ir_parser.tmp.1".to_string()).t,
+        types::Type::Long
+    );
+}
+
+#[test]
+fn test_report_line_of_error() {
+    let text = "function f() {\n    x = copy 70000:short\n}\n";
+    assert_eq!(
+        parse(text),
+        Err("第2行：70000超出了short的范围".to_string())
+    );
+}
+
+#[test]
+fn test_reject_conflicting_declarations() {
+    assert!(parse("extern ir_parser.shared: int\n").is_ok());
+    // 同样的声明可以再解析一次，类型不同就和符号表里的冲突
+    assert!(parse("extern ir_parser.shared: int\n").is_ok());
+    assert_eq!(
+        parse("extern ir_parser.shared: long\n"),
+        Err("第1行：ir_parser.shared已经声明为int，不能再声明为long".to_string())
+    );
+    let text = "\
+function ir_parser.f(ir_parser.p: int) -> int {
+    var ir_parser.p: int
+    return ir_parser.p
+}
+";
+    assert_eq!(parse(text), Err("第2行：ir_parser.p重复声明".to_string()));
+}
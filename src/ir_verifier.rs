@@ -0,0 +1,449 @@
+use std::fmt::Display;
+
+use crate::{cfg, constants, ir, symbols, type_utils, types};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub function: String,
+    pub instruction: Option<ir::Instruction>,
+    pub message: String,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.instruction {
+            Some(i) => write!(f, "函数{}中的指令{}：{}", self.function, i, self.message),
+            None => write!(f, "函数{}：{}", self.function, self.message),
+        }
+    }
+}
+
+// 在某个程序点之前，所有路径上都已经被赋值的变量
+type DefinedVars = Vec<String>;
+
+/// 不在符号表里的变量（例如手写的IR里的临时变量）没有类型信息，只检查它们在使用前是否被定义。
+fn get_type(v: &ir::IrValue) -> Option<types::Type> {
+    match v {
+        ir::IrValue::Constant(c) => Some(constants::type_of_const(c)),
+        ir::IrValue::Var(name) => symbols::get_opt(name.clone()).map(|entry| entry.t),
+    }
+}
+
+fn size(t: &types::Type) -> i64 {
+    type_utils::get_size(t.clone())
+}
+
+fn check_types(instruction: &ir::Instruction) -> Vec<String> {
+    let mut errors = vec![];
+    match instruction {
+        ir::Instruction::SignExtend { src, dst } | ir::Instruction::ZeroExtend { src, dst } => {
+            if let (Some(src_t), Some(dst_t)) = (get_type(src), get_type(dst)) {
+                if size(&src_t) >= size(&dst_t) {
+                    errors.push(format!("不能从{:?}扩展到{:?}", src_t, dst_t))
+                }
+            }
+        }
+        ir::Instruction::Truncate { src, dst } => {
+            if let (Some(src_t), Some(dst_t)) = (get_type(src), get_type(dst)) {
+                if size(&src_t) <= size(&dst_t) {
+                    errors.push(format!("不能从{:?}截断到{:?}", src_t, dst_t))
+                }
+            }
+        }
+        // 同样宽度的有符号和无符号类型之间的转换就是Copy
+        ir::Instruction::Copy { src, dst } => {
+            if let (Some(src_t), Some(dst_t)) = (get_type(src), get_type(dst)) {
+                if size(&src_t) != size(&dst_t) {
+                    errors.push(format!("{:?}和{:?}的宽度不同", src_t, dst_t))
+                }
+            }
+        }
+        ir::Instruction::Unary {
+            op: ir::UnaryOperator::Not,
+            src: _,
+            dst: _,
+        } => (),
+        ir::Instruction::Unary { op: _, src, dst } => {
+            if let (Some(src_t), Some(dst_t)) = (get_type(src), get_type(dst)) {
+                if src_t != dst_t {
+                    errors.push(format!("操作数是{:?}，结果却是{:?}", src_t, dst_t))
+                }
+            }
+        }
+        ir::Instruction::Binary {
+            op,
+            src1,
+            src2,
+            dst,
+        } => {
+            if let (Some(t1), Some(t2)) = (get_type(src1), get_type(src2)) {
+                if t1 != t2 {
+                    errors.push(format!("两个操作数的类型{:?}和{:?}不一致", t1, t2))
+                }
+            }
+            let is_arithmetic = match op {
+                ir::BinaryOperator::Add
+                | ir::BinaryOperator::Subtract
+                | ir::BinaryOperator::Multiply
+                | ir::BinaryOperator::Divide
+                | ir::BinaryOperator::Mod => true,
+                _ => false,
+            };
+            // 比较的结果总是int，只有算术运算的结果和操作数同类型
+            if is_arithmetic {
+                if let (Some(t1), Some(dst_t)) = (get_type(src1), get_type(dst)) {
+                    if t1 != dst_t {
+                        errors.push(format!("操作数是{:?}，结果却是{:?}", t1, dst_t))
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+    errors
+}
+
+fn check_labels(body: &Vec<ir::Instruction>) -> Vec<(Option<ir::Instruction>, String)> {
+    let mut errors = vec![];
+    let mut labels = vec![];
+    for instruction in body {
+        if let ir::Instruction::Label(l) = instruction {
+            if labels.contains(l) {
+                errors.push((Some(instruction.clone()), "标签重复定义".to_string()));
+            }
+            labels.push(l.clone());
+        }
+    }
+    for instruction in body {
+        match instruction {
+            ir::Instruction::Jump(target)
+            | ir::Instruction::JumpIfZero(_, target)
+            | ir::Instruction::JumpIfNotZero(_, target) => {
+                if !labels.contains(target) {
+                    errors.push((
+                        Some(instruction.clone()),
+                        format!("跳转目标{}不存在", target),
+                    ));
+                }
+            }
+            _ => (),
+        }
+    }
+    errors
+}
+
+fn used_values(instruction: &ir::Instruction) -> Vec<ir::IrValue> {
+    match instruction {
+        ir::Instruction::Return(v)
+        | ir::Instruction::JumpIfZero(v, _)
+        | ir::Instruction::JumpIfNotZero(v, _) => vec![v.clone()],
+        ir::Instruction::SignExtend { src, dst: _ }
+        | ir::Instruction::ZeroExtend { src, dst: _ }
+        | ir::Instruction::Truncate { src, dst: _ }
+        | ir::Instruction::Copy { src, dst: _ }
+        | ir::Instruction::Unary { op: _, src, dst: _ } => vec![src.clone()],
+        ir::Instruction::Binary {
+            op: _,
+            src1,
+            src2,
+            dst: _,
+        } => vec![src1.clone(), src2.clone()],
+        ir::Instruction::FunCall { f: _, args, dst: _ } => args.clone(),
+        ir::Instruction::Jump(_) | ir::Instruction::Label(_) => vec![],
+    }
+}
+
+fn defined_value(instruction: &ir::Instruction) -> Option<String> {
+    match instruction {
+        ir::Instruction::SignExtend { src: _, dst }
+        | ir::Instruction::ZeroExtend { src: _, dst }
+        | ir::Instruction::Truncate { src: _, dst }
+        | ir::Instruction::Copy { src: _, dst }
+        | ir::Instruction::Unary { op: _, src: _, dst }
+        | ir::Instruction::Binary {
+            op: _,
+            src1: _,
+            src2: _,
+            dst,
+        }
+        | ir::Instruction::FunCall { f: _, args: _, dst } => match dst {
+            ir::IrValue::Var(name) => Some(name.clone()),
+            ir::IrValue::Constant(_) => None,
+        },
+        _ => None,
+    }
+}
+
+fn transfer(
+    block: &cfg::BasicBlock<DefinedVars, ir::Instruction>,
+    initial: DefinedVars,
+) -> cfg::BasicBlock<DefinedVars, ir::Instruction> {
+    let mut current = initial;
+    let mut instructions = vec![];
+    for (_, instruction) in block.instructions.iter() {
+        instructions.push((current.clone(), instruction.clone()));
+        if let Some(name) = defined_value(instruction) {
+            if !current.contains(&name) {
+                current.push(name);
+            }
+        }
+    }
+    cfg::BasicBlock {
+        id: block.id.clone(),
+        instructions: instructions,
+        preds: block.preds.clone(),
+        succs: block.succs.clone(),
+        value: current,
+    }
+}
+
+fn meet(
+    block: &cfg::BasicBlock<DefinedVars, ir::Instruction>,
+    all_vars: &DefinedVars,
+    params: &Vec<String>,
+    cfg: &cfg::Graph<DefinedVars, ir::Instruction>,
+) -> DefinedVars {
+    let mut incoming = all_vars.clone();
+    for pred in block.preds.iter() {
+        let pred_value = match pred {
+            cfg::NodeId::Entry => params.clone(),
+            cfg::NodeId::Block(n) => cfg::get_block_value(*n, cfg),
+            cfg::NodeId::Exit => panic!("内部错误：出口节点不能是前驱。"),
+        };
+        incoming.retain(|v| pred_value.contains(v));
+    }
+    incoming
+}
+
+fn find_defined_vars(
+    params: &Vec<String>,
+    cfg: cfg::Graph<(), ir::Instruction>,
+) -> cfg::Graph<DefinedVars, ir::Instruction> {
+    let mut all_vars = params.clone();
+    for block in cfg.basic_blocks.values() {
+        for (_, instruction) in block.instructions.iter() {
+            if let Some(name) = defined_value(instruction) {
+                if !all_vars.contains(&name) {
+                    all_vars.push(name);
+                }
+            }
+        }
+    }
+    // 交汇运算是求交集，所以一开始假设所有变量都已经定义
+    let mut annotated = cfg::initialize_annotation(cfg, all_vars.clone());
+    let mut worklist: Vec<usize> = annotated.basic_blocks.keys().cloned().collect();
+    while !worklist.is_empty() {
+        let block_idx = worklist.remove(0);
+        let block = annotated.basic_blocks[&block_idx].clone();
+        let incoming = meet(&block, &all_vars, params, &annotated);
+        let new_block = transfer(&block, incoming);
+        let changed = block.value.len() != new_block.value.len();
+        cfg::update_basic_block(block_idx, new_block, &mut annotated);
+        if changed {
+            for succ in cfg::get_succs(&cfg::NodeId::Block(block_idx), &annotated) {
+                if let cfg::NodeId::Block(n) = succ {
+                    if !worklist.contains(&n) {
+                        worklist.push(n);
+                    }
+                }
+            }
+        }
+    }
+    annotated
+}
+
+/// 除了参数和静态变量，每个变量（包括源程序里的局部变量）在所有到达使用点的路径上都必须先被赋值。
+/// 源程序读取可能没有初始化的局部变量是未定义行为，也会在这里报告。
+fn check_defined_before_use(
+    params: &Vec<String>,
+    cfg: &cfg::Graph<DefinedVars, ir::Instruction>,
+) -> Vec<(Option<ir::Instruction>, String)> {
+    let mut errors = vec![];
+    for block in cfg.basic_blocks.values() {
+        for (defined, instruction) in block.instructions.iter() {
+            for v in used_values(instruction) {
+                if let ir::IrValue::Var(name) = v {
+                    let known = params.contains(&name)
+                        || symbols::is_static(name.clone())
+                        || defined.contains(&name);
+                    if !known {
+                        errors.push((
+                            Some(instruction.clone()),
+                            format!("变量{}在某些路径上没有定义就被使用", name),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    errors
+}
+
+fn check_returns(
+    cfg: &cfg::Graph<DefinedVars, ir::Instruction>,
+) -> Vec<(Option<ir::Instruction>, String)> {
+    let mut errors = vec![];
+    for pred in cfg.exit_preds.iter() {
+        let last = match pred {
+            cfg::NodeId::Block(n) => cfg.basic_blocks[n]
+                .instructions
+                .last()
+                .map(|(_, i)| i.clone()),
+            _ => None,
+        };
+        match last {
+            Some(ir::Instruction::Return(_)) => (),
+            other => errors.push((other, "控制流可以不经过Return到达函数末尾".to_string())),
+        }
+    }
+    errors
+}
+
+pub fn verify_function(
+    name: &String,
+    params: &Vec<String>,
+    body: &Vec<ir::Instruction>,
+) -> Vec<Error> {
+    let mut errors = check_labels(body);
+    // 跳转目标不对的时候没法构造控制流图
+    let labels_ok = errors.is_empty();
+    for instruction in body {
+        for message in check_types(instruction) {
+            errors.push((Some(instruction.clone()), message));
+        }
+    }
+    if labels_ok {
+        let cfg = find_defined_vars(params, cfg::instructions_to_cfg(name.clone(), body.clone()));
+        errors.append(&mut check_defined_before_use(params, &cfg));
+        errors.append(&mut check_returns(&cfg));
+    }
+    errors
+        .into_iter()
+        .map(|(instruction, message)| Error {
+            function: name.clone(),
+            instruction: instruction,
+            message: message,
+        })
+        .collect()
+}
+
+pub fn verify(program: &ir::T) -> Result<(), Vec<Error>> {
+    let mut errors = vec![];
+    match program {
+        ir::T::Program(top_levels) => {
+            for top_level in top_levels {
+                if let ir::TopLevel::Function {
+                    name,
+                    global: _,
+                    params,
+                    body,
+                } = top_level
+                {
+                    errors.append(&mut verify_function(name, params, body));
+                }
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// 只在debug构建里运行，IR不合法说明`after`这一步有bug。
+pub fn check_function(
+    name: &String,
+    params: &Vec<String>,
+    body: &Vec<ir::Instruction>,
+    after: &str,
+) {
+    if cfg!(debug_assertions) {
+        let errors = verify_function(name, params, body);
+        if !errors.is_empty() {
+            for e in errors.iter() {
+                eprintln!("{}", e);
+            }
+            panic!("内部错误：{}之后的IR不合法。", after);
+        }
+    }
+}
+
+pub fn check(program: &ir::T, after: &str) {
+    if cfg!(debug_assertions) {
+        if let Err(errors) = verify(program) {
+            for e in errors.iter() {
+                eprintln!("{}", e);
+            }
+            panic!("内部错误：{}之后的IR不合法。", after);
+        }
+    }
+}
+
+#[test]
+fn test_reject_missing_label_and_return() {
+    let body = vec![
+        ir::Instruction::Copy {
+            src: ir::IrValue::Constant(constants::T::ConstInt(1)),
+            dst: ir::IrValue::Var("ir_verifier.x".to_string()),
+        },
+        ir::Instruction::Jump("ir_verifier.missing".to_string()),
+    ];
+    let errors = verify_function(&"f".to_string(), &vec![], &body);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].instruction,
+        Some(ir::Instruction::Jump("ir_verifier.missing".to_string()))
+    );
+    let errors = verify_function(&"f".to_string(), &vec![], &body[..1].to_vec());
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_use_defined_on_one_path_only() {
+    let x = ir::IrValue::Var("ir_verifier.y".to_string());
+    let body = vec![
+        ir::Instruction::JumpIfZero(ir::IrValue::Var("p".to_string()), "end".to_string()),
+        ir::Instruction::Copy {
+            src: ir::IrValue::Constant(constants::T::ConstInt(1)),
+            dst: x.clone(),
+        },
+        ir::Instruction::Label("end".to_string()),
+        ir::Instruction::Return(x.clone()),
+    ];
+    let errors = verify_function(&"f".to_string(), &vec!["p".to_string()], &body);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].instruction, Some(ir::Instruction::Return(x)));
+}
+
+#[test]
+fn test_temporary_read_before_assignment() {
+    let c = ir::IrValue::Var("ir_verifier.c".to_string());
+    let tmp = ir::IrValue::Var("ir_verifier.tmp.0".to_string());
+    symbols::add_automatic_var("ir_verifier.c".to_string(), types::Type::Int);
+    symbols::add_automatic_var("ir_verifier.tmp.0".to_string(), types::Type::Int);
+    // tmp只在c不为0的路径上被赋值，另一条路径直接读取它
+    let body = vec![
+        ir::Instruction::JumpIfZero(c.clone(), "ir_verifier.skip".to_string()),
+        ir::Instruction::Copy {
+            src: c.clone(),
+            dst: tmp.clone(),
+        },
+        ir::Instruction::Label("ir_verifier.skip".to_string()),
+        ir::Instruction::Return(tmp.clone()),
+    ];
+    let errors = verify_function(&"f".to_string(), &vec!["ir_verifier.c".to_string()], &body);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].instruction, Some(ir::Instruction::Return(tmp)));
+    // 静态变量不需要先赋值
+    symbols::add_static_var(
+        "ir_verifier.counter".to_string(),
+        types::Type::Int,
+        false,
+        symbols::InitialValue::Tentative,
+    );
+    let body = vec![ir::Instruction::Return(ir::IrValue::Var(
+        "ir_verifier.counter".to_string(),
+    ))];
+    assert!(verify_function(&"f".to_string(), &vec![], &body).is_empty());
+}
@@ -0,0 +1,85 @@
+use std::fmt::Display;
+
+/// 最小的JSON值，只用来把各个阶段的中间结果输出给外部工具，不需要解析。
+/// 对象的字段保持插入顺序，这样同样的输入总是得到同样的输出。
+#[derive(Clone, Debug, PartialEq)]
+pub enum T {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Array(Vec<T>),
+    Object(Vec<(String, T)>),
+}
+
+pub fn str(s: &str) -> T {
+    T::Str(s.to_string())
+}
+
+pub fn object(fields: Vec<(&str, T)>) -> T {
+    T::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Display for T {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            T::Null => write!(f, "null"),
+            T::Bool(b) => write!(f, "{}", b),
+            T::Int(i) => write!(f, "{}", i),
+            T::Str(s) => write!(f, "\"{}\"", escape(s)),
+            T::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            T::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "\"{}\":{}", escape(key), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+#[test]
+fn test_escape_and_order() {
+    let value = object(vec![
+        ("b", T::Array(vec![T::Int(-1), T::Null, T::Bool(true)])),
+        ("a", str("say \"hi\"\n\u{1}")),
+    ]);
+    assert_eq!(
+        value.to_string(),
+        "{\"b\":[-1,null,true],\"a\":\"say \\\"hi\\\"\\n\\u0001\"}"
+    );
+}
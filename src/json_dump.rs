@@ -0,0 +1,965 @@
+use crate::{
+    assembly, assembly_symbols, ast, constants, initializers, ir, json, symbols, tokens, types,
+};
+
+/// 输出格式有不兼容的修改时加一，外部工具据此判断能不能读。
+pub const SCHEMA_VERSION: i64 = 1;
+
+fn document(stage: &str, fields: Vec<(&str, json::T)>) -> json::T {
+    let mut all = vec![
+        ("schema_version", json::T::Int(SCHEMA_VERSION)),
+        ("stage", json::str(stage)),
+    ];
+    all.extend(fields);
+    json::object(all)
+}
+
+fn strings(names: &Vec<String>) -> json::T {
+    json::T::Array(names.iter().map(|name| json::str(name)).collect())
+}
+
+fn token_to_json(token: &tokens::Token) -> (&'static str, String) {
+    let (kind, text) = match token {
+        tokens::Token::Identifier(name) => return ("identifier", name.clone()),
+        tokens::Token::ConstInt(i) => return ("constant", i.to_string()),
+        tokens::Token::ConstLong(l) => return ("constant", format!("{}L", l)),
+        tokens::Token::StringLiteral(s) => return ("string", s.clone()),
+        tokens::Token::KWInt => ("keyword", "int"),
+        tokens::Token::KWLong => ("keyword", "long"),
+        tokens::Token::KWShort => ("keyword", "short"),
+        tokens::Token::KWSigned => ("keyword", "signed"),
+        tokens::Token::KWUnsigned => ("keyword", "unsigned"),
+        tokens::Token::KWBool => ("keyword", "_Bool"),
+        tokens::Token::KWReturn => ("keyword", "return"),
+        tokens::Token::KWVoid => ("keyword", "void"),
+        tokens::Token::KWIf => ("keyword", "if"),
+        tokens::Token::KWElse => ("keyword", "else"),
+        tokens::Token::KWDo => ("keyword", "do"),
+        tokens::Token::KWWhile => ("keyword", "while"),
+        tokens::Token::KWFor => ("keyword", "for"),
+        tokens::Token::KWBreak => ("keyword", "break"),
+        tokens::Token::KWContinue => ("keyword", "continue"),
+        tokens::Token::KWStatic => ("keyword", "static"),
+        tokens::Token::KWExtern => ("keyword", "extern"),
+        tokens::Token::KWInline => ("keyword", "inline"),
+        tokens::Token::KWSizeof => ("keyword", "sizeof"),
+        tokens::Token::KWStaticAssert => ("keyword", "_Static_assert"),
+        tokens::Token::OpenParen => ("punctuator", "("),
+        tokens::Token::CloseParen => ("punctuator", ")"),
+        tokens::Token::OpenBrace => ("punctuator", "{"),
+        tokens::Token::CloseBrace => ("punctuator", "}"),
+        tokens::Token::Semicolon => ("punctuator", ";"),
+        tokens::Token::Hyphen => ("punctuator", "-"),
+        tokens::Token::DoubleHyphen => ("punctuator", "--"),
+        tokens::Token::Tilde => ("punctuator", "~"),
+        tokens::Token::GreaterOrEqual => ("punctuator", ">="),
+        tokens::Token::LessOrEqual => ("punctuator", "<="),
+        tokens::Token::GreaterThan => ("punctuator", ">"),
+        tokens::Token::LessThan => ("punctuator", "<"),
+        tokens::Token::DoubleEqual => ("punctuator", "=="),
+        tokens::Token::NotEqual => ("punctuator", "!="),
+        tokens::Token::LogicalOr => ("punctuator", "||"),
+        tokens::Token::LogicalAnd => ("punctuator", "&&"),
+        tokens::Token::Bang => ("punctuator", "!"),
+        tokens::Token::Percent => ("punctuator", "%"),
+        tokens::Token::Slash => ("punctuator", "/"),
+        tokens::Token::Star => ("punctuator", "*"),
+        tokens::Token::Plus => ("punctuator", "+"),
+        tokens::Token::EqualSign => ("punctuator", "="),
+        tokens::Token::QuestionMark => ("punctuator", "?"),
+        tokens::Token::Colon => ("punctuator", ":"),
+        tokens::Token::Comma => ("punctuator", ","),
+        tokens::Token::Eof => ("eof", ""),
+    };
+    (kind, text.to_string())
+}
+
+/// 位置给出字节偏移和从1开始的行号、列号（列号按字节计）。
+pub fn tokens(tokens: &Vec<(tokens::Token, u64)>, source: &str) -> json::T {
+    let bytes = source.as_bytes();
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut scanned = 0;
+    let mut result = vec![];
+    for (token, offset) in tokens {
+        let offset = *offset as usize;
+        while scanned < offset && scanned < bytes.len() {
+            if bytes[scanned] == b'\n' {
+                line += 1;
+                line_start = scanned + 1;
+            }
+            scanned += 1;
+        }
+        let (kind, text) = token_to_json(token);
+        result.push(json::object(vec![
+            ("kind", json::str(kind)),
+            ("text", json::T::Str(text)),
+            ("offset", json::T::Int(offset as i64)),
+            ("line", json::T::Int(line)),
+            ("column", json::T::Int((offset - line_start) as i64 + 1)),
+        ]));
+    }
+    document("tokens", vec![("tokens", json::T::Array(result))])
+}
+
+fn type_to_json(t: &types::Type) -> json::T {
+    match t {
+        types::Type::FunType {
+            param_types,
+            ret_type,
+            has_prototype,
+        } => json::object(vec![
+            ("kind", json::str("function")),
+            (
+                "params",
+                json::T::Array(param_types.iter().map(|t| type_to_json(t)).collect()),
+            ),
+            ("ret", type_to_json(ret_type)),
+            ("has_prototype", json::T::Bool(*has_prototype)),
+        ]),
+        other => json::T::Str(other.to_string()),
+    }
+}
+
+fn const_to_json(c: &constants::T) -> json::T {
+    let value = match c {
+        constants::T::ConstBool(b) => *b as i64,
+        constants::T::ConstShort(s) => *s as i64,
+        constants::T::ConstUShort(u) => *u as i64,
+        constants::T::ConstInt(i) => *i as i64,
+        constants::T::ConstLong(l) => *l,
+    };
+    json::object(vec![
+        ("type", type_to_json(&constants::type_of_const(c))),
+        ("value", json::T::Int(value)),
+    ])
+}
+
+fn static_init_to_json(init: &initializers::StaticInit) -> json::T {
+    let (t, value) = match init {
+        initializers::StaticInit::BoolInit(b) => (types::Type::Bool, *b as i64),
+        initializers::StaticInit::ShortInit(s) => (types::Type::Short, *s as i64),
+        initializers::StaticInit::UShortInit(u) => (types::Type::UShort, *u as i64),
+        initializers::StaticInit::IntInit(i) => (types::Type::Int, *i as i64),
+        initializers::StaticInit::LongInit(l) => (types::Type::Long, *l),
+    };
+    json::object(vec![
+        ("type", type_to_json(&t)),
+        ("value", json::T::Int(value)),
+    ])
+}
+
+/// 符号表是HashMap，按名字排序保证输出稳定。
+fn symbols_to_json() -> json::T {
+    let mut bindings = symbols::bindings();
+    bindings.sort_by(|a, b| a.0.cmp(&b.0));
+    let entries = bindings
+        .iter()
+        .map(|(name, entry)| {
+            let mut fields = vec![("name", json::str(name)), ("type", type_to_json(&entry.t))];
+            match &entry.attrs {
+                symbols::IdentifierAttrs::FunAttr {
+                    defined,
+                    global,
+                    inline,
+                    inline_definition,
+                    stack_frame_size,
+                } => {
+                    fields.push(("kind", json::str("function")));
+                    fields.push(("defined", json::T::Bool(*defined)));
+                    fields.push(("global", json::T::Bool(*global)));
+                    fields.push(("inline", json::T::Bool(*inline)));
+                    fields.push(("inline_definition", json::T::Bool(*inline_definition)));
+                    fields.push(("stack_frame_size", json::T::Int(*stack_frame_size)));
+                }
+                symbols::IdentifierAttrs::StaticAttr { init, global } => {
+                    fields.push(("kind", json::str("static")));
+                    fields.push(("global", json::T::Bool(*global)));
+                    let init = match init {
+                        symbols::InitialValue::Tentative => json::str("tentative"),
+                        symbols::InitialValue::Initial(i) => static_init_to_json(i),
+                        symbols::InitialValue::NoInitializer => json::T::Null,
+                    };
+                    fields.push(("init", init));
+                }
+                symbols::IdentifierAttrs::LocalAttr => fields.push(("kind", json::str("local"))),
+            }
+            json::object(fields)
+        })
+        .collect();
+    json::T::Array(entries)
+}
+
+fn unary_op_name(op: &ast::UnaryOperator) -> &'static str {
+    match op {
+        ast::UnaryOperator::Complement => "complement",
+        ast::UnaryOperator::Negate => "negate",
+        ast::UnaryOperator::Not => "not",
+    }
+}
+
+fn binary_op_name(op: &ast::BinaryOperator) -> &'static str {
+    match op {
+        ast::BinaryOperator::Add => "add",
+        ast::BinaryOperator::Subtract => "sub",
+        ast::BinaryOperator::Multiply => "mul",
+        ast::BinaryOperator::Divide => "div",
+        ast::BinaryOperator::Mod => "mod",
+        ast::BinaryOperator::And => "and",
+        ast::BinaryOperator::Or => "or",
+        ast::BinaryOperator::Equal => "eq",
+        ast::BinaryOperator::NotEqual => "ne",
+        ast::BinaryOperator::LessThan => "lt",
+        ast::BinaryOperator::LessOrEqual => "le",
+        ast::BinaryOperator::GreaterThan => "gt",
+        ast::BinaryOperator::GreaterOrEqual => "ge",
+    }
+}
+
+fn exp_to_json(e: &ast::TypedExp) -> json::T {
+    let mut fields = match &*e.e {
+        ast::TypedInnerExp::Constant(c) => {
+            vec![("kind", json::str("constant")), ("value", const_to_json(c))]
+        }
+        ast::TypedInnerExp::Var(v) => vec![("kind", json::str("var")), ("name", json::str(v))],
+        ast::TypedInnerExp::Cast { target_type, e } => vec![
+            ("kind", json::str("cast")),
+            ("target_type", type_to_json(target_type)),
+            ("e", exp_to_json(e)),
+        ],
+        ast::TypedInnerExp::Unary(op, e) => vec![
+            ("kind", json::str("unary")),
+            ("op", json::str(unary_op_name(op))),
+            ("e", exp_to_json(e)),
+        ],
+        ast::TypedInnerExp::Binary(op, e1, e2) => vec![
+            ("kind", json::str("binary")),
+            ("op", json::str(binary_op_name(op))),
+            ("left", exp_to_json(e1)),
+            ("right", exp_to_json(e2)),
+        ],
+        ast::TypedInnerExp::Assignment(lhs, rhs) => vec![
+            ("kind", json::str("assignment")),
+            ("lhs", exp_to_json(lhs)),
+            ("rhs", exp_to_json(rhs)),
+        ],
+        ast::TypedInnerExp::Conditional {
+            condition,
+            then_result,
+            else_result,
+        } => vec![
+            ("kind", json::str("conditional")),
+            ("condition", exp_to_json(condition)),
+            ("then", exp_to_json(then_result)),
+            ("else", exp_to_json(else_result)),
+        ],
+        ast::TypedInnerExp::Funcall { f, args } => vec![
+            ("kind", json::str("call")),
+            ("f", json::str(f)),
+            (
+                "args",
+                json::T::Array(args.iter().map(exp_to_json).collect()),
+            ),
+        ],
+    };
+    fields.push(("type", type_to_json(&e.t)));
+    json::object(fields)
+}
+
+fn optional_exp_to_json(e: &Option<ast::TypedExp>) -> json::T {
+    match e {
+        Some(e) => exp_to_json(e),
+        None => json::T::Null,
+    }
+}
+
+fn statement_to_json(s: &ast::Statement<ast::TypedExp>) -> json::T {
+    match s {
+        ast::Statement::Return(e) => {
+            json::object(vec![("kind", json::str("return")), ("e", exp_to_json(e))])
+        }
+        ast::Statement::Expression(e) => json::object(vec![
+            ("kind", json::str("expression")),
+            ("e", exp_to_json(e)),
+        ]),
+        ast::Statement::If {
+            condition,
+            then_clause,
+            else_clause,
+        } => json::object(vec![
+            ("kind", json::str("if")),
+            ("condition", exp_to_json(condition)),
+            ("then", statement_to_json(then_clause)),
+            (
+                "else",
+                match else_clause {
+                    Some(s) => statement_to_json(s),
+                    None => json::T::Null,
+                },
+            ),
+        ]),
+        ast::Statement::Compound(block) => json::object(vec![
+            ("kind", json::str("compound")),
+            ("items", block_to_json(block)),
+        ]),
+        ast::Statement::Break(id) => {
+            json::object(vec![("kind", json::str("break")), ("loop", json::str(id))])
+        }
+        ast::Statement::Continue(id) => json::object(vec![
+            ("kind", json::str("continue")),
+            ("loop", json::str(id)),
+        ]),
+        ast::Statement::While {
+            condition,
+            body,
+            id,
+        } => json::object(vec![
+            ("kind", json::str("while")),
+            ("id", json::str(id)),
+            ("condition", exp_to_json(condition)),
+            ("body", statement_to_json(body)),
+        ]),
+        ast::Statement::DoWhile {
+            body,
+            condition,
+            id,
+        } => json::object(vec![
+            ("kind", json::str("do_while")),
+            ("id", json::str(id)),
+            ("body", statement_to_json(body)),
+            ("condition", exp_to_json(condition)),
+        ]),
+        ast::Statement::For {
+            init,
+            condition,
+            post,
+            body,
+            id,
+        } => {
+            let init = match init {
+                ast::ForInit::InitDecl(d) => var_decl_to_json(d),
+                ast::ForInit::InitExp(e) => optional_exp_to_json(e),
+            };
+            json::object(vec![
+                ("kind", json::str("for")),
+                ("id", json::str(id)),
+                ("init", init),
+                ("condition", optional_exp_to_json(condition)),
+                ("post", optional_exp_to_json(post)),
+                ("body", statement_to_json(body)),
+            ])
+        }
+        ast::Statement::Null => json::object(vec![("kind", json::str("null"))]),
+    }
+}
+
+fn block_to_json(block: &ast::Block<ast::TypedExp>) -> json::T {
+    match block {
+        ast::Block::Block(items) => json::T::Array(
+            items
+                .iter()
+                .map(|item| match item {
+                    ast::BlockItem::S(s) => statement_to_json(s),
+                    ast::BlockItem::D(d) => declaration_to_json(d),
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn storage_class_to_json(storage_class: &Option<ast::StorageClass>) -> json::T {
+    match storage_class {
+        Some(ast::StorageClass::Static) => json::str("static"),
+        Some(ast::StorageClass::Extern) => json::str("extern"),
+        None => json::T::Null,
+    }
+}
+
+fn var_decl_to_json(decl: &ast::VariableDeclaration<ast::TypedExp>) -> json::T {
+    json::object(vec![
+        ("kind", json::str("var_decl")),
+        ("name", json::str(&decl.name)),
+        ("type", type_to_json(&decl.var_type)),
+        ("init", optional_exp_to_json(&decl.init)),
+        ("storage_class", storage_class_to_json(&decl.storage_class)),
+    ])
+}
+
+fn declaration_to_json(d: &ast::Declaration<ast::TypedExp>) -> json::T {
+    match d {
+        ast::Declaration::FunDecl(decl) => json::object(vec![
+            ("kind", json::str("fun_decl")),
+            ("name", json::str(&decl.name)),
+            ("type", type_to_json(&decl.fun_type)),
+            ("params", strings(&decl.params)),
+            (
+                "body",
+                match &decl.body {
+                    Some(body) => block_to_json(body),
+                    None => json::T::Null,
+                },
+            ),
+            ("storage_class", storage_class_to_json(&decl.storage_class)),
+            ("inline", json::T::Bool(decl.inline)),
+        ]),
+        ast::Declaration::VarDecl(decl) => var_decl_to_json(decl),
+        ast::Declaration::StaticAssert(decl) => json::object(vec![
+            ("kind", json::str("static_assert")),
+            ("condition", exp_to_json(&decl.condition)),
+            (
+                "message",
+                match &decl.message {
+                    Some(message) => json::str(message),
+                    None => json::T::Null,
+                },
+            ),
+        ]),
+    }
+}
+
+pub fn ast(program: &ast::ProgType<ast::TypedExp>) -> json::T {
+    let decls = match program {
+        ast::ProgType::Program(decls) => decls.iter().map(declaration_to_json).collect(),
+    };
+    document(
+        "ast",
+        vec![
+            ("declarations", json::T::Array(decls)),
+            ("symbols", symbols_to_json()),
+        ],
+    )
+}
+
+fn ir_value_to_json(v: &ir::IrValue) -> json::T {
+    match v {
+        ir::IrValue::Constant(c) => json::object(vec![
+            ("kind", json::str("constant")),
+            ("value", const_to_json(c)),
+        ]),
+        ir::IrValue::Var(name) => {
+            json::object(vec![("kind", json::str("var")), ("name", json::str(name))])
+        }
+    }
+}
+
+/// 指令的`op`字段和文本形式的IR用同样的名字。
+fn ir_instruction_to_json(instruction: &ir::Instruction) -> json::T {
+    let conversion = |op: &str, src: &ir::IrValue, dst: &ir::IrValue| {
+        vec![
+            ("op", json::str(op)),
+            ("src", ir_value_to_json(src)),
+            ("dst", ir_value_to_json(dst)),
+        ]
+    };
+    let fields = match instruction {
+        ir::Instruction::Return(v) => {
+            vec![("op", json::str("return")), ("src", ir_value_to_json(v))]
+        }
+        ir::Instruction::SignExtend { src, dst } => conversion("sign_extend", src, dst),
+        ir::Instruction::Truncate { src, dst } => conversion("truncate", src, dst),
+        ir::Instruction::ZeroExtend { src, dst } => conversion("zero_extend", src, dst),
+        ir::Instruction::Copy { src, dst } => conversion("copy", src, dst),
+        ir::Instruction::Unary { op, src, dst } => conversion(&op.to_string(), src, dst),
+        ir::Instruction::Binary {
+            op,
+            src1,
+            src2,
+            dst,
+        } => vec![
+            ("op", json::T::Str(op.to_string())),
+            ("src1", ir_value_to_json(src1)),
+            ("src2", ir_value_to_json(src2)),
+            ("dst", ir_value_to_json(dst)),
+        ],
+        ir::Instruction::Jump(target) => {
+            vec![("op", json::str("jump")), ("target", json::str(target))]
+        }
+        ir::Instruction::JumpIfZero(v, target) => vec![
+            ("op", json::str("jump_if_zero")),
+            ("condition", ir_value_to_json(v)),
+            ("target", json::str(target)),
+        ],
+        ir::Instruction::JumpIfNotZero(v, target) => vec![
+            ("op", json::str("jump_if_not_zero")),
+            ("condition", ir_value_to_json(v)),
+            ("target", json::str(target)),
+        ],
+        ir::Instruction::Label(l) => vec![("op", json::str("label")), ("name", json::str(l))],
+        ir::Instruction::FunCall { f, args, dst } => vec![
+            ("op", json::str("call")),
+            ("f", json::str(f)),
+            (
+                "args",
+                json::T::Array(args.iter().map(ir_value_to_json).collect()),
+            ),
+            ("dst", ir_value_to_json(dst)),
+        ],
+    };
+    json::object(fields)
+}
+
+pub fn ir(program: &ir::T) -> json::T {
+    let top_levels = match program {
+        ir::T::Program(tls) => tls
+            .iter()
+            .map(|tl| match tl {
+                ir::TopLevel::Function {
+                    name,
+                    global,
+                    params,
+                    body,
+                } => json::object(vec![
+                    ("kind", json::str("function")),
+                    ("name", json::str(name)),
+                    ("global", json::T::Bool(*global)),
+                    ("params", strings(params)),
+                    (
+                        "body",
+                        json::T::Array(body.iter().map(ir_instruction_to_json).collect()),
+                    ),
+                ]),
+                ir::TopLevel::StaticVariable {
+                    name,
+                    t,
+                    global,
+                    init,
+                } => json::object(vec![
+                    ("kind", json::str("static_variable")),
+                    ("name", json::str(name)),
+                    ("type", type_to_json(t)),
+                    ("global", json::T::Bool(*global)),
+                    ("init", static_init_to_json(init)),
+                ]),
+            })
+            .collect(),
+    };
+    document(
+        "ir",
+        vec![
+            ("top_levels", json::T::Array(top_levels)),
+            ("symbols", symbols_to_json()),
+        ],
+    )
+}
+
+fn reg_name(reg: &assembly::Reg) -> &'static str {
+    match reg {
+        assembly::Reg::AX => "ax",
+        assembly::Reg::BX => "bx",
+        assembly::Reg::CX => "cx",
+        assembly::Reg::DX => "dx",
+        assembly::Reg::DI => "di",
+        assembly::Reg::SI => "si",
+        assembly::Reg::R8 => "r8",
+        assembly::Reg::R9 => "r9",
+        assembly::Reg::R10 => "r10",
+        assembly::Reg::R11 => "r11",
+        assembly::Reg::R12 => "r12",
+        assembly::Reg::R13 => "r13",
+        assembly::Reg::R14 => "r14",
+        assembly::Reg::R15 => "r15",
+        assembly::Reg::SP => "sp",
+        assembly::Reg::BP => "bp",
+    }
+}
+
+fn asm_type_name(t: &assembly::AsmType) -> json::T {
+    json::str(match t {
+        assembly::AsmType::Byte => "byte",
+        assembly::AsmType::Word => "word",
+        assembly::AsmType::Longword => "longword",
+        assembly::AsmType::Quadword => "quadword",
+    })
+}
+
+fn cond_code_name(cc: &assembly::CondCode) -> json::T {
+    json::str(match cc {
+        assembly::CondCode::E => "e",
+        assembly::CondCode::NE => "ne",
+        assembly::CondCode::G => "g",
+        assembly::CondCode::GE => "ge",
+        assembly::CondCode::L => "l",
+        assembly::CondCode::LE => "le",
+    })
+}
+
+fn operand_to_json(operand: &assembly::Operand) -> json::T {
+    match operand {
+        assembly::Operand::Imm(i) => json::object(vec![
+            ("kind", json::str("imm")),
+            ("value", json::T::Int(*i)),
+        ]),
+        assembly::Operand::Reg(r) => json::object(vec![
+            ("kind", json::str("reg")),
+            ("reg", json::str(reg_name(r))),
+        ]),
+        assembly::Operand::Pseudo(name) => json::object(vec![
+            ("kind", json::str("pseudo")),
+            ("name", json::str(name)),
+        ]),
+        assembly::Operand::Stack(offset) => json::object(vec![
+            ("kind", json::str("stack")),
+            ("offset", json::T::Int(*offset)),
+        ]),
+        assembly::Operand::Data(name) => {
+            json::object(vec![("kind", json::str("data")), ("name", json::str(name))])
+        }
+    }
+}
+
+fn asm_instruction_to_json(instruction: &assembly::Instruction) -> json::T {
+    let fields = match instruction {
+        assembly::Instruction::Mov(t, src, dst) => vec![
+            ("op", json::str("mov")),
+            ("type", asm_type_name(t)),
+            ("src", operand_to_json(src)),
+            ("dst", operand_to_json(dst)),
+        ],
+        assembly::Instruction::Movsx {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => vec![
+            ("op", json::str("movsx")),
+            ("src_type", asm_type_name(src_t)),
+            ("dst_type", asm_type_name(dst_t)),
+            ("src", operand_to_json(src)),
+            ("dst", operand_to_json(dst)),
+        ],
+        assembly::Instruction::MovZeroExtend {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => vec![
+            ("op", json::str("movzx")),
+            ("src_type", asm_type_name(src_t)),
+            ("dst_type", asm_type_name(dst_t)),
+            ("src", operand_to_json(src)),
+            ("dst", operand_to_json(dst)),
+        ],
+        assembly::Instruction::Unary(op, t, operand) => vec![
+            (
+                "op",
+                json::str(match op {
+                    assembly::UnaryOperator::Neg => "neg",
+                    assembly::UnaryOperator::Not => "not",
+                }),
+            ),
+            ("type", asm_type_name(t)),
+            ("dst", operand_to_json(operand)),
+        ],
+        assembly::Instruction::Binary { op, t, src, dst } => vec![
+            (
+                "op",
+                json::str(match op {
+                    assembly::BinaryOperator::Add => "add",
+                    assembly::BinaryOperator::Sub => "sub",
+                    assembly::BinaryOperator::Mult => "imul",
+                    assembly::BinaryOperator::Xor => "xor",
+                    assembly::BinaryOperator::Shl => "sal",
+                }),
+            ),
+            ("type", asm_type_name(t)),
+            ("src", operand_to_json(src)),
+            ("dst", operand_to_json(dst)),
+        ],
+        assembly::Instruction::Cmp(t, src, dst) => vec![
+            ("op", json::str("cmp")),
+            ("type", asm_type_name(t)),
+            ("src", operand_to_json(src)),
+            ("dst", operand_to_json(dst)),
+        ],
+        assembly::Instruction::Test(t, src, dst) => vec![
+            ("op", json::str("test")),
+            ("type", asm_type_name(t)),
+            ("src", operand_to_json(src)),
+            ("dst", operand_to_json(dst)),
+        ],
+        assembly::Instruction::Idiv(t, operand) => vec![
+            ("op", json::str("idiv")),
+            ("type", asm_type_name(t)),
+            ("src", operand_to_json(operand)),
+        ],
+        assembly::Instruction::Cdq(t) => vec![("op", json::str("cdq")), ("type", asm_type_name(t))],
+        assembly::Instruction::Jmp(target) => {
+            vec![("op", json::str("jmp")), ("target", json::str(target))]
+        }
+        assembly::Instruction::JmpCC(cc, target) => vec![
+            ("op", json::str("jcc")),
+            ("cc", cond_code_name(cc)),
+            ("target", json::str(target)),
+        ],
+        assembly::Instruction::SetCC(cc, operand) => vec![
+            ("op", json::str("setcc")),
+            ("cc", cond_code_name(cc)),
+            ("dst", operand_to_json(operand)),
+        ],
+        assembly::Instruction::Label(l) => vec![("op", json::str("label")), ("name", json::str(l))],
+        assembly::Instruction::AllocateStack(bytes) => vec![
+            ("op", json::str("allocate_stack")),
+            ("bytes", json::T::Int(*bytes)),
+        ],
+        assembly::Instruction::DeallocateStack(bytes) => vec![
+            ("op", json::str("deallocate_stack")),
+            ("bytes", json::T::Int(*bytes)),
+        ],
+        assembly::Instruction::Push(operand) => {
+            vec![("op", json::str("push")), ("src", operand_to_json(operand))]
+        }
+        assembly::Instruction::Pop(r) => {
+            vec![("op", json::str("pop")), ("reg", json::str(reg_name(r)))]
+        }
+        assembly::Instruction::Call(f) => vec![("op", json::str("call")), ("f", json::str(f))],
+        assembly::Instruction::Ret => vec![("op", json::str("ret"))],
+    };
+    json::object(fields)
+}
+
+fn assembly_symbols_to_json() -> json::T {
+    let mut bindings = assembly_symbols::bindings();
+    bindings.sort_by(|a, b| a.0.cmp(&b.0));
+    let entries = bindings
+        .iter()
+        .map(|(name, entry)| match entry {
+            assembly_symbols::Entry::Fun {
+                defined,
+                bytes_required,
+                callee_saved_regs,
+            } => json::object(vec![
+                ("name", json::str(name)),
+                ("kind", json::str("function")),
+                ("defined", json::T::Bool(*defined)),
+                ("bytes_required", json::T::Int(*bytes_required)),
+                (
+                    "callee_saved_regs",
+                    json::T::Array(
+                        callee_saved_regs
+                            .iter()
+                            .map(|r| json::str(reg_name(r)))
+                            .collect(),
+                    ),
+                ),
+            ]),
+            assembly_symbols::Entry::Obj { t, is_static } => json::object(vec![
+                ("name", json::str(name)),
+                ("kind", json::str("object")),
+                ("type", asm_type_name(t)),
+                ("is_static", json::T::Bool(*is_static)),
+            ]),
+        })
+        .collect();
+    json::T::Array(entries)
+}
+
+pub fn asm(program: &assembly::T) -> json::T {
+    let top_levels = match program {
+        assembly::T::Program(tls) => tls
+            .iter()
+            .map(|tl| match tl {
+                assembly::TopLevel::Function {
+                    name,
+                    global,
+                    instructions,
+                } => json::object(vec![
+                    ("kind", json::str("function")),
+                    ("name", json::str(name)),
+                    ("global", json::T::Bool(*global)),
+                    (
+                        "instructions",
+                        json::T::Array(instructions.iter().map(asm_instruction_to_json).collect()),
+                    ),
+                ]),
+                assembly::TopLevel::StaticVariable {
+                    name,
+                    alignment,
+                    global,
+                    init,
+                } => json::object(vec![
+                    ("kind", json::str("static_variable")),
+                    ("name", json::str(name)),
+                    ("alignment", json::T::Int(*alignment)),
+                    ("global", json::T::Bool(*global)),
+                    ("init", static_init_to_json(init)),
+                ]),
+            })
+            .collect(),
+    };
+    document(
+        "asm",
+        vec![
+            ("top_levels", json::T::Array(top_levels)),
+            ("symbols", symbols_to_json()),
+            ("assembly_symbols", assembly_symbols_to_json()),
+        ],
+    )
+}
+
+#[test]
+fn test_token_positions() {
+    let source = "int x;\n  x = 1L;";
+    let tokens = crate::lexer::Lexer::new(source.as_bytes()).lex_with_positions();
+    match self::tokens(&tokens, source) {
+        json::T::Object(fields) => {
+            assert_eq!(
+                fields[0],
+                ("schema_version".to_string(), json::T::Int(SCHEMA_VERSION))
+            );
+            assert_eq!(
+                fields[2].1.to_string(),
+                "[{\"kind\":\"keyword\",\"text\":\"int\",\"offset\":0,\"line\":1,\"column\":1},\
+                {\"kind\":\"identifier\",\"text\":\"x\",\"offset\":4,\"line\":1,\"column\":5},\
+                {\"kind\":\"punctuator\",\"text\":\";\",\"offset\":5,\"line\":1,\"column\":6},\
+                {\"kind\":\"identifier\",\"text\":\"x\",\"offset\":9,\"line\":2,\"column\":3},\
+                {\"kind\":\"punctuator\",\"text\":\"=\",\"offset\":11,\"line\":2,\"column\":5},\
+                {\"kind\":\"constant\",\"text\":\"1L\",\"offset\":13,\"line\":2,\"column\":7},\
+                {\"kind\":\"punctuator\",\"text\":\";\",\"offset\":15,\"line\":2,\"column\":9},\
+                {\"kind\":\"eof\",\"text\":\"\",\"offset\":16,\"line\":2,\"column\":10}]"
+            );
+        }
+        other => panic!("内部错误：{:?}", other),
+    }
+}
+
+#[test]
+fn test_ast_dump() {
+    fn fields(value: json::T) -> Vec<(String, json::T)> {
+        match value {
+            json::T::Object(fields) => fields,
+            other => panic!("内部错误：{:?}", other),
+        }
+    }
+
+    let tokens = crate::lexer::Lexer::new("int json_dump_x = 3L;".as_bytes()).lex();
+    let program = crate::parser::Parser::new(tokens).parse();
+    let resolved = crate::identifier_resolution::resolve(program);
+    let labeled = crate::label_loops::label_loops(resolved);
+    let typed = crate::typecheck::typecheck(labeled);
+    let doc = fields(ast(&typed));
+    let keys: Vec<&str> = doc.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(
+        keys,
+        vec!["schema_version", "stage", "declarations", "symbols"]
+    );
+    assert_eq!(doc[0].1, json::T::Int(SCHEMA_VERSION));
+    assert_eq!(doc[1].1, json::str("ast"));
+    // 初始化表达式已经转换成变量的类型
+    assert_eq!(
+        doc[2].1.to_string(),
+        "[{\"kind\":\"var_decl\",\"name\":\"json_dump_x\",\"type\":\"int\",\
+        \"init\":{\"kind\":\"cast\",\"target_type\":\"int\",\
+        \"e\":{\"kind\":\"constant\",\"value\":{\"type\":\"long\",\"value\":3},\"type\":\"long\"},\
+        \"type\":\"int\"},\"storage_class\":null}]"
+    );
+    // 符号表是全局的，别的测试的符号也在里面，只检查这个程序自己的
+    match &doc[3].1 {
+        json::T::Array(entries) => assert!(entries.iter().any(|entry| entry.to_string()
+            == "{\"name\":\"json_dump_x\",\"type\":\"int\",\"kind\":\"static\",\
+            \"global\":true,\"init\":{\"type\":\"int\",\"value\":3}}")),
+        other => panic!("内部错误：{:?}", other),
+    }
+}
+
+#[test]
+fn test_ir_dump() {
+    fn fields(value: json::T) -> Vec<(String, json::T)> {
+        match value {
+            json::T::Object(fields) => fields,
+            other => panic!("内部错误：{:?}", other),
+        }
+    }
+
+    let program = crate::ir_parser::parse(
+        "\
+static json_dump.y: long = 5
+function global json_dump.g(json_dump.a: int) -> int {
+    var json_dump.b: int
+    json_dump.b = add json_dump.a, 1:int
+    return json_dump.b
+}",
+    )
+    .unwrap();
+    let doc = fields(ir(&program));
+    let keys: Vec<&str> = doc.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(
+        keys,
+        vec!["schema_version", "stage", "top_levels", "symbols"]
+    );
+    assert_eq!(doc[0].1, json::T::Int(SCHEMA_VERSION));
+    assert_eq!(doc[1].1, json::str("ir"));
+    match &doc[2].1 {
+        json::T::Array(top_levels) => {
+            assert_eq!(
+                top_levels[0].to_string(),
+                "{\"kind\":\"static_variable\",\"name\":\"json_dump.y\",\"type\":\"long\",\
+                \"global\":false,\"init\":{\"type\":\"long\",\"value\":5}}"
+            );
+            assert_eq!(
+                top_levels[1].to_string(),
+                "{\"kind\":\"function\",\"name\":\"json_dump.g\",\"global\":true,\
+                \"params\":[\"json_dump.a\"],\"body\":[\
+                {\"op\":\"add\",\"src1\":{\"kind\":\"var\",\"name\":\"json_dump.a\"},\
+                \"src2\":{\"kind\":\"constant\",\"value\":{\"type\":\"int\",\"value\":1}},\
+                \"dst\":{\"kind\":\"var\",\"name\":\"json_dump.b\"}},\
+                {\"op\":\"return\",\"src\":{\"kind\":\"var\",\"name\":\"json_dump.b\"}}]}"
+            );
+        }
+        other => panic!("内部错误：{:?}", other),
+    }
+    match &doc[3].1 {
+        json::T::Array(entries) => assert!(entries.iter().any(|entry| entry.to_string()
+            == "{\"name\":\"json_dump.b\",\"type\":\"int\",\"kind\":\"local\"}")),
+        other => panic!("内部错误：{:?}", other),
+    }
+}
+
+#[test]
+fn test_asm_dump() {
+    fn fields(value: json::T) -> Vec<(String, json::T)> {
+        match value {
+            json::T::Object(fields) => fields,
+            other => panic!("内部错误：{:?}", other),
+        }
+    }
+
+    assembly_symbols::add_fun("json_dump.h".to_string(), true);
+    let program = assembly::T::Program(vec![assembly::TopLevel::Function {
+        name: "json_dump.h".to_string(),
+        global: true,
+        instructions: vec![
+            assembly::Instruction::Mov(
+                assembly::AsmType::Longword,
+                assembly::Operand::Stack(-4),
+                assembly::Operand::Reg(assembly::Reg::AX),
+            ),
+            assembly::Instruction::Ret,
+        ],
+    }]);
+    let doc = fields(asm(&program));
+    let keys: Vec<&str> = doc.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "schema_version",
+            "stage",
+            "top_levels",
+            "symbols",
+            "assembly_symbols"
+        ]
+    );
+    assert_eq!(doc[0].1, json::T::Int(SCHEMA_VERSION));
+    assert_eq!(doc[1].1, json::str("asm"));
+    assert_eq!(
+        doc[2].1.to_string(),
+        "[{\"kind\":\"function\",\"name\":\"json_dump.h\",\"global\":true,\"instructions\":[\
+        {\"op\":\"mov\",\"type\":\"longword\",\"src\":{\"kind\":\"stack\",\"offset\":-4},\
+        \"dst\":{\"kind\":\"reg\",\"reg\":\"ax\"}},{\"op\":\"ret\"}]}]"
+    );
+    match &doc[4].1 {
+        json::T::Array(entries) => assert!(entries.iter().any(|entry| entry.to_string()
+            == "{\"name\":\"json_dump.h\",\"kind\":\"function\",\"defined\":true,\
+            \"bytes_required\":0,\"callee_saved_regs\":[]}")),
+        other => panic!("内部错误：{:?}", other),
+    }
+}
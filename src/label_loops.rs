@@ -2,8 +2,8 @@ use crate::{ast, unique_ids};
 
 fn label_statement(
     current_label: Option<String>,
-    statement: ast::Statement<ast::Exp>,
-) -> ast::Statement<ast::Exp> {
+    statement: ast::Statement<ast::UnTypedExp>,
+) -> ast::Statement<ast::UnTypedExp> {
     match statement {
         ast::Statement::Break(_) => match current_label {
             Some(l) => ast::Statement::Break(l),
@@ -72,15 +72,18 @@ fn label_statement(
 
 fn label_block_item(
     current_label: Option<String>,
-    block_item: ast::BlockItem<ast::Exp>,
-) -> ast::BlockItem<ast::Exp> {
+    block_item: ast::BlockItem<ast::UnTypedExp>,
+) -> ast::BlockItem<ast::UnTypedExp> {
     match block_item {
         ast::BlockItem::S(s) => ast::BlockItem::S(label_statement(current_label, s)),
         decl => decl,
     }
 }
 
-fn label_block(current_label: Option<String>, b: ast::Block<ast::Exp>) -> ast::Block<ast::Exp> {
+fn label_block(
+    current_label: Option<String>,
+    b: ast::Block<ast::UnTypedExp>,
+) -> ast::Block<ast::UnTypedExp> {
     match b {
         ast::Block::Block(items) => {
             let mut block_items = vec![];
@@ -92,7 +95,7 @@ fn label_block(current_label: Option<String>, b: ast::Block<ast::Exp>) -> ast::B
     }
 }
 
-fn label_decl(f: ast::Declaration<ast::Exp>) -> ast::Declaration<ast::Exp> {
+fn label_decl(f: ast::Declaration<ast::UnTypedExp>) -> ast::Declaration<ast::UnTypedExp> {
     match f {
         ast::Declaration::FunDecl(fd) => ast::Declaration::FunDecl(ast::FunctionDeclaration {
             name: fd.name,
@@ -103,6 +106,7 @@ fn label_decl(f: ast::Declaration<ast::Exp>) -> ast::Declaration<ast::Exp> {
                 None => None,
             },
             storage_class: fd.storage_class,
+            inline: fd.inline,
         }),
         var_decl => var_decl,
     }
@@ -12,6 +12,8 @@ pub struct Lexer<R: Read> {
 }
 
 impl<R: Read> Lexer<R> {
+    // 调用者传进来的都是内存里的&[u8]，逐字节读不会有系统调用
+    #[allow(clippy::unbuffered_bytes)]
     pub fn new(reader: R) -> Self {
         Lexer {
             bytes_iter: reader.bytes().peekable(),
@@ -60,6 +62,10 @@ impl<R: Read> Lexer<R> {
             "void" => tokens::Token::KWVoid,
             "int" => tokens::Token::KWInt,
             "long" => tokens::Token::KWLong,
+            "short" => tokens::Token::KWShort,
+            "signed" => tokens::Token::KWSigned,
+            "unsigned" => tokens::Token::KWUnsigned,
+            "_Bool" => tokens::Token::KWBool,
             "return" => tokens::Token::KWReturn,
             "if" => tokens::Token::KWIf,
             "else" => tokens::Token::KWElse,
@@ -70,6 +76,9 @@ impl<R: Read> Lexer<R> {
             "continue" => tokens::Token::KWContinue,
             "extern" => tokens::Token::KWExtern,
             "static" => tokens::Token::KWStatic,
+            "inline" => tokens::Token::KWInline,
+            "sizeof" => tokens::Token::KWSizeof,
+            "_Static_assert" => tokens::Token::KWStaticAssert,
             _ => tokens::Token::Identifier(buffer),
         };
         token
@@ -103,11 +112,47 @@ impl<R: Read> Lexer<R> {
         }
     }
 
+    fn string_literal(&mut self) -> tokens::Token {
+        self.save_start();
+        self.advance(); // 吃掉开头的引号
+        let mut buffer = String::new();
+        loop {
+            match self.current_char() {
+                Some(b'"') => {
+                    self.advance();
+                    break;
+                }
+                Some(b'\\') => {
+                    self.advance();
+                    let escaped = match self.current_char() {
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'\\') => '\\',
+                        Some(b'"') => '"',
+                        Some(b'\'') => '\'',
+                        Some(b'0') => '\0',
+                        other => panic!("不支持的转义字符：{:?}", other.map(|c| c as char)),
+                    };
+                    buffer.push(escaped);
+                    self.advance();
+                }
+                Some(b'\n') | None => panic!("字符串字面量没有结束。"),
+                Some(c) => {
+                    buffer.push(c as char);
+                    self.advance();
+                }
+            }
+        }
+        tokens::Token::StringLiteral(buffer)
+    }
+
     pub fn get_one_token(&mut self) -> tokens::Token {
+        self.save_start();
         if let Some(&Ok(ch)) = self.bytes_iter.peek() {
-            return match ch {
+            match ch {
                 b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.identifier(),
                 b'0'..=b'9' => self.integer(),
+                b'"' => self.string_literal(),
                 b' ' | b'\n' | b'\t' => {
                     self.advance();
                     self.get_one_token()
@@ -228,23 +273,31 @@ impl<R: Read> Lexer<R> {
                     }
                 }
                 _ => unreachable!(),
-            };
+            }
         } else {
             tokens::Token::Eof
         }
     }
 
-    pub fn lex(&mut self) -> Vec<tokens::Token> {
+    /// 每个记号和它第一个字节在源文件中的偏移。
+    pub fn lex_with_positions(&mut self) -> Vec<(tokens::Token, u64)> {
         let mut tokens = vec![];
         loop {
             let token = self.get_one_token();
-            tokens.push(token.clone());
+            tokens.push((token.clone(), self.saved_pos));
             if token == tokens::Token::Eof {
                 break;
             }
         }
         tokens
     }
+
+    pub fn lex(&mut self) -> Vec<tokens::Token> {
+        self.lex_with_positions()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect()
+    }
 }
 
 #[test]
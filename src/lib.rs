@@ -0,0 +1,377 @@
+// 这些lint和仓库一贯的写法冲突：结构体初始化写全`name: name`，用match而不是matches!，
+// 参数写成&Vec和&String，单分支的match里再套if。
+#![allow(
+    clippy::redundant_field_names,
+    clippy::match_like_matches_macro,
+    clippy::single_match,
+    clippy::collapsible_match,
+    clippy::manual_map,
+    clippy::ptr_arg,
+    clippy::unnecessary_cast,
+    clippy::if_same_then_else
+)]
+
+pub mod assembly;
+pub mod assembly_symbols;
+pub mod assembly_verifier;
+pub mod ast;
+pub mod ast_interpreter;
+pub mod c_printer;
+pub mod cfg;
+pub mod codegen;
+pub mod const_convert;
+pub mod const_eval;
+pub mod constant_folding;
+pub mod constants;
+pub mod copy_propagation;
+pub mod dead_store_elimination;
+pub mod emit;
+pub mod identifier_resolution;
+pub mod initializers;
+pub mod instruction_fixup;
+pub mod ir;
+pub mod ir_gen;
+pub mod ir_interpreter;
+pub mod ir_parser;
+pub mod ir_verifier;
+pub mod json;
+pub mod json_dump;
+pub mod label_loops;
+pub mod lexer;
+pub mod optimize;
+pub mod parser;
+pub mod peephole;
+pub mod regalloc;
+pub mod replace_pseudos;
+pub mod rounding;
+pub mod symbols;
+pub mod tokens;
+pub mod type_utils;
+pub mod typecheck;
+pub mod types;
+pub mod unique_ids;
+pub mod unreachable_code;
+pub mod warnings;
+
+use std::{
+    cell::Cell,
+    fmt::Display,
+    path::PathBuf,
+    sync::{Mutex, Once},
+};
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    // 符号表是全局的，同一时间只能有一次编译在进行
+    static ref COMPILER_LOCK: Mutex<()> = Mutex::new(());
+}
+
+thread_local! {
+    // 当前线程正在Compiler::run里编译，这时的panic会转换成Diagnostic
+    static COMPILING: Cell<bool> = const { Cell::new(false) };
+}
+
+static INSTALL_PANIC_HOOK: Once = Once::new();
+
+/// panic钩子是进程全局的，只在第一次编译时包装一次原来的钩子：
+/// 编译线程里的panic已经作为Diagnostic返回，不再打印，其他线程的panic照常交给原来的钩子。
+fn install_panic_hook() {
+    INSTALL_PANIC_HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !COMPILING.with(|compiling| compiling.get()) {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// 出错时编译器正在执行的阶段。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Lex,
+    Parse,
+    Resolve,
+    Typecheck,
+    IrGen,
+    Optimize,
+    Codegen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub stage: Stage,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "错误",
+            Severity::Warning => "警告",
+        };
+        write!(f, "{}（{:?}）：{}", severity, self.stage, self.message)
+    }
+}
+
+/// 编译成功时的结果，以及编译过程中产生的警告。
+#[derive(Clone, Debug)]
+pub struct Output<T> {
+    pub value: T,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// 生成代码的目标平台。目前只支持x86-64 Linux：System V调用约定、GNU as语法。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    X86_64SysV,
+}
+
+/// 在进程内调用编译器。各个阶段遇到错误时直接panic，这里把panic转换成`Diagnostic`，
+/// 所以一次编译最多报告一个前端错误；汇编代码验证失败时会报告所有不合法的指令。
+/// 警告不会打断编译：成功时放在`Output`里，失败时排在错误前面一起返回。
+/// 符号表是全局的，每次编译前清空，编译结束后保留最近一次编译的内容。
+///
+/// 编译器没有预处理器，`#include`和宏要先交给`gcc -E -P`处理。头文件搜索路径只是记录下来，
+/// 由调用者在预处理时传给`gcc -I`。
+///
+/// ```ignore
+/// let asm = wacc::Compiler::new().optimize_level(2).compile_to_asm(source)?.value;
+/// ```
+pub struct Compiler {
+    optimize_options: optimize::Options,
+    verify: bool,
+    include_paths: Vec<PathBuf>,
+    target: Target,
+    ir_hook: Option<IrHook>,
+    asm_hook: Option<AsmHook>,
+}
+
+type IrHook = Box<dyn Fn(&ir::T)>;
+type AsmHook = Box<dyn Fn(&assembly::T)>;
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            optimize_options: optimize::Options::new(),
+            verify: true,
+            include_paths: vec![],
+            target: Target::X86_64SysV,
+            ir_hook: None,
+            asm_hook: None,
+        }
+    }
+
+    /// 和命令行的`-O0`、`-O1`、`-O2`一样。
+    pub fn optimize_level(mut self, level: u8) -> Self {
+        self.optimize_options.set_level(level);
+        self
+    }
+
+    pub fn optimize_options(mut self, options: optimize::Options) -> Self {
+        self.optimize_options = options;
+        self
+    }
+
+    /// 是否在代码生成之后验证汇编代码，默认打开。
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// 追加一个头文件搜索路径，按添加的顺序搜索。
+    pub fn include_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.include_paths.push(path.into());
+        self
+    }
+
+    pub fn include_paths(&self) -> &[PathBuf] {
+        &self.include_paths
+    }
+
+    pub fn target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn get_target(&self) -> Target {
+        self.target
+    }
+
+    /// 优化之后的IR，命令行用它把每个函数的控制流图写成dot文件。
+    pub fn inspect_ir(mut self, hook: impl Fn(&ir::T) + 'static) -> Self {
+        self.ir_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// 窥孔优化之后、输出之前的汇编代码。
+    pub fn inspect_asm(mut self, hook: impl Fn(&assembly::T) + 'static) -> Self {
+        self.asm_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// 清空上一次编译留下的全局状态，执行`f`并捕获其中的panic。
+    fn run<T>(
+        &self,
+        f: impl FnOnce(&mut Stage) -> Result<T, Vec<Diagnostic>>,
+    ) -> Result<Output<T>, Vec<Diagnostic>> {
+        let _guard = COMPILER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        symbols::clear();
+        assembly_symbols::clear();
+        warnings::clear();
+        let mut stage = Stage::Lex;
+        install_panic_hook();
+        let was_compiling = COMPILING.with(|compiling| compiling.replace(true));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(&mut stage)));
+        COMPILING.with(|compiling| compiling.set(was_compiling));
+        let mut diagnostics = warnings::take();
+        match result {
+            Ok(Ok(value)) => Ok(Output {
+                value: value,
+                warnings: diagnostics,
+            }),
+            Ok(Err(mut errors)) => {
+                diagnostics.append(&mut errors);
+                Err(diagnostics)
+            }
+            Err(payload) => {
+                let message = if let Some(s) = payload.downcast_ref::<&str>() {
+                    s.to_string()
+                } else if let Some(s) = payload.downcast_ref::<String>() {
+                    s.clone()
+                } else {
+                    "未知错误".to_string()
+                };
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    stage: stage,
+                    message: message,
+                });
+                Err(diagnostics)
+            }
+        }
+    }
+
+    fn parse_source(source: &str, stage: &mut Stage) -> ast::ProgType<ast::UnTypedExp> {
+        *stage = Stage::Lex;
+        let tokens = lexer::Lexer::new(source.as_bytes()).lex();
+        *stage = Stage::Parse;
+        parser::Parser::new(tokens).parse()
+    }
+
+    fn typecheck_source(source: &str, stage: &mut Stage) -> ast::ProgType<ast::TypedExp> {
+        let ast = Compiler::parse_source(source, stage);
+        *stage = Stage::Resolve;
+        let resolved_ast = identifier_resolution::resolve(ast);
+        let validated_ast = label_loops::label_loops(resolved_ast);
+        *stage = Stage::Typecheck;
+        typecheck::typecheck(validated_ast)
+    }
+
+    fn lower_source(&self, source: &str, stage: &mut Stage) -> ir::T {
+        let typed_ast = Compiler::typecheck_source(source, stage);
+        *stage = Stage::IrGen;
+        let ir = ir_gen::gen(typed_ast);
+        ir_verifier::check(&ir, "ir_gen");
+        *stage = Stage::Optimize;
+        let ir = optimize::optimize(ir, &self.optimize_options);
+        if let Some(hook) = &self.ir_hook {
+            hook(&ir);
+        }
+        ir
+    }
+
+    fn verify_assembly(
+        &self,
+        program: &assembly::T,
+        stage: assembly_verifier::Stage,
+    ) -> Result<(), Vec<Diagnostic>> {
+        if !self.verify {
+            return Ok(());
+        }
+        assembly_verifier::verify(program, stage).map_err(|errors| {
+            errors
+                .iter()
+                .map(|e| Diagnostic {
+                    severity: Severity::Error,
+                    stage: Stage::Codegen,
+                    message: format!("{:?}之后：{}", stage, e),
+                })
+                .collect()
+        })
+    }
+
+    /// 每个记号和它在源代码里的字节偏移。
+    pub fn tokens(
+        &self,
+        source: &str,
+    ) -> Result<Output<Vec<(tokens::Token, u64)>>, Vec<Diagnostic>> {
+        self.run(|stage| {
+            *stage = Stage::Lex;
+            Ok(lexer::Lexer::new(source.as_bytes()).lex_with_positions())
+        })
+    }
+
+    pub fn parse(&self, source: &str) -> Result<Output<ast::UntypedProgType>, Vec<Diagnostic>> {
+        self.run(|stage| Ok(Compiler::parse_source(source, stage)))
+    }
+
+    /// 解析、名字解析、给循环加标签，再做类型检查。
+    pub fn typecheck(&self, source: &str) -> Result<Output<ast::TypedProgType>, Vec<Diagnostic>> {
+        self.run(|stage| Ok(Compiler::typecheck_source(source, stage)))
+    }
+
+    /// 生成IR并按照选项做优化。
+    pub fn lower_to_ir(&self, source: &str) -> Result<Output<ir::T>, Vec<Diagnostic>> {
+        self.run(|stage| Ok(self.lower_source(source, stage)))
+    }
+
+    /// 一直编译到窥孔优化，返回最终的汇编代码。
+    pub fn compile(&self, source: &str) -> Result<Output<assembly::T>, Vec<Diagnostic>> {
+        self.run(|stage| {
+            let ir = self.lower_source(source, stage);
+            *stage = Stage::Codegen;
+            let asm = match self.target {
+                Target::X86_64SysV => codegen::gen(ir),
+            };
+            self.verify_assembly(&asm, assembly_verifier::Stage::Codegen)?;
+            let asm = regalloc::allocate_registers(asm);
+            let asm = replace_pseudos::ReplacementState::new().replace_pseudos(asm);
+            self.verify_assembly(&asm, assembly_verifier::Stage::ReplacePseudos)?;
+            let asm = instruction_fixup::fixup_program(asm);
+            self.verify_assembly(&asm, assembly_verifier::Stage::Fixup)?;
+            let asm = peephole::optimize_program(asm);
+            self.verify_assembly(&asm, assembly_verifier::Stage::Peephole)?;
+            if let Some(hook) = &self.asm_hook {
+                hook(&asm);
+            }
+            Ok(asm)
+        })
+    }
+
+    pub fn compile_to_asm(&self, source: &str) -> Result<Output<String>, Vec<Diagnostic>> {
+        self.compile(source).map(|output| Output {
+            value: emit::to_string(output.value),
+            warnings: output.warnings,
+        })
+    }
+}
+
+/// 用默认选项编译，相当于`Compiler::new().compile_to_asm(source)`。
+pub fn compile_to_asm(source: &str) -> Result<Output<String>, Vec<Diagnostic>> {
+    Compiler::new().compile_to_asm(source)
+}
@@ -1,29 +1,13 @@
-mod assembly;
-mod ast;
-mod codegen;
-mod emit;
-mod identifier_resolution;
-mod instruction_fixup;
-mod ir;
-mod ir_gen;
-mod label_loops;
-mod lexer;
-mod parser;
-mod replace_pseudos;
-mod rounding;
-mod symbols;
-mod tokens;
-mod typecheck;
-mod types;
-mod unique_ids;
-mod constants;
-mod assembly_symbols;
-mod initializers;
-mod type_utils;
-mod const_convert;
+#![allow(clippy::redundant_field_names)]
 
-fn main() {
-    let program = "
+use std::io::Write;
+
+use wacc::{
+    assembly, ast_interpreter, c_printer, cfg, copy_propagation, dead_store_elimination, emit, ir,
+    ir_interpreter, json_dump, optimize, Compiler, Diagnostic, Output,
+};
+
+const SAMPLE_PROGRAM: &str = "
     int a = 3;
     int b = 4;
     
@@ -31,31 +15,233 @@ fn main() {
         return a * b;
     }
     ";
-    let mut lexer = lexer::Lexer::new(program.as_bytes());
-    let tokens = lexer.lex();
-    println!("{:?}", tokens);
-    let mut parser = parser::Parser::new(tokens);
-    let ast = parser.parse();
-    println!("{:?}", ast);
-    let resolved_ast = identifier_resolution::resolve(ast.clone());
-    println!("resolved_ast: {:?}", resolved_ast);
-    let validated_ast = label_loops::label_loops(resolved_ast);
-    println!("validated_ast: {:?}", validated_ast);
-    typecheck::typecheck(validated_ast.clone());
-    let ir = ir_gen::gen(validated_ast);
-    println!("{:?}", ir);
-    println!("{}", ir);
-    let asm_ast = codegen::gen(ir);
-    println!("================= asm_ast =====================\r\n");
-    emit::emit(asm_ast.clone());
-    println!("================= asm_ast =====================\r\n");
-    let mut replacement_state = replace_pseudos::ReplacementState::new();
-    let asm_ast1 = replacement_state.replace_pseudos(asm_ast);
-    // let symbol_table = symbol_table.clone();
-    println!("================= asm_ast1 =====================\r\n");
-    emit::emit(asm_ast1.clone());
-    println!("================= asm_ast1 =====================\r\n");
-    // println!("symbol_table: {:?}", symbol_table);
-    let asm_ast2 = instruction_fixup::fixup_program(asm_ast1);
-    emit::emit(asm_ast2);
+
+enum DumpFormat {
+    C,
+    Json,
+}
+
+// 每个函数的控制流图画成一个dot文件，IR的图可以标注活跃变量或者到达的Copy
+#[derive(Clone, Copy)]
+enum CfgDot {
+    Ir,
+    Liveness,
+    Copies,
+    Asm,
+}
+
+struct Args {
+    source_file: Option<String>,
+    optimize_options: optimize::Options,
+    // 把某个阶段的结果打印到stdout并退出，记号、IR和汇编只支持json格式
+    dump_tokens: bool,
+    dump_ast: Option<DumpFormat>,
+    dump_ir: bool,
+    dump_asm: bool,
+    c_printer_options: c_printer::Options,
+    emit_cfg_dot: Option<CfgDot>,
+    // 不生成IR，直接解释执行类型检查之后的AST
+    run: bool,
+    // 不生成汇编，直接解释执行优化之后的IR
+    run_ir: bool,
+}
+
+/// 解析命令行：`wacc [-O0|-O1|-O2] [--fold-constants] [--propagate-copies]
+/// [--eliminate-unreachable-code] [--eliminate-dead-stores] [--optimization-stats]
+/// [--dump-tokens=json] [--dump-ast=c|json] [--dump-ir=json] [--dump-asm=json]
+/// [--annotate-types] [--annotate-names] [--emit-cfg-dot[=liveness|copies|asm]]
+/// [--run] [--run-ir] [file.c]`
+fn parse_args(args: Vec<String>) -> Args {
+    let mut options = optimize::Options::new();
+    let mut source_file = None;
+    let mut dump_tokens = false;
+    let mut dump_ast = None;
+    let mut dump_ir = false;
+    let mut dump_asm = false;
+    let mut c_printer_options = c_printer::Options::new();
+    let mut emit_cfg_dot = None;
+    let mut run = false;
+    let mut run_ir = false;
+    for arg in args {
+        match arg.as_str() {
+            "-O0" => options.set_level(0),
+            "-O1" => options.set_level(1),
+            "-O2" => options.set_level(2),
+            "--fold-constants" => options.constant_folding = true,
+            "--propagate-copies" => options.copy_propagation = true,
+            "--eliminate-unreachable-code" => options.unreachable_code_elimination = true,
+            "--eliminate-dead-stores" => options.dead_store_elimination = true,
+            "--optimization-stats" => options.print_stats = true,
+            "--dump-tokens=json" => dump_tokens = true,
+            "--dump-ast=c" => dump_ast = Some(DumpFormat::C),
+            "--dump-ast=json" => dump_ast = Some(DumpFormat::Json),
+            "--dump-ir=json" => dump_ir = true,
+            "--dump-asm=json" => dump_asm = true,
+            "--annotate-types" => c_printer_options.annotate_types = true,
+            "--annotate-names" => c_printer_options.annotate_names = true,
+            "--emit-cfg-dot" => emit_cfg_dot = Some(CfgDot::Ir),
+            "--emit-cfg-dot=liveness" => emit_cfg_dot = Some(CfgDot::Liveness),
+            "--emit-cfg-dot=copies" => emit_cfg_dot = Some(CfgDot::Copies),
+            "--emit-cfg-dot=asm" => emit_cfg_dot = Some(CfgDot::Asm),
+            "--run" => run = true,
+            "--run-ir" => run_ir = true,
+            other if other.starts_with('-') => panic!("未知选项：{}", other),
+            other => source_file = Some(other.to_string()),
+        }
+    }
+    Args {
+        source_file: source_file,
+        optimize_options: options,
+        dump_tokens: dump_tokens,
+        dump_ast: dump_ast,
+        dump_ir: dump_ir,
+        dump_asm: dump_asm,
+        c_printer_options: c_printer_options,
+        emit_cfg_dot: emit_cfg_dot,
+        run: run,
+        run_ir: run_ir,
+    }
+}
+
+/// 解释器的结果：程序的输出写到stdout，退出码就是程序的退出码。
+fn exit_with<E: std::fmt::Display>(result: Result<ir_interpreter::Outcome, E>) -> ! {
+    match result {
+        Ok(outcome) => {
+            let mut stdout = std::io::stdout().lock();
+            if let Err(e) = stdout.write_all(&outcome.output).and_then(|_| stdout.flush()) {
+                eprintln!("无法写出程序的输出：{}", e);
+            }
+            std::process::exit(outcome.exit_code)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        }
+    }
+}
+
+/// `foo.c`里函数`main`的控制流图写到`foo.main.dot`，没有源文件时写到`main.dot`。
+fn write_cfg_dot(source_file: &Option<String>, function: &String, dot: String) {
+    let path = match source_file {
+        Some(path) => std::path::Path::new(path).with_extension(format!("{}.dot", function)),
+        None => std::path::PathBuf::from(format!("{}.dot", function)),
+    };
+    if let Err(e) = std::fs::write(&path, dot) {
+        eprintln!("无法写出{}：{}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+fn write_ir_cfg_dots(source_file: &Option<String>, mode: CfgDot, ir: &ir::T) {
+    let ir::T::Program(tls) = ir;
+    for tl in tls {
+        if let ir::TopLevel::Function {
+            name,
+            global: _,
+            params: _,
+            body,
+        } = tl
+        {
+            let dot = match mode {
+                CfgDot::Liveness => dead_store_elimination::to_graphviz(name, body.clone()),
+                CfgDot::Copies => copy_propagation::to_graphviz(name, body.clone()),
+                CfgDot::Ir => {
+                    let cfg = cfg::instructions_to_cfg(name.clone(), body.clone());
+                    cfg::to_graphviz(&cfg, |i| i.to_string(), |_| "".to_string())
+                }
+                CfgDot::Asm => panic!("内部错误：汇编的控制流图要在生成汇编之后输出。"),
+            };
+            write_cfg_dot(source_file, name, dot);
+        }
+    }
+}
+
+fn write_asm_cfg_dots(source_file: &Option<String>, asm: &assembly::T) {
+    let assembly::T::Program(tls) = asm;
+    for tl in tls {
+        if let assembly::TopLevel::Function {
+            name,
+            global: _,
+            instructions,
+        } = tl
+        {
+            let cfg = cfg::instructions_to_cfg(name.clone(), instructions.clone());
+            let dot = cfg::to_graphviz(&cfg, emit::show_instruction, |_| "".to_string());
+            write_cfg_dot(source_file, name, dot);
+        }
+    }
+}
+
+/// 警告写到stderr，不影响编译结果。
+fn report_warnings<T>(output: Output<T>) -> T {
+    for w in output.warnings.iter() {
+        eprintln!("{}", w);
+    }
+    output.value
+}
+
+/// 按照选项决定编译到哪个阶段，把那个阶段的结果打印出来或者解释执行。
+/// 调试输出都写到stderr，stdout上只有请求的结果。
+fn compile(args: &Args, compiler: &Compiler, program: &str) -> Result<(), Vec<Diagnostic>> {
+    if args.dump_tokens {
+        let tokens = report_warnings(compiler.tokens(program)?);
+        println!("{}", json_dump::tokens(&tokens, program));
+        return Ok(());
+    }
+    if args.dump_ast.is_some() || args.run {
+        let typed_ast = report_warnings(compiler.typecheck(program)?);
+        match args.dump_ast {
+            Some(DumpFormat::C) => print!(
+                "{}",
+                c_printer::to_string(&typed_ast, &args.c_printer_options)
+            ),
+            Some(DumpFormat::Json) => println!("{}", json_dump::ast(&typed_ast)),
+            None => exit_with(ast_interpreter::run(&typed_ast)),
+        }
+        return Ok(());
+    }
+    if args.dump_ir || args.run_ir {
+        let ir = report_warnings(compiler.lower_to_ir(program)?);
+        if args.dump_ir {
+            println!("{}", json_dump::ir(&ir));
+            return Ok(());
+        }
+        exit_with(ir_interpreter::run(&ir));
+    }
+    let asm = report_warnings(compiler.compile(program)?);
+    if args.dump_asm {
+        println!("{}", json_dump::asm(&asm));
+        return Ok(());
+    }
+    if let Err(e) = emit::emit(&mut std::io::stdout().lock(), asm) {
+        eprintln!("无法写出汇编代码：{}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = parse_args(std::env::args().skip(1).collect());
+    let program = match &args.source_file {
+        Some(path) => std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("无法读取文件{}：{}", path, e)),
+        None => SAMPLE_PROGRAM.to_string(),
+    };
+    let mut compiler = Compiler::new().optimize_options(args.optimize_options.clone());
+    let source_file = args.source_file.clone();
+    match args.emit_cfg_dot {
+        Some(CfgDot::Asm) => {
+            compiler = compiler.inspect_asm(move |asm| write_asm_cfg_dots(&source_file, asm))
+        }
+        Some(mode) => {
+            compiler = compiler.inspect_ir(move |ir| write_ir_cfg_dots(&source_file, mode, ir))
+        }
+        None => (),
+    }
+    if let Err(diagnostics) = compile(&args, &compiler, &program) {
+        for d in diagnostics.iter() {
+            eprintln!("{}", d);
+        }
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,231 @@
+use crate::{
+    constant_folding, copy_propagation, dead_store_elimination, ir, ir_verifier, unreachable_code,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub constant_folding: bool,
+    pub copy_propagation: bool,
+    pub unreachable_code_elimination: bool,
+    pub dead_store_elimination: bool,
+    pub print_stats: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Options {
+            constant_folding: false,
+            copy_propagation: false,
+            unreachable_code_elimination: false,
+            dead_store_elimination: false,
+            print_stats: false,
+        }
+    }
+
+    /// -O0不做优化，-O1只做常量折叠和不可达代码消除，-O2打开所有优化。
+    /// 优化等级只会打开pass，不会关掉单独打开的pass，所以和各个开关的先后顺序无关。
+    pub fn set_level(&mut self, level: u8) {
+        self.constant_folding |= level >= 1;
+        self.unreachable_code_elimination |= level >= 1;
+        self.copy_propagation |= level >= 2;
+        self.dead_store_elimination |= level >= 2;
+    }
+}
+
+struct Pass {
+    name: &'static str,
+    run: fn(Vec<ir::Instruction>) -> Vec<ir::Instruction>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassStats {
+    pub name: &'static str,
+    // 这个pass运行的次数
+    pub runs: usize,
+    // 其中真正修改了函数体的次数
+    pub changes: usize,
+    // 累计删掉的指令条数（指令变多时为负数）
+    pub instructions_removed: i64,
+}
+
+fn build_pipeline(options: &Options) -> Vec<Pass> {
+    let mut pipeline = vec![];
+    if options.constant_folding {
+        pipeline.push(Pass {
+            name: "constant-folding",
+            run: constant_folding::optimize,
+        });
+    }
+    if options.unreachable_code_elimination {
+        pipeline.push(Pass {
+            name: "unreachable-code-elimination",
+            run: unreachable_code::optimize,
+        });
+    }
+    if options.copy_propagation {
+        pipeline.push(Pass {
+            name: "copy-propagation",
+            run: copy_propagation::optimize,
+        });
+    }
+    if options.dead_store_elimination {
+        pipeline.push(Pass {
+            name: "dead-store-elimination",
+            run: dead_store_elimination::optimize,
+        });
+    }
+    pipeline
+}
+
+fn optimize_function(
+    name: &String,
+    params: &Vec<String>,
+    body: Vec<ir::Instruction>,
+    pipeline: &Vec<Pass>,
+    stats: &mut Vec<PassStats>,
+) -> Vec<ir::Instruction> {
+    if pipeline.is_empty() {
+        return body;
+    }
+    // 每个pass都可能给其他pass创造新的机会，所以一直运行到函数体不再变化为止
+    let mut current = body;
+    loop {
+        let before = current.clone();
+        for (pass, pass_stats) in pipeline.iter().zip(stats.iter_mut()) {
+            let old_len = current.len() as i64;
+            let optimized = (pass.run)(current.clone());
+            ir_verifier::check_function(name, params, &optimized, pass.name);
+            pass_stats.runs += 1;
+            if optimized != current {
+                pass_stats.changes += 1;
+                pass_stats.instructions_removed += old_len - optimized.len() as i64;
+            }
+            current = optimized;
+        }
+        if current == before {
+            return current;
+        }
+    }
+}
+
+fn print_stats(stats: &Vec<PassStats>) {
+    for s in stats {
+        eprintln!(
+            "{}: 运行{}次，修改{}次，删除{}条指令",
+            s.name, s.runs, s.changes, s.instructions_removed
+        );
+    }
+}
+
+pub fn optimize(program: ir::T, options: &Options) -> ir::T {
+    let (optimized, stats) = optimize_with_stats(program, options);
+    if options.print_stats {
+        print_stats(&stats);
+    }
+    optimized
+}
+
+/// 和`optimize`一样，另外返回每个pass的统计信息，顺序和pass的运行顺序相同。
+pub fn optimize_with_stats(program: ir::T, options: &Options) -> (ir::T, Vec<PassStats>) {
+    let pipeline = build_pipeline(options);
+    let mut stats = vec![];
+    for pass in pipeline.iter() {
+        stats.push(PassStats {
+            name: pass.name,
+            runs: 0,
+            changes: 0,
+            instructions_removed: 0,
+        });
+    }
+    match program {
+        ir::T::Program(top_levels) => {
+            let mut optimized = vec![];
+            for top_level in top_levels {
+                optimized.push(match top_level {
+                    ir::TopLevel::Function {
+                        name,
+                        global,
+                        params,
+                        body,
+                    } => {
+                        let body = optimize_function(&name, &params, body, &pipeline, &mut stats);
+                        ir::TopLevel::Function {
+                            name: name,
+                            global: global,
+                            params: params,
+                            body: body,
+                        }
+                    }
+                    static_var => static_var,
+                });
+            }
+            (ir::T::Program(optimized), stats)
+        }
+    }
+}
+
+#[test]
+fn test_level_only_turns_passes_on() {
+    let mut options = Options::new();
+    options.copy_propagation = true;
+    options.set_level(1);
+    assert!(options.copy_propagation);
+    assert!(options.constant_folding);
+    assert!(options.unreachable_code_elimination);
+    assert!(!options.dead_store_elimination);
+    options.set_level(0);
+    assert!(options.constant_folding);
+}
+
+#[test]
+fn test_pipeline_runs_to_fixed_point() {
+    let program = crate::ir_parser::parse(
+        "\
+function global optimize.main() -> int {
+    var optimize.x: int
+    var optimize.y: int
+    optimize.x = copy 3:int
+    optimize.y = add optimize.x, 4:int
+    jump_if_zero optimize.y, optimize.dead
+    return optimize.y
+optimize.dead:
+    return 0:int
+}
+",
+    )
+    .unwrap();
+    let mut options = Options::new();
+    options.set_level(2);
+    let (optimized, stats_of_run) = optimize_with_stats(program, &options);
+    assert_eq!(
+        optimized.to_string(),
+        "\
+function global optimize.main() -> int {
+    return 7:int
+}
+"
+    );
+    // 常量折叠、复制传播、死存储消除都依赖别的pass先做的修改，
+    // 所以要跑到第4轮才稳定下来：最后一轮什么也没有改
+    let stats = |name, changes, instructions_removed| PassStats {
+        name: name,
+        runs: 4,
+        changes: changes,
+        instructions_removed: instructions_removed,
+    };
+    assert_eq!(
+        stats_of_run,
+        vec![
+            stats("constant-folding", 2, 1),
+            stats("unreachable-code-elimination", 1, 2),
+            stats("copy-propagation", 2, 0),
+            stats("dead-store-elimination", 2, 2),
+        ]
+    );
+}
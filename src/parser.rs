@@ -43,32 +43,46 @@ impl Parser {
         }
     }
 
+    fn is_type_specifier(&self, token: tokens::Token) -> bool {
+        match token {
+            tokens::Token::KWInt
+            | tokens::Token::KWLong
+            | tokens::Token::KWShort
+            | tokens::Token::KWSigned
+            | tokens::Token::KWUnsigned
+            | tokens::Token::KWBool => true,
+            _ => false,
+        }
+    }
+
+    fn is_specifier(&self, token: tokens::Token) -> bool {
+        match token {
+            tokens::Token::KWStatic | tokens::Token::KWExtern | tokens::Token::KWInline => true,
+            other => self.is_type_specifier(other),
+        }
+    }
+
     fn parse_type_specifier_list(&mut self) -> Vec<tokens::Token> {
-        match self.current_token() {
-            tokens::Token::KWInt | tokens::Token::KWLong => {
-                let spec = self.current_token();
-                self.pos += 1;
-                let mut result = vec![spec];
-                result.append(&mut self.parse_type_specifier_list());
-                result
-            }
-            _ => vec![],
+        let spec = self.current_token();
+        if self.is_type_specifier(spec.clone()) {
+            self.pos += 1;
+            let mut result = vec![spec];
+            result.append(&mut self.parse_type_specifier_list());
+            result
+        } else {
+            vec![]
         }
     }
 
     fn parse_specifier_list(&mut self) -> Vec<tokens::Token> {
-        match self.current_token() {
-            tokens::Token::KWInt
-            | tokens::Token::KWLong
-            | tokens::Token::KWStatic
-            | tokens::Token::KWExtern => {
-                let spec = self.current_token();
-                self.pos += 1;
-                let mut result = vec![spec];
-                result.append(&mut self.parse_specifier_list());
-                result
-            }
-            _ => vec![],
+        let spec = self.current_token();
+        if self.is_specifier(spec.clone()) {
+            self.pos += 1;
+            let mut result = vec![spec];
+            result.append(&mut self.parse_specifier_list());
+            result
+        } else {
+            vec![]
         }
     }
 
@@ -80,13 +94,45 @@ impl Parser {
         }
     }
 
+    /// 类型说明符的顺序无关紧要，所以这里统计每种说明符出现的次数。
+    /// `long long`和`long`宽度相同，`unsigned`目前只能修饰`short`。
     fn parse_type(&mut self, token_vec: Vec<tokens::Token>) -> types::Type {
-        match token_vec.as_slice() {
-            [tokens::Token::KWInt] => types::Type::Int,
-            [tokens::Token::KWInt, tokens::Token::KWLong]
-            | [tokens::Token::KWLong, tokens::Token::KWInt]
-            | [tokens::Token::KWLong] => types::Type::Long,
-            _ => panic!("invalid type specifier."),
+        let count = |tok: tokens::Token| token_vec.iter().filter(|t| **t == tok).count();
+        let ints = count(tokens::Token::KWInt);
+        let longs = count(tokens::Token::KWLong);
+        let shorts = count(tokens::Token::KWShort);
+        let signeds = count(tokens::Token::KWSigned);
+        let unsigneds = count(tokens::Token::KWUnsigned);
+        let bools = count(tokens::Token::KWBool);
+        if token_vec.is_empty()
+            || ints > 1
+            || shorts > 1
+            || longs > 2
+            || bools > 1
+            || signeds + unsigneds > 1
+        {
+            panic!("invalid type specifier.");
+        }
+        if bools == 1 {
+            if token_vec.len() != 1 {
+                panic!("invalid type specifier.");
+            }
+            types::Type::Bool
+        } else if shorts == 1 {
+            if longs != 0 {
+                panic!("invalid type specifier.");
+            }
+            if unsigneds == 1 {
+                types::Type::UShort
+            } else {
+                types::Type::Short
+            }
+        } else if unsigneds == 1 {
+            panic!("目前只支持unsigned short类型。");
+        } else if longs > 0 {
+            types::Type::Long
+        } else {
+            types::Type::Int
         }
     }
 
@@ -97,23 +143,20 @@ impl Parser {
         let mut types = vec![];
         let mut storage_classes = vec![];
         for t in specifier_list {
-            if t == tokens::Token::KWInt {
+            if self.is_type_specifier(t.clone()) {
                 types.push(t);
             } else {
                 storage_classes.push(t);
             }
         }
 
-        if types.len() != 1 {
-            panic!("invalid type specifier.");
-        } else {
-            let storage_class = match storage_classes.len() {
-                0 => None,
-                1 => Some(self.parse_storage_class(storage_classes[0].clone())),
-                _ => panic!(),
-            };
-            (types::Type::Int, storage_class)
-        }
+        let typ = self.parse_type(types);
+        let storage_class = match storage_classes.len() {
+            0 => None,
+            1 => Some(self.parse_storage_class(storage_classes[0].clone())),
+            _ => panic!("invalid storage class."),
+        };
+        (typ, storage_class)
     }
 
     fn parse_id(&mut self) -> String {
@@ -179,7 +222,8 @@ impl Parser {
         }
     }
 
-    /// <factor> ::= <int> | <identifier> <unop> <factor> | "(" <exp> ")"
+    /// <factor> ::= <int> | <identifier> <unop> <factor> | "(" { <type-specifier> }+ ")" <factor> | "(" <exp> ")"
+    ///            | "sizeof" <factor> | "sizeof" "(" { <type-specifier> }+ ")"
     fn parse_factor(&mut self) -> ast::UnTypedExp {
         match self.current_token() {
             tokens::Token::ConstInt(_) | tokens::Token::ConstLong(_) => self.parse_constant(),
@@ -193,11 +237,40 @@ impl Parser {
                     _ => ast::UnTypedExp::Var(id),
                 }
             }
+            tokens::Token::KWSizeof => {
+                self.eat_token(tokens::Token::KWSizeof);
+                match self.current_token() {
+                    tokens::Token::OpenParen
+                        if self.is_type_specifier(self.tokens[self.pos + 1].clone()) =>
+                    {
+                        self.eat_token(tokens::Token::OpenParen);
+                        let specifiers = self.parse_type_specifier_list();
+                        let t = self.parse_type(specifiers);
+                        self.eat_token(tokens::Token::CloseParen);
+                        ast::UnTypedExp::SizeOfT(t)
+                    }
+                    _ => {
+                        let inner_exp = self.parse_factor();
+                        ast::UnTypedExp::SizeOf(Box::new(inner_exp))
+                    }
+                }
+            }
             tokens::Token::Hyphen | tokens::Token::Tilde | tokens::Token::Bang => {
                 let operator = self.parse_unop();
                 let inner_exp = self.parse_factor();
                 ast::UnTypedExp::Unary(operator, Box::new(inner_exp))
             }
+            tokens::Token::OpenParen if self.is_type_specifier(self.tokens[self.pos + 1].clone()) => {
+                self.eat_token(tokens::Token::OpenParen); // 吃掉"("
+                let specifiers = self.parse_type_specifier_list();
+                let target_type = self.parse_type(specifiers);
+                self.eat_token(tokens::Token::CloseParen); // 吃掉")"
+                let inner_exp = self.parse_factor();
+                ast::UnTypedExp::Cast {
+                    target_type: target_type,
+                    e: Box::new(inner_exp),
+                }
+            }
             tokens::Token::OpenParen => {
                 self.eat_token(tokens::Token::OpenParen); // 吃掉"(""
                 let e = self.parse_expression(0);
@@ -411,7 +484,8 @@ impl Parser {
     /// <block-item> ::= <statement> | <declaration>
     fn parse_block_item(&mut self) -> ast::BlockItem<ast::UnTypedExp> {
         match self.current_token() {
-            tokens::Token::KWInt | tokens::Token::KWStatic | tokens::Token::KWExtern => {
+            tokens::Token::KWStaticAssert => ast::BlockItem::D(self.parse_declaration()),
+            t if self.is_specifier(t.clone()) => {
                 ast::BlockItem::D(self.parse_declaration())
             }
             _ => ast::BlockItem::S(self.parse_statement()),
@@ -440,27 +514,47 @@ impl Parser {
         ast::Block::Block(block_items)
     }
 
+    /// "(" [ "void" | <param-list> | <identifier-list> ] ")" ( <block> | ";" )
     fn finish_parsing_function_declaration(
         &mut self,
         ret_type: types::Type,
         storage_class: Option<ast::StorageClass>,
+        inline: bool,
         name: String,
     ) -> ast::FunctionDeclaration<ast::UnTypedExp> {
         self.eat_token(tokens::Token::OpenParen);
-        let params_with_types = match self.current_token() {
+        let (params_with_types, has_prototype) = match self.current_token() {
             tokens::Token::KWVoid => {
                 self.eat_token(tokens::Token::KWVoid);
-                vec![]
+                self.eat_token(tokens::Token::CloseParen);
+                (vec![], true)
+            }
+            // `int f();`没有原型，参数个数和类型都未知
+            tokens::Token::CloseParen => {
+                self.eat_token(tokens::Token::CloseParen);
+                (vec![], false)
+            }
+            // 旧式（K&R）函数定义：`int f(a, b) long b; { ... }`
+            tokens::Token::Identifier(_) => {
+                let ids = self.parse_identifier_list();
+                self.eat_token(tokens::Token::CloseParen);
+                if self.current_token() == tokens::Token::Semicolon {
+                    panic!("标识符列表只能出现在函数定义中。");
+                }
+                (self.parse_old_style_param_declarations(ids), false)
+            }
+            _ => {
+                let params = self.parse_param_list();
+                self.eat_token(tokens::Token::CloseParen);
+                (params, true)
             }
-            _ => self.parse_param_list(),
         };
         let mut param_types = vec![];
         let mut params = vec![];
         for p in params_with_types {
-            param_types.push(p.0);
+            param_types.push(Box::new(p.0));
             params.push(p.1);
         }
-        self.eat_token(tokens::Token::CloseParen);
         let body = match self.current_token() {
             tokens::Token::OpenBrace => Some(self.parse_block()),
             tokens::Token::Semicolon => {
@@ -471,7 +565,8 @@ impl Parser {
         };
         let fun_type = types::Type::FunType {
             param_types: param_types,
-            ret_type: ret_type,
+            ret_type: Box::new(ret_type),
+            has_prototype: has_prototype,
         };
         ast::FunctionDeclaration {
             name: name,
@@ -479,6 +574,7 @@ impl Parser {
             params: params,
             body: body,
             storage_class: storage_class,
+            inline: inline,
         }
     }
 
@@ -498,6 +594,49 @@ impl Parser {
         }
     }
 
+    /// <identifier-list> ::= <identifier> { "," <identifier> }
+    fn parse_identifier_list(&mut self) -> Vec<String> {
+        let id = self.parse_id();
+        match self.current_token() {
+            tokens::Token::Comma => {
+                self.eat_token(tokens::Token::Comma);
+                let mut result = vec![id];
+                result.append(&mut self.parse_identifier_list());
+                result
+            }
+            _ => vec![id],
+        }
+    }
+
+    /// 旧式函数定义的参数声明：{ { <type-specifier> }+ <identifier> { "," <identifier> } ";" }
+    /// 没有声明类型的参数默认是`int`。
+    fn parse_old_style_param_declarations(&mut self, ids: Vec<String>) -> Vec<(types::Type, String)> {
+        let mut declared: Vec<(types::Type, String)> = vec![];
+        while self.current_token() != tokens::Token::OpenBrace {
+            let specifiers = self.parse_type_specifier_list();
+            let param_type = self.parse_type(specifiers);
+            for name in self.parse_identifier_list() {
+                if !ids.contains(&name) {
+                    panic!("{}不在函数的标识符列表中。", name);
+                }
+                if declared.iter().any(|(_, n)| *n == name) {
+                    panic!("参数{}重复声明。", name);
+                }
+                declared.push((param_type.clone(), name));
+            }
+            self.eat_token(tokens::Token::Semicolon);
+        }
+        let mut result = vec![];
+        for id in ids {
+            let t = match declared.iter().find(|(_, n)| *n == id) {
+                Some((t, _)) => t.clone(),
+                None => types::Type::Int,
+            };
+            result.push((t, id));
+        }
+        result
+    }
+
     fn finish_parsing_variable_declaration(
         &mut self,
         var_type: types::Type,
@@ -505,12 +644,15 @@ impl Parser {
         name: String,
     ) -> ast::VariableDeclaration<ast::UnTypedExp> {
         match self.current_token() {
-            tokens::Token::Semicolon => ast::VariableDeclaration {
-                name: name,
-                var_type: var_type,
-                init: None,
-                storage_class: storage_class,
-            },
+            tokens::Token::Semicolon => {
+                self.eat_token(tokens::Token::Semicolon);
+                ast::VariableDeclaration {
+                    name: name,
+                    var_type: var_type,
+                    init: None,
+                    storage_class: storage_class,
+                }
+            }
             tokens::Token::EqualSign => {
                 self.eat_token(tokens::Token::EqualSign);
                 let init = self.parse_expression(0);
@@ -526,14 +668,50 @@ impl Parser {
         }
     }
 
+    /// "_Static_assert" "(" <exp> [ "," <string> ] ")" ";"
+    fn parse_static_assert(&mut self) -> ast::StaticAssertDeclaration<ast::UnTypedExp> {
+        self.eat_token(tokens::Token::KWStaticAssert);
+        self.eat_token(tokens::Token::OpenParen);
+        let condition = self.parse_expression(0);
+        let message = match self.current_token() {
+            tokens::Token::Comma => {
+                self.eat_token(tokens::Token::Comma);
+                match self.current_token() {
+                    tokens::Token::StringLiteral(s) => {
+                        self.pos += 1;
+                        Some(s)
+                    }
+                    other => panic!("预期是字符串字面量，实际是{:?}", other),
+                }
+            }
+            _ => None,
+        };
+        self.eat_token(tokens::Token::CloseParen);
+        self.eat_token(tokens::Token::Semicolon);
+        ast::StaticAssertDeclaration {
+            condition: condition,
+            message: message,
+        }
+    }
+
     fn parse_declaration(&mut self) -> ast::Declaration<ast::UnTypedExp> {
+        if self.current_token() == tokens::Token::KWStaticAssert {
+            return ast::Declaration::StaticAssert(self.parse_static_assert());
+        }
         let specifiers = self.parse_specifier_list();
+        // inline是函数说明符，不属于类型也不属于存储类别，所以先单独拿出来
+        let inline = specifiers.contains(&tokens::Token::KWInline);
+        let specifiers = specifiers
+            .into_iter()
+            .filter(|t| *t != tokens::Token::KWInline)
+            .collect();
         let (_typ, storage_class) = self.parse_type_and_storage_class(specifiers);
         let name = self.parse_id();
         match self.current_token() {
             tokens::Token::OpenParen => ast::Declaration::FunDecl(
-                self.finish_parsing_function_declaration(_typ, storage_class, name),
+                self.finish_parsing_function_declaration(_typ, storage_class, inline, name),
             ),
+            _ if inline => panic!("inline只能用于函数声明。"),
             _ => ast::Declaration::VarDecl(
                 self.finish_parsing_variable_declaration(_typ, storage_class, name),
             ),
@@ -544,13 +722,14 @@ impl Parser {
         match self.parse_declaration() {
             ast::Declaration::VarDecl(vd) => vd,
             ast::Declaration::FunDecl(_) => panic!("预期是变量声明，这里是函数声明。"),
+            ast::Declaration::StaticAssert(_) => panic!("预期是变量声明，这里是静态断言。"),
         }
     }
 
     /// <for-init> ::= <declaration> | [ <exp> ] ";"
     fn parse_for_init(&mut self) -> ast::ForInit<ast::UnTypedExp> {
         match self.current_token() {
-            tokens::Token::KWInt | tokens::Token::KWStatic | tokens::Token::KWExtern => {
+            t if self.is_specifier(t.clone()) => {
                 ast::ForInit::InitDecl(self.parse_variable_declaration())
             }
             _ => {
@@ -579,3 +758,49 @@ impl Parser {
         ast::UntypedProgType::Program(declarations)
     }
 }
+
+#[test]
+fn test_parse_type_specifier_combinations() {
+    let mut parser = Parser::new(vec![]);
+    let mut parse = |specifiers: Vec<tokens::Token>| parser.parse_type(specifiers);
+    assert_eq!(
+        parse(vec![tokens::Token::KWLong, tokens::Token::KWLong]),
+        types::Type::Long
+    );
+    assert_eq!(
+        parse(vec![
+            tokens::Token::KWLong,
+            tokens::Token::KWInt,
+            tokens::Token::KWLong
+        ]),
+        types::Type::Long
+    );
+    assert_eq!(
+        parse(vec![tokens::Token::KWUnsigned, tokens::Token::KWShort]),
+        types::Type::UShort
+    );
+    assert_eq!(
+        parse(vec![
+            tokens::Token::KWShort,
+            tokens::Token::KWUnsigned,
+            tokens::Token::KWInt
+        ]),
+        types::Type::UShort
+    );
+    assert_eq!(
+        parse(vec![tokens::Token::KWShort, tokens::Token::KWInt]),
+        types::Type::Short
+    );
+    assert_eq!(
+        parse(vec![tokens::Token::KWSigned, tokens::Token::KWShort]),
+        types::Type::Short
+    );
+    assert_eq!(parse(vec![tokens::Token::KWBool]), types::Type::Bool);
+    assert_eq!(parse(vec![tokens::Token::KWSigned]), types::Type::Int);
+}
+
+#[test]
+#[should_panic(expected = "invalid type specifier.")]
+fn test_parse_type_rejects_long_short() {
+    Parser::new(vec![]).parse_type(vec![tokens::Token::KWLong, tokens::Token::KWShort]);
+}
@@ -0,0 +1,398 @@
+use crate::assembly;
+
+/// 一条窥孔规则只看从当前位置开始的指令，匹配成功时返回它消耗掉的指令条数和替换后的指令。
+/// 新规则只要写成这个签名的函数，再加到`RULES`里就行。
+type Rule = fn(&[assembly::Instruction]) -> Option<(usize, Vec<assembly::Instruction>)>;
+
+const RULES: [(&str, Rule); 7] = [
+    ("remove-self-move", remove_self_move),
+    ("drop-reload-after-store", drop_reload_after_store),
+    ("remove-jump-to-next-label", remove_jump_to_next_label),
+    ("remove-add-zero", remove_add_zero),
+    ("strength-reduce-multiply", strength_reduce_multiply),
+    ("zero-register-with-xor", zero_register_with_xor),
+    ("compare-zero-with-test", compare_zero_with_test),
+];
+
+fn is_memory(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Stack(_) | assembly::Operand::Data(_) => true,
+        _ => false,
+    }
+}
+
+/// 从某条指令之后开始，在标志位被读取之前就被重新设置（或者函数调用、返回），
+/// 这条指令对标志位的影响就无关紧要。遇到标签和跳转时保守地认为标志位还活跃。
+/// `not`和`mov`一样不改变标志位。
+fn flags_dead(rest: &[assembly::Instruction]) -> bool {
+    for instruction in rest {
+        match instruction {
+            assembly::Instruction::SetCC(_, _)
+            | assembly::Instruction::JmpCC(_, _)
+            | assembly::Instruction::Label(_)
+            | assembly::Instruction::Jmp(_) => return false,
+            assembly::Instruction::Cmp(_, _, _)
+            | assembly::Instruction::Test(_, _, _)
+            | assembly::Instruction::Binary {
+                op: _,
+                t: _,
+                src: _,
+                dst: _,
+            }
+            | assembly::Instruction::Unary(assembly::UnaryOperator::Neg, _, _)
+            | assembly::Instruction::Idiv(_, _)
+            | assembly::Instruction::Call(_)
+            | assembly::Instruction::Ret => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// `mov %rax, %rax`。`movl %eax, %eax`会把RAX的高32位清零，不能删掉。
+pub fn remove_self_move(
+    instructions: &[assembly::Instruction],
+) -> Option<(usize, Vec<assembly::Instruction>)> {
+    match instructions.first() {
+        Some(assembly::Instruction::Mov(assembly::AsmType::Quadword, src, dst)) if src == dst => {
+            Some((1, vec![]))
+        }
+        _ => None,
+    }
+}
+
+/// `mov %r10d, -4(%rbp)`后面紧跟`mov -4(%rbp), %r10d`，第二条是多余的。
+pub fn drop_reload_after_store(
+    instructions: &[assembly::Instruction],
+) -> Option<(usize, Vec<assembly::Instruction>)> {
+    match instructions {
+        [assembly::Instruction::Mov(t1, assembly::Operand::Reg(r1), m1), assembly::Instruction::Mov(t2, m2, assembly::Operand::Reg(r2)), ..]
+            if t1 == t2 && r1 == r2 && m1 == m2 && is_memory(m1) =>
+        {
+            Some((2, vec![instructions[0].clone()]))
+        }
+        _ => None,
+    }
+}
+
+/// `jmp .Lfoo`后面紧跟`.Lfoo:`
+pub fn remove_jump_to_next_label(
+    instructions: &[assembly::Instruction],
+) -> Option<(usize, Vec<assembly::Instruction>)> {
+    match instructions {
+        [assembly::Instruction::Jmp(target), assembly::Instruction::Label(l), ..]
+        | [assembly::Instruction::JmpCC(_, target), assembly::Instruction::Label(l), ..]
+            if target == l =>
+        {
+            Some((1, vec![]))
+        }
+        _ => None,
+    }
+}
+
+/// `add $0, %eax`和`sub $0, %eax`
+pub fn remove_add_zero(
+    instructions: &[assembly::Instruction],
+) -> Option<(usize, Vec<assembly::Instruction>)> {
+    match instructions.first() {
+        Some(assembly::Instruction::Binary {
+            op: assembly::BinaryOperator::Add | assembly::BinaryOperator::Sub,
+            t: _,
+            src: assembly::Operand::Imm(0),
+            dst: _,
+        }) if flags_dead(&instructions[1..]) => Some((1, vec![])),
+        _ => None,
+    }
+}
+
+/// 乘以1直接删掉，乘以2的幂换成左移。
+pub fn strength_reduce_multiply(
+    instructions: &[assembly::Instruction],
+) -> Option<(usize, Vec<assembly::Instruction>)> {
+    match instructions.first() {
+        Some(assembly::Instruction::Binary {
+            op: assembly::BinaryOperator::Mult,
+            t,
+            src: assembly::Operand::Imm(i),
+            dst,
+        }) if *i > 0 && (*i & (*i - 1)) == 0 && flags_dead(&instructions[1..]) => {
+            if *i == 1 {
+                Some((1, vec![]))
+            } else {
+                Some((
+                    1,
+                    vec![assembly::Instruction::Binary {
+                        op: assembly::BinaryOperator::Shl,
+                        t: *t,
+                        src: assembly::Operand::Imm(i.trailing_zeros() as i64),
+                        dst: dst.clone(),
+                    }],
+                ))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `mov $0, %eax`换成更短的`xor %eax, %eax`，但xor会改写标志位。
+pub fn zero_register_with_xor(
+    instructions: &[assembly::Instruction],
+) -> Option<(usize, Vec<assembly::Instruction>)> {
+    match instructions.first() {
+        Some(assembly::Instruction::Mov(
+            t,
+            assembly::Operand::Imm(0),
+            dst @ assembly::Operand::Reg(_),
+        )) if flags_dead(&instructions[1..]) => Some((
+            1,
+            vec![assembly::Instruction::Binary {
+                op: assembly::BinaryOperator::Xor,
+                t: *t,
+                src: dst.clone(),
+                dst: dst.clone(),
+            }],
+        )),
+        _ => None,
+    }
+}
+
+/// `cmp $0, %eax`换成`test %eax, %eax`，两者设置的标志位相同。
+pub fn compare_zero_with_test(
+    instructions: &[assembly::Instruction],
+) -> Option<(usize, Vec<assembly::Instruction>)> {
+    match instructions.first() {
+        Some(assembly::Instruction::Cmp(
+            t,
+            assembly::Operand::Imm(0),
+            op @ assembly::Operand::Reg(_),
+        )) => Some((
+            1,
+            vec![assembly::Instruction::Test(*t, op.clone(), op.clone())],
+        )),
+        _ => None,
+    }
+}
+
+fn optimize_instructions(instructions: Vec<assembly::Instruction>) -> Vec<assembly::Instruction> {
+    let mut current = instructions;
+    loop {
+        let mut changed = false;
+        let mut optimized = vec![];
+        let mut i = 0;
+        while i < current.len() {
+            let rewrite = RULES.iter().find_map(|(_, rule)| rule(&current[i..]));
+            match rewrite {
+                Some((consumed, mut replacement)) => {
+                    changed = true;
+                    optimized.append(&mut replacement);
+                    i += consumed;
+                }
+                None => {
+                    optimized.push(current[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        current = optimized;
+        if !changed {
+            return current;
+        }
+    }
+}
+
+pub fn optimize_program(program: assembly::T) -> assembly::T {
+    match program {
+        assembly::T::Program(tls) => {
+            let mut optimized = vec![];
+            for tl in tls {
+                optimized.push(match tl {
+                    assembly::TopLevel::Function {
+                        name,
+                        global,
+                        instructions,
+                    } => assembly::TopLevel::Function {
+                        name: name,
+                        global: global,
+                        instructions: optimize_instructions(instructions),
+                    },
+                    static_var => static_var,
+                });
+            }
+            assembly::T::Program(optimized)
+        }
+    }
+}
+
+#[test]
+fn test_xor_only_when_flags_dead() {
+    let eax = assembly::Operand::Reg(assembly::Reg::AX);
+    let zero_eax = assembly::Instruction::Mov(
+        assembly::AsmType::Longword,
+        assembly::Operand::Imm(0),
+        eax.clone(),
+    );
+    let set_e = assembly::Instruction::SetCC(assembly::CondCode::E, eax.clone());
+    // cmp和sete之间的mov $0不能换成xor
+    assert_eq!(zero_register_with_xor(&[zero_eax.clone(), set_e]), None);
+    assert_eq!(
+        zero_register_with_xor(&[zero_eax, assembly::Instruction::Ret]),
+        Some((
+            1,
+            vec![assembly::Instruction::Binary {
+                op: assembly::BinaryOperator::Xor,
+                t: assembly::AsmType::Longword,
+                src: eax.clone(),
+                dst: eax,
+            }]
+        ))
+    );
+}
+
+#[test]
+fn test_strength_reduce_multiply() {
+    let eax = assembly::Operand::Reg(assembly::Reg::AX);
+    let optimized = optimize_instructions(vec![
+        assembly::Instruction::Binary {
+            op: assembly::BinaryOperator::Mult,
+            t: assembly::AsmType::Quadword,
+            src: assembly::Operand::Imm(8),
+            dst: eax.clone(),
+        },
+        assembly::Instruction::Ret,
+    ]);
+    assert_eq!(
+        optimized,
+        vec![
+            assembly::Instruction::Binary {
+                op: assembly::BinaryOperator::Shl,
+                t: assembly::AsmType::Quadword,
+                src: assembly::Operand::Imm(3),
+                dst: eax,
+            },
+            assembly::Instruction::Ret,
+        ]
+    );
+}
+
+#[test]
+fn test_drop_reload_after_store() {
+    let r10 = assembly::Operand::Reg(assembly::Reg::R10);
+    let store = assembly::Instruction::Mov(
+        assembly::AsmType::Longword,
+        r10.clone(),
+        assembly::Operand::Stack(-4),
+    );
+    let reload = assembly::Instruction::Mov(
+        assembly::AsmType::Longword,
+        assembly::Operand::Stack(-4),
+        r10,
+    );
+    assert_eq!(
+        optimize_instructions(vec![store.clone(), reload, assembly::Instruction::Ret]),
+        vec![store, assembly::Instruction::Ret]
+    );
+}
+
+#[test]
+fn test_remove_self_move() {
+    let self_move = |t: assembly::AsmType| {
+        assembly::Instruction::Mov(
+            t,
+            assembly::Operand::Reg(assembly::Reg::AX),
+            assembly::Operand::Reg(assembly::Reg::AX),
+        )
+    };
+    assert_eq!(
+        remove_self_move(&[self_move(assembly::AsmType::Quadword)]),
+        Some((1, vec![]))
+    );
+    // movl会清零高32位，不是空操作
+    assert_eq!(
+        remove_self_move(&[self_move(assembly::AsmType::Longword)]),
+        None
+    );
+}
+
+#[test]
+fn test_remove_jump_to_next_label() {
+    let label = assembly::Instruction::Label("peephole.next".to_string());
+    assert_eq!(
+        optimize_instructions(vec![
+            assembly::Instruction::JmpCC(assembly::CondCode::E, "peephole.next".to_string()),
+            assembly::Instruction::Jmp("peephole.next".to_string()),
+            label.clone(),
+            assembly::Instruction::Ret,
+        ]),
+        vec![label.clone(), assembly::Instruction::Ret]
+    );
+    // 中间隔着别的指令就不能删
+    let jump = assembly::Instruction::Jmp("peephole.next".to_string());
+    assert_eq!(
+        remove_jump_to_next_label(&[jump, assembly::Instruction::Ret, label]),
+        None
+    );
+}
+
+#[test]
+fn test_remove_add_zero() {
+    let add_zero = assembly::Instruction::Binary {
+        op: assembly::BinaryOperator::Add,
+        t: assembly::AsmType::Longword,
+        src: assembly::Operand::Imm(0),
+        dst: assembly::Operand::Reg(assembly::Reg::AX),
+    };
+    let set_e = assembly::Instruction::SetCC(
+        assembly::CondCode::E,
+        assembly::Operand::Reg(assembly::Reg::CX),
+    );
+    let not = assembly::Instruction::Unary(
+        assembly::UnaryOperator::Not,
+        assembly::AsmType::Longword,
+        assembly::Operand::Reg(assembly::Reg::DX),
+    );
+    let neg = assembly::Instruction::Unary(
+        assembly::UnaryOperator::Neg,
+        assembly::AsmType::Longword,
+        assembly::Operand::Reg(assembly::Reg::DX),
+    );
+    assert_eq!(
+        remove_add_zero(&[add_zero.clone(), assembly::Instruction::Ret]),
+        Some((1, vec![]))
+    );
+    // not不改变标志位，后面的sete读到的还是add设置的标志位
+    assert_eq!(
+        remove_add_zero(&[add_zero.clone(), not, set_e.clone()]),
+        None
+    );
+    // neg重新设置了标志位
+    assert_eq!(remove_add_zero(&[add_zero, neg, set_e]), Some((1, vec![])));
+}
+
+#[test]
+fn test_compare_zero_with_test() {
+    let eax = assembly::Operand::Reg(assembly::Reg::AX);
+    assert_eq!(
+        compare_zero_with_test(&[assembly::Instruction::Cmp(
+            assembly::AsmType::Quadword,
+            assembly::Operand::Imm(0),
+            eax.clone(),
+        )]),
+        Some((
+            1,
+            vec![assembly::Instruction::Test(
+                assembly::AsmType::Quadword,
+                eax.clone(),
+                eax
+            )]
+        ))
+    );
+    // test不能有两个内存操作数，所以内存里的值保持cmp
+    assert_eq!(
+        compare_zero_with_test(&[assembly::Instruction::Cmp(
+            assembly::AsmType::Longword,
+            assembly::Operand::Imm(0),
+            assembly::Operand::Stack(-4),
+        )]),
+        None
+    );
+}
@@ -0,0 +1,810 @@
+use std::collections::HashMap;
+
+use crate::{assembly, assembly_symbols, cfg, symbols, types};
+
+// R10和R11留给instruction_fixup修正指令用，SP和BP有专门的用途，都不参与分配。
+// 调用者保存的寄存器排在前面，着色时优先使用，跨越调用活跃的值和它们都冲突，自然会分到后面的被调用者保存的寄存器
+const ALLOCATABLE_REGS: [assembly::Reg; 12] = [
+    assembly::Reg::AX,
+    assembly::Reg::CX,
+    assembly::Reg::DX,
+    assembly::Reg::DI,
+    assembly::Reg::SI,
+    assembly::Reg::R8,
+    assembly::Reg::R9,
+    assembly::Reg::BX,
+    assembly::Reg::R12,
+    assembly::Reg::R13,
+    assembly::Reg::R14,
+    assembly::Reg::R15,
+];
+
+const CALLEE_SAVED_REGS: [assembly::Reg; 5] = [
+    assembly::Reg::BX,
+    assembly::Reg::R12,
+    assembly::Reg::R13,
+    assembly::Reg::R14,
+    assembly::Reg::R15,
+];
+
+const CALLER_SAVED_REGS: [assembly::Reg; 9] = [
+    assembly::Reg::AX,
+    assembly::Reg::CX,
+    assembly::Reg::DX,
+    assembly::Reg::DI,
+    assembly::Reg::SI,
+    assembly::Reg::R8,
+    assembly::Reg::R9,
+    assembly::Reg::R10,
+    assembly::Reg::R11,
+];
+
+const PARAM_PASSING_REGS: [assembly::Reg; 6] = [
+    assembly::Reg::DI,
+    assembly::Reg::SI,
+    assembly::Reg::DX,
+    assembly::Reg::CX,
+    assembly::Reg::R8,
+    assembly::Reg::R9,
+];
+
+// 某个程序点之后还会被读取的寄存器和伪寄存器
+type LiveRegs = Vec<assembly::Operand>;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Node {
+    id: assembly::Operand,
+    neighbors: Vec<assembly::Operand>,
+    spill_cost: f64,
+    color: Option<assembly::Reg>,
+    pruned: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct InterferenceGraph {
+    nodes: Vec<Node>,
+    index: HashMap<assembly::Operand, usize>,
+}
+
+/// 只有可分配的硬件寄存器和不是静态变量的伪寄存器才是干涉图里的节点。
+fn is_node(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Reg(r) => ALLOCATABLE_REGS.contains(r),
+        assembly::Operand::Pseudo(name) => !assembly_symbols::is_static(name.clone()),
+        _ => false,
+    }
+}
+
+/// 有原型的函数只读取实际传参用到的寄存器；没有原型的函数不知道有几个参数，
+/// 保守地认为所有传参寄存器都会被读取。
+fn param_regs_used(f: &String) -> Vec<assembly::Reg> {
+    match symbols::get_opt(f.clone()) {
+        Some(symbols::Entry {
+            t:
+                types::Type::FunType {
+                    param_types,
+                    ret_type: _,
+                    has_prototype: true,
+                },
+            attrs: _,
+        }) => PARAM_PASSING_REGS
+            .iter()
+            .take(param_types.len())
+            .cloned()
+            .collect(),
+        _ => PARAM_PASSING_REGS.to_vec(),
+    }
+}
+
+fn regs(rs: Vec<assembly::Reg>) -> Vec<assembly::Operand> {
+    rs.into_iter().map(assembly::Operand::Reg).collect()
+}
+
+/// 返回一条指令读取和写入的操作数。
+fn regs_used_and_written(
+    instruction: &assembly::Instruction,
+) -> (Vec<assembly::Operand>, Vec<assembly::Operand>) {
+    let (used, written) = match instruction {
+        assembly::Instruction::Mov(_, src, dst)
+        | assembly::Instruction::Movsx {
+            src_t: _,
+            dst_t: _,
+            src,
+            dst,
+        }
+        | assembly::Instruction::MovZeroExtend {
+            src_t: _,
+            dst_t: _,
+            src,
+            dst,
+        } => (vec![src.clone()], vec![dst.clone()]),
+        assembly::Instruction::Binary {
+            op: _,
+            t: _,
+            src,
+            dst,
+        } => (vec![src.clone(), dst.clone()], vec![dst.clone()]),
+        assembly::Instruction::Unary(_, _, dst) => (vec![dst.clone()], vec![dst.clone()]),
+        assembly::Instruction::Cmp(_, op1, op2) | assembly::Instruction::Test(_, op1, op2) => {
+            (vec![op1.clone(), op2.clone()], vec![])
+        }
+        assembly::Instruction::SetCC(_, dst) => (vec![], vec![dst.clone()]),
+        assembly::Instruction::Push(op) => (vec![op.clone()], vec![]),
+        assembly::Instruction::Idiv(_, op) => (
+            vec![
+                op.clone(),
+                assembly::Operand::Reg(assembly::Reg::AX),
+                assembly::Operand::Reg(assembly::Reg::DX),
+            ],
+            regs(vec![assembly::Reg::AX, assembly::Reg::DX]),
+        ),
+        assembly::Instruction::Cdq(_) => {
+            (regs(vec![assembly::Reg::AX]), regs(vec![assembly::Reg::DX]))
+        }
+        // 调用会破坏所有调用者保存的寄存器
+        assembly::Instruction::Call(f) => {
+            (regs(param_regs_used(f)), regs(CALLER_SAVED_REGS.to_vec()))
+        }
+        assembly::Instruction::Ret => (regs(vec![assembly::Reg::AX]), vec![]),
+        _ => (vec![], vec![]),
+    };
+    (
+        used.into_iter().filter(is_node).collect(),
+        written.into_iter().filter(is_node).collect(),
+    )
+}
+
+fn add_operand(live: &mut LiveRegs, operand: &assembly::Operand) {
+    if !live.contains(operand) {
+        live.push(operand.clone());
+    }
+}
+
+fn transfer(
+    block: &cfg::BasicBlock<LiveRegs, assembly::Instruction>,
+    end_live_regs: LiveRegs,
+) -> cfg::BasicBlock<LiveRegs, assembly::Instruction> {
+    let mut current = end_live_regs;
+    let mut instructions = vec![];
+    for (_, instruction) in block.instructions.iter().rev() {
+        instructions.push((current.clone(), instruction.clone()));
+        let (used, written) = regs_used_and_written(instruction);
+        current.retain(|r| !written.contains(r));
+        for r in used.iter() {
+            add_operand(&mut current, r);
+        }
+    }
+    instructions.reverse();
+    cfg::BasicBlock {
+        id: block.id.clone(),
+        instructions: instructions,
+        preds: block.preds.clone(),
+        succs: block.succs.clone(),
+        value: current,
+    }
+}
+
+fn meet(
+    block: &cfg::BasicBlock<LiveRegs, assembly::Instruction>,
+    cfg: &cfg::Graph<LiveRegs, assembly::Instruction>,
+) -> LiveRegs {
+    let mut live = vec![];
+    for succ in block.succs.iter() {
+        match succ {
+            cfg::NodeId::Entry => panic!("内部错误：入口节点不能是后继。"),
+            // 返回值所在的AX由Ret指令本身读取
+            cfg::NodeId::Exit => (),
+            cfg::NodeId::Block(n) => {
+                for r in cfg::get_block_value(*n, cfg).iter() {
+                    add_operand(&mut live, r);
+                }
+            }
+        }
+    }
+    live
+}
+
+fn analyze_liveness(
+    cfg: cfg::Graph<(), assembly::Instruction>,
+) -> cfg::Graph<LiveRegs, assembly::Instruction> {
+    let mut annotated = cfg::initialize_annotation(cfg, vec![]);
+    let mut worklist: Vec<usize> = annotated.basic_blocks.keys().rev().cloned().collect();
+    while !worklist.is_empty() {
+        let block_idx = worklist.remove(0);
+        let block = annotated.basic_blocks[&block_idx].clone();
+        let end_live_regs = meet(&block, &annotated);
+        let new_block = transfer(&block, end_live_regs);
+        let changed = block.value.len() != new_block.value.len()
+            || !block.value.iter().all(|r| new_block.value.contains(r));
+        cfg::update_basic_block(block_idx, new_block, &mut annotated);
+        if changed {
+            for pred in cfg::get_preds(&cfg::NodeId::Block(block_idx), &annotated) {
+                if let cfg::NodeId::Block(n) = pred {
+                    if !worklist.contains(&n) {
+                        worklist.push(n);
+                    }
+                }
+            }
+        }
+    }
+    annotated
+}
+
+fn operands(instruction: &assembly::Instruction) -> Vec<assembly::Operand> {
+    match instruction {
+        assembly::Instruction::Mov(_, src, dst)
+        | assembly::Instruction::Movsx {
+            src_t: _,
+            dst_t: _,
+            src,
+            dst,
+        }
+        | assembly::Instruction::MovZeroExtend {
+            src_t: _,
+            dst_t: _,
+            src,
+            dst,
+        }
+        | assembly::Instruction::Binary {
+            op: _,
+            t: _,
+            src,
+            dst,
+        }
+        | assembly::Instruction::Cmp(_, src, dst)
+        | assembly::Instruction::Test(_, src, dst) => vec![src.clone(), dst.clone()],
+        assembly::Instruction::Unary(_, _, op)
+        | assembly::Instruction::Idiv(_, op)
+        | assembly::Instruction::SetCC(_, op)
+        | assembly::Instruction::Push(op) => vec![op.clone()],
+        _ => vec![],
+    }
+}
+
+fn add_node(graph: &mut InterferenceGraph, id: assembly::Operand) {
+    if graph.index.contains_key(&id) {
+        return;
+    }
+    let color = match &id {
+        assembly::Operand::Reg(r) => Some(r.clone()),
+        _ => None,
+    };
+    graph.index.insert(id.clone(), graph.nodes.len());
+    graph.nodes.push(Node {
+        id: id,
+        neighbors: vec![],
+        spill_cost: 0.0,
+        color: color,
+        pruned: false,
+    });
+}
+
+fn add_edge(graph: &mut InterferenceGraph, a: &assembly::Operand, b: &assembly::Operand) {
+    let a_idx = graph.index[a];
+    let b_idx = graph.index[b];
+    if !graph.nodes[a_idx].neighbors.contains(b) {
+        graph.nodes[a_idx].neighbors.push(b.clone());
+    }
+    if !graph.nodes[b_idx].neighbors.contains(a) {
+        graph.nodes[b_idx].neighbors.push(a.clone());
+    }
+}
+
+/// 一个操作数被写入时，所有在这之后还活跃的其他节点都和它冲突。
+/// `mov`的源操作数是个例外：两者的值相同，可以放在同一个寄存器里。
+fn build_interference_graph(instructions: &Vec<assembly::Instruction>) -> InterferenceGraph {
+    let mut graph = InterferenceGraph {
+        nodes: vec![],
+        index: HashMap::new(),
+    };
+    for r in ALLOCATABLE_REGS.iter() {
+        add_node(&mut graph, assembly::Operand::Reg(r.clone()));
+    }
+    // 硬件寄存器两两冲突，合并时George测试依赖这一点
+    for r1 in ALLOCATABLE_REGS.iter() {
+        for r2 in ALLOCATABLE_REGS.iter() {
+            if r1 != r2 {
+                add_edge(
+                    &mut graph,
+                    &assembly::Operand::Reg(r1.clone()),
+                    &assembly::Operand::Reg(r2.clone()),
+                );
+            }
+        }
+    }
+    for instruction in instructions {
+        for op in operands(instruction) {
+            if let assembly::Operand::Pseudo(_) = op {
+                if is_node(&op) {
+                    add_node(&mut graph, op.clone());
+                    let idx = graph.index[&op];
+                    // 溢出代价按照使用次数估计
+                    graph.nodes[idx].spill_cost += 1.0;
+                }
+            }
+        }
+    }
+    let liveness = analyze_liveness(cfg::instructions_to_cfg(
+        "".to_string(),
+        instructions.clone(),
+    ));
+    for (_, block) in liveness.basic_blocks.iter() {
+        for (live_after, instruction) in block.instructions.iter() {
+            let (_, written) = regs_used_and_written(instruction);
+            let mov_src = match instruction {
+                assembly::Instruction::Mov(_, src, _) => Some(src.clone()),
+                _ => None,
+            };
+            for l in live_after.iter() {
+                if Some(l.clone()) == mov_src {
+                    continue;
+                }
+                for w in written.iter() {
+                    if l != w {
+                        add_edge(&mut graph, l, w);
+                    }
+                }
+            }
+        }
+    }
+    graph
+}
+
+fn unpruned_degree(graph: &InterferenceGraph, node: &Node) -> usize {
+    node.neighbors
+        .iter()
+        .filter(|n| !graph.nodes[graph.index[*n]].pruned)
+        .count()
+}
+
+/// Chaitin-Briggs着色：不断剪掉度数小于k的节点，剪不动的时候挑溢出代价和度数之比
+/// 最小的节点乐观地压栈，最后按相反的顺序给节点着色，着不上色的才真正溢出。
+fn color_graph(graph: &mut InterferenceGraph) {
+    let k = ALLOCATABLE_REGS.len();
+    let mut stack = vec![];
+    loop {
+        let remaining: Vec<usize> = (0..graph.nodes.len())
+            .filter(|i| graph.nodes[*i].color.is_none() && !graph.nodes[*i].pruned)
+            .collect();
+        if remaining.is_empty() {
+            break;
+        }
+        let degrees: Vec<usize> = remaining
+            .iter()
+            .map(|i| unpruned_degree(graph, &graph.nodes[*i]))
+            .collect();
+        let chosen = match degrees.iter().position(|d| *d < k) {
+            Some(pos) => remaining[pos],
+            None => {
+                let mut best = 0;
+                for pos in 1..remaining.len() {
+                    let metric = graph.nodes[remaining[pos]].spill_cost / degrees[pos] as f64;
+                    let best_metric =
+                        graph.nodes[remaining[best]].spill_cost / degrees[best] as f64;
+                    if metric < best_metric {
+                        best = pos;
+                    }
+                }
+                remaining[best]
+            }
+        };
+        graph.nodes[chosen].pruned = true;
+        stack.push(chosen);
+    }
+    while let Some(idx) = stack.pop() {
+        let mut available = ALLOCATABLE_REGS.to_vec();
+        for neighbor in graph.nodes[idx].neighbors.iter() {
+            let neighbor_node = &graph.nodes[graph.index[neighbor]];
+            if neighbor_node.pruned {
+                continue;
+            }
+            if let Some(c) = &neighbor_node.color {
+                available.retain(|r| r != c);
+            }
+        }
+        graph.nodes[idx].pruned = false;
+        graph.nodes[idx].color = available.first().cloned();
+    }
+}
+
+fn replace_operand(
+    operand: assembly::Operand,
+    register_map: &HashMap<String, assembly::Operand>,
+) -> assembly::Operand {
+    match operand {
+        assembly::Operand::Pseudo(p) => match register_map.get(&p) {
+            Some(r) => r.clone(),
+            None => assembly::Operand::Pseudo(p),
+        },
+        other => other,
+    }
+}
+
+fn replace_pseudoregs(
+    instruction: assembly::Instruction,
+    register_map: &HashMap<String, assembly::Operand>,
+) -> Option<assembly::Instruction> {
+    let f = |op: assembly::Operand| replace_operand(op, register_map);
+    match instruction {
+        assembly::Instruction::Mov(t, src, dst) => {
+            let new_src = f(src);
+            let new_dst = f(dst);
+            // 源和目的分到同一个寄存器，或者已经合并成同一个节点，这条mov就没用了
+            if new_src == new_dst {
+                None
+            } else {
+                Some(assembly::Instruction::Mov(t, new_src, new_dst))
+            }
+        }
+        assembly::Instruction::Movsx {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => Some(assembly::Instruction::Movsx {
+            src_t: src_t,
+            dst_t: dst_t,
+            src: f(src),
+            dst: f(dst),
+        }),
+        assembly::Instruction::MovZeroExtend {
+            src_t,
+            dst_t,
+            src,
+            dst,
+        } => Some(assembly::Instruction::MovZeroExtend {
+            src_t: src_t,
+            dst_t: dst_t,
+            src: f(src),
+            dst: f(dst),
+        }),
+        assembly::Instruction::Unary(op, t, dst) => {
+            Some(assembly::Instruction::Unary(op, t, f(dst)))
+        }
+        assembly::Instruction::Binary { op, t, src, dst } => Some(assembly::Instruction::Binary {
+            op: op,
+            t: t,
+            src: f(src),
+            dst: f(dst),
+        }),
+        assembly::Instruction::Cmp(t, op1, op2) => {
+            Some(assembly::Instruction::Cmp(t, f(op1), f(op2)))
+        }
+        assembly::Instruction::Test(t, op1, op2) => {
+            Some(assembly::Instruction::Test(t, f(op1), f(op2)))
+        }
+        assembly::Instruction::Idiv(t, op) => Some(assembly::Instruction::Idiv(t, f(op))),
+        assembly::Instruction::SetCC(code, op) => Some(assembly::Instruction::SetCC(code, f(op))),
+        assembly::Instruction::Push(op) => Some(assembly::Instruction::Push(f(op))),
+        other => Some(other),
+    }
+}
+
+fn is_hard_reg(operand: &assembly::Operand) -> bool {
+    match operand {
+        assembly::Operand::Reg(_) => true,
+        _ => false,
+    }
+}
+
+fn degree(graph: &InterferenceGraph, operand: &assembly::Operand) -> usize {
+    graph.nodes[graph.index[operand]].neighbors.len()
+}
+
+fn are_neighbors(graph: &InterferenceGraph, a: &assembly::Operand, b: &assembly::Operand) -> bool {
+    graph.nodes[graph.index[a]].neighbors.contains(b)
+}
+
+/// Briggs测试：合并后的节点里度数不小于k的邻居少于k个，合并就不会让图变得更难着色。
+fn briggs_test(graph: &InterferenceGraph, x: &assembly::Operand, y: &assembly::Operand) -> bool {
+    let k = ALLOCATABLE_REGS.len();
+    let mut combined: Vec<assembly::Operand> = graph.nodes[graph.index[x]].neighbors.clone();
+    for n in graph.nodes[graph.index[y]].neighbors.iter() {
+        if !combined.contains(n) {
+            combined.push(n.clone());
+        }
+    }
+    let significant = combined
+        .iter()
+        .filter(|n| {
+            // 同时是两个节点的邻居，合并以后度数要减一
+            let d = if are_neighbors(graph, x, n) && are_neighbors(graph, y, n) {
+                degree(graph, n) - 1
+            } else {
+                degree(graph, n)
+            };
+            is_hard_reg(n) || d >= k
+        })
+        .count();
+    significant < k
+}
+
+/// George测试：伪寄存器的每个邻居要么已经和硬件寄存器冲突，要么度数小于k。
+fn george_test(
+    graph: &InterferenceGraph,
+    hard_reg: &assembly::Operand,
+    pseudo: &assembly::Operand,
+) -> bool {
+    let k = ALLOCATABLE_REGS.len();
+    graph.nodes[graph.index[pseudo]]
+        .neighbors
+        .iter()
+        .all(|n| are_neighbors(graph, n, hard_reg) || (!is_hard_reg(n) && degree(graph, n) < k))
+}
+
+fn conservative_coalesceable(
+    graph: &InterferenceGraph,
+    src: &assembly::Operand,
+    dst: &assembly::Operand,
+) -> bool {
+    if briggs_test(graph, src, dst) {
+        true
+    } else if is_hard_reg(src) {
+        george_test(graph, src, dst)
+    } else if is_hard_reg(dst) {
+        george_test(graph, dst, src)
+    } else {
+        false
+    }
+}
+
+fn find(
+    operand: &assembly::Operand,
+    merged: &HashMap<assembly::Operand, assembly::Operand>,
+) -> assembly::Operand {
+    match merged.get(operand) {
+        Some(parent) => find(parent, merged),
+        None => operand.clone(),
+    }
+}
+
+/// 把`to_merge`的所有边转移到`to_keep`上，然后把`to_merge`从图里摘掉。
+fn merge_nodes(
+    graph: &mut InterferenceGraph,
+    to_merge: &assembly::Operand,
+    to_keep: &assembly::Operand,
+) {
+    let merge_idx = graph.index[to_merge];
+    let neighbors = graph.nodes[merge_idx].neighbors.clone();
+    for n in neighbors.iter() {
+        add_edge(graph, to_keep, n);
+        let n_idx = graph.index[n];
+        graph.nodes[n_idx].neighbors.retain(|m| m != to_merge);
+    }
+    graph.nodes[merge_idx].neighbors = vec![];
+    graph.nodes[merge_idx].pruned = true;
+}
+
+/// 保守合并：源和目的互不冲突、合并以后不会影响着色的mov，两端合并成同一个节点。
+fn coalesce(
+    graph: &mut InterferenceGraph,
+    instructions: &Vec<assembly::Instruction>,
+) -> HashMap<assembly::Operand, assembly::Operand> {
+    let mut merged = HashMap::new();
+    for instruction in instructions {
+        if let assembly::Instruction::Mov(_, src, dst) = instruction {
+            let src = find(src, &merged);
+            let dst = find(dst, &merged);
+            if is_node(&src)
+                && is_node(&dst)
+                && src != dst
+                && !are_neighbors(graph, &src, &dst)
+                && conservative_coalesceable(graph, &src, &dst)
+            {
+                // 硬件寄存器不能被合并掉
+                let (to_keep, to_merge) = if is_hard_reg(&src) {
+                    (src, dst)
+                } else {
+                    (dst, src)
+                };
+                merge_nodes(graph, &to_merge, &to_keep);
+                merged.insert(to_merge, to_keep);
+            }
+        }
+    }
+    merged
+}
+
+fn allocate_registers_for_function(
+    name: &String,
+    instructions: Vec<assembly::Instruction>,
+) -> Vec<assembly::Instruction> {
+    // 每合并一轮都要重新做活跃分析、重建干涉图，直到没有可以合并的mov为止
+    let mut instructions = instructions;
+    let mut graph = loop {
+        let mut graph = build_interference_graph(&instructions);
+        let merged = coalesce(&mut graph, &instructions);
+        if merged.is_empty() {
+            break graph;
+        }
+        let mut coalesced_map = HashMap::new();
+        for op in merged.keys() {
+            if let assembly::Operand::Pseudo(p) = op {
+                coalesced_map.insert(p.clone(), find(op, &merged));
+            }
+        }
+        instructions = instructions
+            .into_iter()
+            .filter_map(|i| replace_pseudoregs(i, &coalesced_map))
+            .collect();
+    };
+    color_graph(&mut graph);
+    let mut register_map = HashMap::new();
+    let mut callee_saved_regs = vec![];
+    for node in graph.nodes.iter() {
+        if let (assembly::Operand::Pseudo(p), Some(r)) = (&node.id, &node.color) {
+            register_map.insert(p.clone(), assembly::Operand::Reg(r.clone()));
+            if CALLEE_SAVED_REGS.contains(r) && !callee_saved_regs.contains(r) {
+                callee_saved_regs.push(r.clone());
+            }
+        }
+    }
+    assembly_symbols::add_callee_saved_regs_used(name.clone(), callee_saved_regs);
+    // 没有分到寄存器的伪寄存器留给replace_pseudos分配栈上的位置
+    instructions
+        .into_iter()
+        .filter_map(|i| replace_pseudoregs(i, &register_map))
+        .collect()
+}
+
+pub fn allocate_registers(program: assembly::T) -> assembly::T {
+    match program {
+        assembly::T::Program(tls) => {
+            let mut allocated = vec![];
+            for tl in tls {
+                allocated.push(match tl {
+                    assembly::TopLevel::Function {
+                        name,
+                        global,
+                        instructions,
+                    } => {
+                        let allocated_instructions =
+                            allocate_registers_for_function(&name, instructions);
+                        assembly::TopLevel::Function {
+                            name: name,
+                            global: global,
+                            instructions: allocated_instructions,
+                        }
+                    }
+                    static_var => static_var,
+                });
+            }
+            assembly::T::Program(allocated)
+        }
+    }
+}
+
+#[test]
+fn test_allocate_into_return_register() {
+    assembly_symbols::add_fun("regalloc_test".to_string(), true);
+    assembly_symbols::add_var(
+        "regalloc_test.0".to_string(),
+        assembly::AsmType::Longword,
+        false,
+    );
+    let p = assembly::Operand::Pseudo("regalloc_test.0".to_string());
+    let allocated = allocate_registers_for_function(
+        &"regalloc_test".to_string(),
+        vec![
+            assembly::Instruction::Mov(
+                assembly::AsmType::Longword,
+                assembly::Operand::Imm(1),
+                p.clone(),
+            ),
+            assembly::Instruction::Mov(
+                assembly::AsmType::Longword,
+                p,
+                assembly::Operand::Reg(assembly::Reg::AX),
+            ),
+            assembly::Instruction::Ret,
+        ],
+    );
+    assert_eq!(
+        allocated,
+        vec![
+            assembly::Instruction::Mov(
+                assembly::AsmType::Longword,
+                assembly::Operand::Imm(1),
+                assembly::Operand::Reg(assembly::Reg::AX),
+            ),
+            assembly::Instruction::Ret,
+        ]
+    );
+}
+
+#[test]
+fn test_callee_saved_across_call() {
+    assembly_symbols::add_fun("regalloc_test_call".to_string(), true);
+    assembly_symbols::add_var(
+        "regalloc_test.1".to_string(),
+        assembly::AsmType::Longword,
+        false,
+    );
+    let p = assembly::Operand::Pseudo("regalloc_test.1".to_string());
+    let instructions = vec![
+        assembly::Instruction::Mov(
+            assembly::AsmType::Longword,
+            assembly::Operand::Imm(1),
+            p.clone(),
+        ),
+        assembly::Instruction::Call("regalloc_test_callee".to_string()),
+        assembly::Instruction::Mov(
+            assembly::AsmType::Longword,
+            p,
+            assembly::Operand::Reg(assembly::Reg::AX),
+        ),
+        assembly::Instruction::Ret,
+    ];
+    // 调用者保存的寄存器都会被call破坏，p只能分到被调用者保存的寄存器
+    let bx = assembly::Operand::Reg(assembly::Reg::BX);
+    let allocated =
+        allocate_registers_for_function(&"regalloc_test_call".to_string(), instructions);
+    assert_eq!(
+        allocated,
+        vec![
+            assembly::Instruction::Mov(
+                assembly::AsmType::Longword,
+                assembly::Operand::Imm(1),
+                bx.clone()
+            ),
+            assembly::Instruction::Call("regalloc_test_callee".to_string()),
+            assembly::Instruction::Mov(
+                assembly::AsmType::Longword,
+                bx,
+                assembly::Operand::Reg(assembly::Reg::AX),
+            ),
+            assembly::Instruction::Ret,
+        ]
+    );
+    assert_eq!(
+        assembly_symbols::get_callee_saved_regs_used("regalloc_test_call".to_string()),
+        vec![assembly::Reg::BX]
+    );
+}
+
+#[test]
+fn test_coalesce_parameter_moves() {
+    assembly_symbols::add_fun("regalloc_test".to_string(), true);
+    assembly_symbols::add_var(
+        "regalloc_test.2".to_string(),
+        assembly::AsmType::Longword,
+        false,
+    );
+    assembly_symbols::add_var(
+        "regalloc_test.3".to_string(),
+        assembly::AsmType::Longword,
+        false,
+    );
+    let p = assembly::Operand::Pseudo("regalloc_test.2".to_string());
+    let q = assembly::Operand::Pseudo("regalloc_test.3".to_string());
+    let di = assembly::Operand::Reg(assembly::Reg::DI);
+    let ax = assembly::Operand::Reg(assembly::Reg::AX);
+    let allocated = allocate_registers_for_function(
+        &"regalloc_test".to_string(),
+        vec![
+            assembly::Instruction::Mov(assembly::AsmType::Longword, di.clone(), p.clone()),
+            assembly::Instruction::Mov(assembly::AsmType::Longword, p, q.clone()),
+            assembly::Instruction::Binary {
+                op: assembly::BinaryOperator::Add,
+                t: assembly::AsmType::Longword,
+                src: assembly::Operand::Imm(1),
+                dst: q.clone(),
+            },
+            assembly::Instruction::Mov(assembly::AsmType::Longword, q, ax.clone()),
+            assembly::Instruction::Ret,
+        ],
+    );
+    assert_eq!(
+        allocated,
+        vec![
+            assembly::Instruction::Binary {
+                op: assembly::BinaryOperator::Add,
+                t: assembly::AsmType::Longword,
+                src: assembly::Operand::Imm(1),
+                dst: di.clone(),
+            },
+            assembly::Instruction::Mov(assembly::AsmType::Longword, di, ax),
+            assembly::Instruction::Ret,
+        ]
+    );
+}
@@ -8,6 +8,12 @@ pub struct ReplacementState {
     offset_map: HashMap<String, i64>,
 }
 
+impl Default for ReplacementState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ReplacementState {
     pub fn new() -> Self {
         ReplacementState {
@@ -25,7 +31,7 @@ impl ReplacementState {
                     if let Some(offset) = self.offset_map.get(&s) {
                         assembly::Operand::Stack(*offset)
                     } else {
-                        self.current_offset = self.current_offset - 4;
+                        self.current_offset -= 4;
                         self.offset_map.insert(s, self.current_offset);
                         assembly::Operand::Stack(self.current_offset)
                     }
@@ -45,6 +51,36 @@ impl ReplacementState {
                 let new_dst = self.replace_operand(dst);
                 assembly::Instruction::Mov(t, new_src, new_dst)
             }
+            assembly::Instruction::Movsx {
+                src_t,
+                dst_t,
+                src,
+                dst,
+            } => {
+                let new_src = self.replace_operand(src);
+                let new_dst = self.replace_operand(dst);
+                assembly::Instruction::Movsx {
+                    src_t: src_t,
+                    dst_t: dst_t,
+                    src: new_src,
+                    dst: new_dst,
+                }
+            }
+            assembly::Instruction::MovZeroExtend {
+                src_t,
+                dst_t,
+                src,
+                dst,
+            } => {
+                let new_src = self.replace_operand(src);
+                let new_dst = self.replace_operand(dst);
+                assembly::Instruction::MovZeroExtend {
+                    src_t: src_t,
+                    dst_t: dst_t,
+                    src: new_src,
+                    dst: new_dst,
+                }
+            }
             assembly::Instruction::Unary(t, op, dst) => {
                 let new_dst = self.replace_operand(dst);
                 assembly::Instruction::Unary(t, op, new_dst)
@@ -64,6 +100,11 @@ impl ReplacementState {
                 let new_op2 = self.replace_operand(op2);
                 assembly::Instruction::Cmp(t, new_op1, new_op2)
             }
+            assembly::Instruction::Test(t, op1, op2) => {
+                let new_op1 = self.replace_operand(op1);
+                let new_op2 = self.replace_operand(op2);
+                assembly::Instruction::Test(t, new_op1, new_op2)
+            }
             assembly::Instruction::Idiv(t, op) => {
                 let new_op = self.replace_operand(op);
                 assembly::Instruction::Idiv(t, new_op)
@@ -83,6 +124,7 @@ impl ReplacementState {
             | assembly::Instruction::Jmp(_)
             | assembly::Instruction::DeallocateStack(_)
             | assembly::Instruction::Call(_)
+            | assembly::Instruction::Pop(_)
             | assembly::Instruction::AllocateStack(_)) => other,
         }
     }
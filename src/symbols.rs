@@ -1,4 +1,4 @@
-use crate::types;
+use crate::{initializers, types};
 use lazy_static::lazy_static;
 use std::{collections::HashMap, sync::Mutex};
 
@@ -9,7 +9,7 @@ lazy_static! {
 #[derive(Clone, Debug, PartialEq)]
 pub enum InitialValue {
     Tentative,
-    Initial(i64),
+    Initial(initializers::StaticInit),
     NoInitializer,
 }
 
@@ -18,6 +18,10 @@ pub enum IdentifierAttrs {
     FunAttr {
         defined: bool,
         global: bool,
+        // 至少有一个声明带了inline
+        inline: bool,
+        // 所有文件作用域声明都是不带extern的inline，这时定义只是内联定义，不生成外部定义
+        inline_definition: bool,
         stack_frame_size: i64,
     },
     StaticAttr {
@@ -54,13 +58,22 @@ pub fn add_static_var(name: String, t: types::Type, global: bool, init: InitialV
     _map.insert(name, entry);
 }
 
-pub fn add_fun(name: String, t: types::Type, global: bool, defined: bool) {
+pub fn add_fun(
+    name: String,
+    t: types::Type,
+    global: bool,
+    defined: bool,
+    inline: bool,
+    inline_definition: bool,
+) {
     let mut _map = SYMBOL_TABLE.lock().unwrap();
     let entry = Entry {
         t: t,
         attrs: IdentifierAttrs::FunAttr {
             defined: defined,
             global: global,
+            inline: inline,
+            inline_definition: inline_definition,
             stack_frame_size: 0,
         },
     };
@@ -88,6 +101,8 @@ pub fn is_global(name: String) -> bool {
         IdentifierAttrs::FunAttr {
             defined: _,
             global,
+            inline: _,
+            inline_definition: _,
             stack_frame_size: _,
         } => global,
     }
@@ -101,6 +116,8 @@ pub fn is_static(name: String) -> bool {
             IdentifierAttrs::FunAttr {
                 defined: _,
                 global: _,
+                inline: _,
+                inline_definition: _,
                 stack_frame_size: _,
             } => panic!("内部错误：函数没有storage duration。"),
         },
@@ -108,6 +125,41 @@ pub fn is_static(name: String) -> bool {
     }
 }
 
+/// 只有内联定义的外部函数不在本文件里生成代码，调用它要链接到别处的外部定义。
+pub fn is_inline_definition(name: String) -> bool {
+    match get_opt(name) {
+        Some(Entry {
+            t: _,
+            attrs:
+                IdentifierAttrs::FunAttr {
+                    defined: _,
+                    global: true,
+                    inline: true,
+                    inline_definition: true,
+                    stack_frame_size: _,
+                },
+        }) => true,
+        _ => false,
+    }
+}
+
+pub fn is_static_inline(name: String) -> bool {
+    match get_opt(name) {
+        Some(Entry {
+            t: _,
+            attrs:
+                IdentifierAttrs::FunAttr {
+                    defined: _,
+                    global: false,
+                    inline: true,
+                    inline_definition: _,
+                    stack_frame_size: _,
+                },
+        }) => true,
+        _ => false,
+    }
+}
+
 pub fn is_defined(name: String) -> bool {
     let _map = SYMBOL_TABLE.lock().unwrap();
     _map.contains_key(&name)
@@ -129,12 +181,16 @@ pub fn set_bytes_required(name: String, bytes_required: i64) {
         IdentifierAttrs::FunAttr {
             defined,
             global,
+            inline,
+            inline_definition,
             stack_frame_size: _,
         } => Entry {
             t: entry.t.clone(),
             attrs: IdentifierAttrs::FunAttr {
                 defined: defined,
                 global: global,
+                inline: inline,
+                inline_definition: inline_definition,
                 stack_frame_size: bytes_required,
             },
         },
@@ -152,6 +208,8 @@ pub fn get_bytes_required(name: String) -> i64 {
                 IdentifierAttrs::FunAttr {
                     defined: _,
                     global: _,
+                    inline: _,
+                    inline_definition: _,
                     stack_frame_size,
                 },
         }) => *stack_frame_size,
@@ -159,9 +217,17 @@ pub fn get_bytes_required(name: String) -> i64 {
     }
 }
 
-pub fn iter<F>(f: F) where F: Fn(String, Entry) -> () {
+pub fn iter<F>(f: F) where F: Fn(String, Entry) {
     let mut _map = SYMBOL_TABLE.lock().unwrap();
     for (k, v) in _map.iter() {
-        f(k, v);
+        f(k.clone(), v.clone());
     }
-}
\ No newline at end of file
+}
+
+/// 同一个进程里编译多个文件时，每次编译前清空符号表。
+/// 上一次编译如果在持有锁的时候panic，锁会中毒，这里一并恢复。
+pub fn clear() {
+    let mut _map = SYMBOL_TABLE.lock().unwrap_or_else(|e| e.into_inner());
+    _map.clear();
+    SYMBOL_TABLE.clear_poison();
+}
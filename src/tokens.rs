@@ -3,8 +3,13 @@ pub enum Token {
     Identifier(String),
     ConstInt(i32),
     ConstLong(i64),
+    StringLiteral(String),
     KWInt,
     KWLong,
+    KWShort,
+    KWSigned,
+    KWUnsigned,
+    KWBool,
     KWReturn,
     KWVoid,
     KWIf,
@@ -16,6 +21,9 @@ pub enum Token {
     KWContinue,
     KWStatic,
     KWExtern,
+    KWInline,
+    KWSizeof,
+    KWStaticAssert,
     OpenParen,
     CloseParen,
     OpenBrace,
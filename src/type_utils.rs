@@ -5,16 +5,56 @@ pub fn get_type(t: ast::TypedExp) -> types::Type {
 }
 
 pub fn set_type(e: ast::TypedInnerExp, new_type: types::Type) -> ast::TypedExp {
-    ast::TypedExp { e: e, t: new_type }
+    ast::TypedExp {
+        e: Box::new(e),
+        t: new_type,
+    }
+}
+
+pub fn get_size(t: types::Type) -> i64 {
+    match t {
+        types::Type::Bool => 1,
+        types::Type::Short | types::Type::UShort => 2,
+        types::Type::Int => 4,
+        types::Type::Long => 8,
+        types::Type::FunType {
+            param_types: _,
+            ret_type: _,
+            has_prototype: _,
+        } => panic!("内部错误：函数类型没有大小。"),
+    }
 }
 
 pub fn get_alignment(t: types::Type) -> i64 {
     match t {
+        types::Type::Bool => 1,
+        types::Type::Short | types::Type::UShort => 2,
         types::Type::Int => 4,
         types::Type::Long => 8,
         types::Type::FunType {
             param_types: _,
             ret_type: _,
+            has_prototype: _,
         } => panic!("内部错误：函数类型不存在对齐这一说。"),
     }
 }
+
+pub fn is_signed(t: types::Type) -> bool {
+    match t {
+        types::Type::Short | types::Type::Int | types::Type::Long => true,
+        types::Type::Bool | types::Type::UShort => false,
+        types::Type::FunType {
+            param_types: _,
+            ret_type: _,
+            has_prototype: _,
+        } => panic!("内部错误：函数类型没有符号。"),
+    }
+}
+
+/// 整数提升：比`int`窄的类型（`_Bool`、`short`、`unsigned short`）都提升为`int`。
+pub fn promote(t: types::Type) -> types::Type {
+    match t {
+        types::Type::Bool | types::Type::Short | types::Type::UShort => types::Type::Int,
+        other => other,
+    }
+}
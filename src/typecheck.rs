@@ -1,16 +1,19 @@
-use std::process::id;
-
-use crate::{ast, const_convert, constants, initializers, symbols, type_utils, types};
+use crate::{
+    ast, const_convert, const_eval, constants, initializers, symbols, type_utils, types, warnings,
+    Stage,
+};
 
 pub fn convert_to(e: ast::TypedExp, target_type: types::Type) -> ast::TypedExp {
     let cast = ast::TypedInnerExp::Cast {
-        target_type: target_type,
+        target_type: target_type.clone(),
         e: e,
     };
     type_utils::set_type(cast, target_type)
 }
 
 pub fn get_common_type(t1: types::Type, t2: types::Type) -> types::Type {
+    let t1 = type_utils::promote(t1);
+    let t2 = type_utils::promote(t2);
     if t1 == t2 {
         t1
     } else {
@@ -18,21 +21,108 @@ pub fn get_common_type(t1: types::Type, t2: types::Type) -> types::Type {
     }
 }
 
+/// 没有原型的函数声明只和参数类型不受默认实参提升影响的原型兼容。
+fn params_compatible_with_unprototyped(
+    prototype_params: &[Box<types::Type>],
+    old_style_params: &[Box<types::Type>],
+) -> bool {
+    if old_style_params.is_empty() {
+        prototype_params
+            .iter()
+            .all(|t| type_utils::promote((**t).clone()) == **t)
+    } else {
+        prototype_params.len() == old_style_params.len()
+            && prototype_params
+                .iter()
+                .zip(old_style_params.iter())
+                .all(|(p, o)| **p == type_utils::promote((**o).clone()))
+    }
+}
+
+pub fn is_compatible(t1: types::Type, t2: types::Type) -> bool {
+    match (t1, t2) {
+        (
+            types::Type::FunType {
+                param_types: params1,
+                ret_type: ret1,
+                has_prototype: proto1,
+            },
+            types::Type::FunType {
+                param_types: params2,
+                ret_type: ret2,
+                has_prototype: proto2,
+            },
+        ) => {
+            if ret1 != ret2 {
+                false
+            } else {
+                match (proto1, proto2) {
+                    (true, true) => params1 == params2,
+                    (true, false) => params_compatible_with_unprototyped(&params1, &params2),
+                    (false, true) => params_compatible_with_unprototyped(&params2, &params1),
+                    (false, false) => {
+                        params1.is_empty() || params2.is_empty() || params1 == params2
+                    }
+                }
+            }
+        }
+        (t1, t2) => t1 == t2,
+    }
+}
+
+/// 两个兼容的函数声明合成的类型：有原型的一方优先，
+/// 否则保留旧式定义里的参数信息。
+pub fn composite_type(t1: types::Type, t2: types::Type) -> types::Type {
+    match (t1.clone(), t2.clone()) {
+        (
+            types::Type::FunType {
+                param_types: params1,
+                ret_type: _,
+                has_prototype: proto1,
+            },
+            types::Type::FunType {
+                param_types: params2,
+                ret_type: _,
+                has_prototype: proto2,
+            },
+        ) => {
+            if proto1 {
+                t1
+            } else if proto2 {
+                t2
+            } else if params1.is_empty() && !params2.is_empty() {
+                t2
+            } else {
+                t1
+            }
+        }
+        _ => t1,
+    }
+}
+
 pub fn typecheck_var(v: String) -> ast::TypedExp {
-    let v_type = symbols::get(v).t;
+    let v_type = symbols::get(v.clone()).t;
     let e = ast::TypedInnerExp::Var(v);
     match v_type {
         types::Type::FunType {
             param_types: _,
             ret_type: _,
+            has_prototype: _,
         } => panic!("试图将函数名用作变量。"),
-        types::Type::Int | types::Type::Long => type_utils::set_type(e, v_type),
+        types::Type::Bool
+        | types::Type::Short
+        | types::Type::UShort
+        | types::Type::Int
+        | types::Type::Long => type_utils::set_type(e, v_type),
     }
 }
 
 pub fn typecheck_const(c: constants::T) -> ast::TypedExp {
-    let e = ast::TypedInnerExp::Constant(c);
+    let e = ast::TypedInnerExp::Constant(c.clone());
     match c {
+        constants::T::ConstBool(_) => type_utils::set_type(e, types::Type::Bool),
+        constants::T::ConstShort(_) => type_utils::set_type(e, types::Type::Short),
+        constants::T::ConstUShort(_) => type_utils::set_type(e, types::Type::UShort),
         constants::T::ConstInt(_) => type_utils::set_type(e, types::Type::Int),
         constants::T::ConstLong(_) => type_utils::set_type(e, types::Type::Long),
     }
@@ -45,13 +135,7 @@ pub fn typecheck_exp(exp: ast::UnTypedExp) -> ast::TypedExp {
         ast::UnTypedExp::Cast {
             target_type,
             e: inner,
-        } => {
-            let cast_exp = ast::TypedInnerExp::Cast {
-                target_type: target_type,
-                e: Box::new(typecheck_exp(*inner).e),
-            };
-            type_utils::set_type(cast_exp, target_type)
-        }
+        } => convert_to(typecheck_exp(*inner), target_type),
         ast::UnTypedExp::Unary(op, inner) => typecheck_unary(op, *inner),
         ast::UnTypedExp::Binary(op, e1, e2) => typecheck_binary(op, *e1, *e2),
         ast::UnTypedExp::Assignment(lhs, rhs) => typecheck_assignment(*lhs, *rhs),
@@ -61,15 +145,33 @@ pub fn typecheck_exp(exp: ast::UnTypedExp) -> ast::TypedExp {
             else_result,
         } => typecheck_conditional(*condition, *then_result, *else_result),
         ast::UnTypedExp::Constant(c) => typecheck_const(c),
+        // sizeof不对操作数求值，这里直接算出结果。还没有unsigned long，所以结果是long
+        ast::UnTypedExp::SizeOf(inner) => {
+            let typed_inner = typecheck_exp(*inner);
+            let size = type_utils::get_size(type_utils::get_type(typed_inner));
+            typecheck_const(constants::T::ConstLong(size))
+        }
+        ast::UnTypedExp::SizeOfT(t) => {
+            let size = type_utils::get_size(t);
+            typecheck_const(constants::T::ConstLong(size))
+        }
     }
 }
 
 pub fn typecheck_unary(op: ast::UnaryOperator, inner: ast::UnTypedExp) -> ast::TypedExp {
     let typed_inner = typecheck_exp(inner);
-    let unary_exp = ast::TypedInnerExp::Unary(op, Box::new(typed_inner.e));
     match op {
-        ast::UnaryOperator::Not => type_utils::set_type(unary_exp, types::Type::Int),
-        _ => type_utils::set_type(unary_exp, type_utils::get_type(typed_inner)),
+        ast::UnaryOperator::Not => {
+            let unary_exp = ast::TypedInnerExp::Unary(op, typed_inner);
+            type_utils::set_type(unary_exp, types::Type::Int)
+        }
+        _ => {
+            // 取负和按位取反之前要先做整数提升
+            let promoted_t = type_utils::promote(type_utils::get_type(typed_inner.clone()));
+            let promoted_inner = convert_to(typed_inner, promoted_t.clone());
+            let unary_exp = ast::TypedInnerExp::Unary(op, promoted_inner);
+            type_utils::set_type(unary_exp, promoted_t)
+        }
     }
 }
 
@@ -77,36 +179,38 @@ pub fn typecheck_binary(op: ast::BinaryOperator, e1: ast::UnTypedExp, e2: ast::U
     let typed_e1 = typecheck_exp(e1);
     let typed_e2 = typecheck_exp(e2);
     match op {
-        ast::BinaryOperator::Add | ast::BinaryOperator::Or => {
-            let typed_binexp = ast::TypedInnerExp::Binary(op, Box::new(typed_e1.e), Box::new(typed_e2.e));
+        // 逻辑运算符的两个操作数各自和0比较，不需要转换成公共类型
+        ast::BinaryOperator::And | ast::BinaryOperator::Or => {
+            let typed_binexp = ast::TypedInnerExp::Binary(op, typed_e1, typed_e2);
             type_utils::set_type(typed_binexp, types::Type::Int)
         }
         _ => {
-            let t1 = type_utils::get_type(typed_e1);
-            let t2 = type_utils::get_type(typed_e2);
+            let t1 = type_utils::get_type(typed_e1.clone());
+            let t2 = type_utils::get_type(typed_e2.clone());
             let common_type = get_common_type(t1, t2);
-            let converted_e1 = convert_to(typed_e1, common_type);
-            let converted_e2 = convert_to(typed_e2, common_type);
-            let binary_exp =
-                ast::TypedInnerExp::Binary(op, Box::new(converted_e1.e), Box::new(converted_e2.e));
-            match op {
+            let converted_e1 = convert_to(typed_e1, common_type.clone());
+            let converted_e2 = convert_to(typed_e2, common_type.clone());
+            let result_type = match op {
                 ast::BinaryOperator::Add
                 | ast::BinaryOperator::Subtract
                 | ast::BinaryOperator::Multiply
                 | ast::BinaryOperator::Divide
-                | ast::BinaryOperator::Mod => type_utils::set_type(binary_exp, common_type),
-                _ => type_utils::set_type(binary_exp, types::Type::Int),
-            }
+                | ast::BinaryOperator::Mod => common_type,
+                _ => types::Type::Int,
+            };
+            let binary_exp = ast::TypedInnerExp::Binary(op, converted_e1, converted_e2);
+            type_utils::set_type(binary_exp, result_type)
         }
     }
 }
 
 pub fn typecheck_assignment(lhs: ast::UnTypedExp, rhs: ast::UnTypedExp) -> ast::TypedExp {
     let typed_lhs = typecheck_exp(lhs);
-    let lhs_type = type_utils::get_type(typed_lhs);
+    let lhs_type = type_utils::get_type(typed_lhs.clone());
     let typed_rhs = typecheck_exp(rhs);
-    let converted_rhs = convert_to(Box::new(typed_rhs.e), lhs_type);
-    let assign_exp = ast::TypedInnerExp::Assignment(Box::new(typed_lhs.e), Box::new(converted_rhs.e));
+    // 赋值给_Bool时，转换会把非零值变成1
+    let converted_rhs = convert_to(typed_rhs, lhs_type.clone());
+    let assign_exp = ast::TypedInnerExp::Assignment(typed_lhs, converted_rhs);
     type_utils::set_type(assign_exp, lhs_type)
 }
 
@@ -119,59 +223,107 @@ pub fn typecheck_conditional(
     let typed_then = typecheck_exp(then_exp);
     let typed_else = typecheck_exp(else_exp);
     let common_type = get_common_type(
-        type_utils::get_type(typed_then),
-        type_utils::get_type(typed_else),
+        type_utils::get_type(typed_then.clone()),
+        type_utils::get_type(typed_else.clone()),
     );
-    let converted_then = convert_to(Box::new(typed_then.e), common_type);
-    let converted_else = convert_to(Box::new(typed_else.e), common_type);
+    let converted_then = convert_to(typed_then, common_type.clone());
+    let converted_else = convert_to(typed_else, common_type.clone());
     let conditional_exp = ast::TypedInnerExp::Conditional {
-        condition: Box::new(typed_condition.e),
-        then_result: Box::new(converted_then.e),
-        else_result: Box::new(converted_else.e),
+        condition: typed_condition,
+        then_result: converted_then,
+        else_result: converted_else,
     };
     type_utils::set_type(conditional_exp, common_type)
 }
 
 pub fn typecheck_fun_call(f: String, args: Vec<ast::UnTypedExp>) -> ast::TypedExp {
-    let f_type = symbols::get(f).t;
+    let f_type = match symbols::get_opt(f.clone()) {
+        Some(entry) => entry.t,
+        // 隐式声明的函数被当作`extern int f();`处理
+        None => {
+            let implicit_type = types::Type::FunType {
+                param_types: vec![],
+                ret_type: Box::new(types::Type::Int),
+                has_prototype: false,
+            };
+            symbols::add_fun(f.clone(), implicit_type.clone(), true, false, false, false);
+            implicit_type
+        }
+    };
     match f_type {
-        types::Type::Int | types::Type::Long => panic!("tried to use variable as function name."),
+        types::Type::Bool
+        | types::Type::Short
+        | types::Type::UShort
+        | types::Type::Int
+        | types::Type::Long => panic!("tried to use variable as function name."),
         types::Type::FunType {
             param_types,
             ret_type,
+            has_prototype: true,
         } => {
             if param_types.len() != args.len() {
                 panic!("function called with wrong number of arguments.")
             }
             let mut converted_args = vec![];
-            for i in 0..param_types.len() {
-                converted_args.push(convert_to(
-                    Box::new(typecheck_exp(args[i]).e),
-                    *param_types[i],
-                ));
+            for (arg, param_t) in args.into_iter().zip(param_types) {
+                converted_args.push(convert_to(typecheck_exp(arg), *param_t));
             }
-            let call_exp = ast::TypedInnerExp::FunCall {
+            let call_exp = ast::TypedInnerExp::Funcall {
                 f: f,
                 args: converted_args,
             };
-            type_utils::set_type(call_exp, ret_type)
+            type_utils::set_type(call_exp, *ret_type)
         }
-    }
-}
-
-pub fn to_static_init(var_type: types::Type, init: ast::UnTypedExp) -> ast::TypedExp {
-    match init {
-        ast::UnTypedExp::Constant(c) => {
-            let init_val = match const_convert::const_convert(var_type, c) {
-                constants::T::ConstInt(i) => initializers::StaticInit::IntInit(i),
-                constants::T::ConstLong(l) => initializers::StaticInit::LongInit(l),
+        types::Type::FunType {
+            param_types,
+            ret_type,
+            has_prototype: false,
+        } => {
+            // 旧式定义知道参数个数，个数不对的调用是未定义行为
+            if !param_types.is_empty() && param_types.len() != args.len() {
+                warnings::warn(
+                    Stage::Typecheck,
+                    format!("调用函数{}时实参个数与定义不一致。", f),
+                );
+            }
+            // 没有原型时只做默认实参提升
+            let mut promoted_args = vec![];
+            for arg in args {
+                let typed_arg = typecheck_exp(arg);
+                let promoted_t = type_utils::promote(type_utils::get_type(typed_arg.clone()));
+                promoted_args.push(convert_to(typed_arg, promoted_t));
+            }
+            let call_exp = ast::TypedInnerExp::Funcall {
+                f: f,
+                args: promoted_args,
             };
-            symbols::InitialValue::Initial(init_val)
+            type_utils::set_type(call_exp, *ret_type)
         }
-        _ => panic!("non-constant initializer on static variable."),
     }
 }
 
+/// 初始化器按赋值的规则转换成变量的类型。
+pub fn typecheck_init(var_type: types::Type, init: ast::UnTypedExp) -> ast::TypedExp {
+    convert_to(typecheck_exp(init), var_type)
+}
+
+/// 静态变量的初始化器可以是任意整数常量表达式，`typed_init`已经转换成了变量的类型，
+/// 这里在编译期把它折叠成对应宽度的初始值。
+pub fn to_static_init(var_type: types::Type, typed_init: ast::TypedExp) -> symbols::InitialValue {
+    let c = match const_eval::eval(typed_init) {
+        Some(c) => c,
+        None => panic!("non-constant initializer on static variable."),
+    };
+    let init_val = match const_convert::const_convert(var_type, c) {
+        constants::T::ConstBool(b) => initializers::StaticInit::BoolInit(b),
+        constants::T::ConstShort(s) => initializers::StaticInit::ShortInit(s),
+        constants::T::ConstUShort(u) => initializers::StaticInit::UShortInit(u),
+        constants::T::ConstInt(i) => initializers::StaticInit::IntInit(i),
+        constants::T::ConstLong(l) => initializers::StaticInit::LongInit(l),
+    };
+    symbols::InitialValue::Initial(init_val)
+}
+
 pub fn typecheck_block(
     ret_type: types::Type,
     b: ast::Block<ast::UnTypedExp>,
@@ -180,8 +332,9 @@ pub fn typecheck_block(
         ast::Block::Block(block_items) => {
             let mut typed_block_items = vec![];
             for item in block_items {
-                typed_block_items.push(typecheck_block_item(ret_type, item));
+                typed_block_items.push(typecheck_block_item(ret_type.clone(), item));
             }
+            ast::Block::Block(typed_block_items)
         }
     }
 }
@@ -191,8 +344,8 @@ pub fn typecheck_block_item(
     block_item: ast::BlockItem<ast::UnTypedExp>,
 ) -> ast::BlockItem<ast::TypedExp> {
     match block_item {
-        ast::BlockItem::S(s) => typecheck_statement(ret_type, s),
-        ast::BlockItem::D(d) => typecheck_local_decl(d),
+        ast::BlockItem::S(s) => ast::BlockItem::S(typecheck_statement(ret_type, s)),
+        ast::BlockItem::D(d) => ast::BlockItem::D(typecheck_local_decl(d)),
     }
 }
 
@@ -212,12 +365,8 @@ pub fn typecheck_statement(
             else_clause,
         } => ast::Statement::If {
             condition: typecheck_exp(condition),
-            then_clause: typecheck_statement(ret_type, then_clause),
-            else_clause: if else_clause.is_some() {
-                typecheck_statement(ret_type, else_clause.unwrap())
-            } else {
-                None
-            },
+            then_clause: Box::new(typecheck_statement(ret_type.clone(), *then_clause)),
+            else_clause: else_clause.map(|e| Box::new(typecheck_statement(ret_type, *e))),
         },
         ast::Statement::Compound(block) => {
             ast::Statement::Compound(typecheck_block(ret_type, block))
@@ -228,7 +377,7 @@ pub fn typecheck_statement(
             id,
         } => ast::Statement::While {
             condition: typecheck_exp(condition),
-            body: typecheck_statement(ret_type, body),
+            body: Box::new(typecheck_statement(ret_type, *body)),
             id: id,
         },
         ast::Statement::DoWhile {
@@ -236,7 +385,7 @@ pub fn typecheck_statement(
             condition,
             id,
         } => ast::Statement::DoWhile {
-            body: typecheck_statement(ret_type, body),
+            body: Box::new(typecheck_statement(ret_type, *body)),
             condition: typecheck_exp(condition),
             id: id,
         },
@@ -245,7 +394,7 @@ pub fn typecheck_statement(
             condition,
             post,
             body,
-            id: _,
+            id,
         } => {
             let typechecked_for_init = match init {
                 ast::ForInit::InitDecl(ast::VariableDeclaration {
@@ -255,36 +404,51 @@ pub fn typecheck_statement(
                     storage_class: Some(_),
                 }) => panic!("storage class not permitted on declaration in for loop header."),
                 ast::ForInit::InitDecl(d) => ast::ForInit::InitDecl(typecheck_local_var_decl(d)),
-                ast::ForInit::InitExp(e) => ast::ForInit::InitExp(if e.is_some() {
-                    Some(typecheck_exp(e))
-                } else {
-                    None
-                }),
+                ast::ForInit::InitExp(e) => ast::ForInit::InitExp(e.map(typecheck_exp)),
             };
             ast::Statement::For {
                 init: typechecked_for_init,
-                condition: if condition.is_some() {
-                    Some(typecheck_exp(condition))
-                } else {
-                    None
-                },
-                post: if post.is_some() {
-                    Some(typecheck_exp(post))
-                } else {
-                    None
-                },
-                body: typecheck_statement(ret_type, body),
+                condition: condition.map(typecheck_exp),
+                post: post.map(typecheck_exp),
+                body: Box::new(typecheck_statement(ret_type, *body)),
                 id: id,
             }
         }
-        s @ (ast::Statement::Null | ast::Statement::Break(_) | ast::Statement::Continue(_)) => s,
+        ast::Statement::Null => ast::Statement::Null,
+        ast::Statement::Break(id) => ast::Statement::Break(id),
+        ast::Statement::Continue(id) => ast::Statement::Continue(id),
+    }
+}
+
+pub fn typecheck_static_assert(
+    sa: ast::StaticAssertDeclaration<ast::UnTypedExp>,
+) -> ast::StaticAssertDeclaration<ast::TypedExp> {
+    let typed_condition = typecheck_exp(sa.condition);
+    match const_eval::eval(typed_condition.clone()) {
+        Some(c) => {
+            if const_eval::is_zero(c) {
+                match sa.message {
+                    Some(message) => panic!("静态断言失败：{}", message),
+                    None => panic!("静态断言失败。"),
+                }
+            } else {
+                ast::StaticAssertDeclaration {
+                    condition: typed_condition,
+                    message: sa.message,
+                }
+            }
+        }
+        None => panic!("_Static_assert的条件不是整数常量表达式。"),
     }
 }
 
 pub fn typecheck_local_decl(d: ast::Declaration<ast::UnTypedExp>) -> ast::Declaration<ast::TypedExp> {
     match d {
-        ast::Declaration::VarDecl(vd) => typecheck_local_var_decl(vd),
-        ast::Declaration::FunDecl(fd) => typecheck_fn_decl(fd),
+        ast::Declaration::VarDecl(vd) => ast::Declaration::VarDecl(typecheck_local_var_decl(vd)),
+        ast::Declaration::FunDecl(fd) => ast::Declaration::FunDecl(typecheck_fn_decl(fd, false)),
+        ast::Declaration::StaticAssert(sa) => {
+            ast::Declaration::StaticAssert(typecheck_static_assert(sa))
+        }
     }
 }
 
@@ -298,30 +462,55 @@ pub fn typecheck_local_var_decl(
             }
             match symbols::get_opt(vd.name.clone()) {
                 Some(symbols::Entry { t, attrs: _ }) => {
-                    if t != types::Type::Int {
-                        panic!("function redeclared as variable");
+                    if t != vd.var_type {
+                        panic!("variable {} redeclared with a different type.", vd.name);
                     }
                 }
                 None => symbols::add_static_var(
-                    vd.name,
-                    types::Type::Int,
+                    vd.name.clone(),
+                    vd.var_type.clone(),
                     true,
                     symbols::InitialValue::NoInitializer,
                 ),
             }
+            ast::VariableDeclaration {
+                name: vd.name,
+                var_type: vd.var_type,
+                init: None,
+                storage_class: vd.storage_class,
+            }
         }
         Some(ast::StorageClass::Static) => {
-            let ini = match vd.init {
-                Some(ast::Exp::Constant(i)) => symbols::InitialValue::Initial(i),
-                None => symbols::InitialValue::Initial(0),
-                Some(_) => panic!("non-constant initializer on local static variable"),
+            let (init, ini) = match vd.init {
+                Some(e) => {
+                    let typed_init = typecheck_init(vd.var_type.clone(), e);
+                    let ini = to_static_init(vd.var_type.clone(), typed_init.clone());
+                    (Some(typed_init), ini)
+                }
+                None => (
+                    None,
+                    symbols::InitialValue::Initial(initializers::zero(vd.var_type.clone())),
+                ),
             };
-            symbols::add_static_var(vd.name, types::Type::Int, false, ini);
+            symbols::add_static_var(vd.name.clone(), vd.var_type.clone(), false, ini);
+            ast::VariableDeclaration {
+                name: vd.name,
+                var_type: vd.var_type,
+                init: init,
+                storage_class: vd.storage_class,
+            }
         }
         None => {
-            symbols::add_automatic_var(vd.name, types::Type::Int);
-            if vd.init.is_some() {
-                typecheck_exp(vd.init.unwrap());
+            symbols::add_automatic_var(vd.name.clone(), vd.var_type.clone());
+            let init = match vd.init {
+                Some(e) => Some(typecheck_init(vd.var_type.clone(), e)),
+                None => None,
+            };
+            ast::VariableDeclaration {
+                name: vd.name,
+                var_type: vd.var_type,
+                init: init,
+                storage_class: None,
             }
         }
     }
@@ -329,20 +518,35 @@ pub fn typecheck_local_var_decl(
 
 pub fn typecheck_fn_decl(
     fd: ast::FunctionDeclaration<ast::UnTypedExp>,
+    file_scope: bool,
 ) -> ast::FunctionDeclaration<ast::TypedExp> {
     let has_body = fd.body.is_some();
     let global = fd.storage_class != Some(ast::StorageClass::Static);
+    // C99 6.7.4：只有文件作用域的声明决定inline定义是不是外部定义
+    let current_inline_definition = if file_scope {
+        fd.inline && fd.storage_class != Some(ast::StorageClass::Extern)
+    } else {
+        true
+    };
     let old_decl = symbols::get_opt(fd.name.clone());
-    let (defined, global) = match old_decl {
-        None => (has_body, global),
+    let (defined, global, inline, inline_definition, composite_type) = match old_decl {
+        None => (
+            has_body,
+            global,
+            fd.inline,
+            current_inline_definition,
+            fd.fun_type.clone(),
+        ),
         Some(_old_decl) => {
-            if _old_decl.t != fd.fun_type {
-                panic!("redeclared function {} with a different type", fd.name);
+            if !is_compatible(_old_decl.t.clone(), fd.fun_type.clone()) {
+                panic!("redeclared function {} with an incompatible type", fd.name);
             } else {
                 match _old_decl.attrs {
                     symbols::IdentifierAttrs::FunAttr {
                         defined: prev_defined,
                         global: prev_global,
+                        inline: prev_inline,
+                        inline_definition: prev_inline_definition,
                         stack_frame_size: _,
                     } => {
                         if prev_defined && has_body {
@@ -352,7 +556,13 @@ pub fn typecheck_fn_decl(
                             panic!("static function declaration follows non-static");
                         } else {
                             let defined = has_body || prev_defined;
-                            (defined, prev_global)
+                            (
+                                defined,
+                                prev_global,
+                                prev_inline || fd.inline,
+                                prev_inline_definition && current_inline_definition,
+                                composite_type(_old_decl.t, fd.fun_type.clone()),
+                            )
                         }
                     }
                     _ => panic!("内部错误：symbol has function type but not function attributes."),
@@ -361,29 +571,52 @@ pub fn typecheck_fn_decl(
         }
     };
 
-    symbols::add_fun(fd.name, fd.fun_type, global, defined);
-    let (param_ts, return_t) = match fd.fun_type {
+    symbols::add_fun(
+        fd.name.clone(),
+        composite_type,
+        global,
+        defined,
+        inline,
+        inline_definition,
+    );
+    // 函数体内部按照这次声明自己的参数类型来看待参数，
+    // 这样旧式定义的参数在函数体里仍然是它声明的类型
+    let (param_ts, return_t) = match fd.fun_type.clone() {
         types::Type::FunType {
             param_types,
             ret_type,
+            has_prototype: _,
         } => (param_types, ret_type),
         _ => panic!("内部错误，function has non-function type."),
     };
     if has_body {
-        for param in param_ts {
-            symbols::add_automatic_var(param, types::Type::Int);
+        for (param, param_t) in fd.params.iter().zip(param_ts.iter()) {
+            symbols::add_automatic_var(param.clone(), (**param_t).clone());
         }
     }
-    if fd.body.is_some() {
-        let body = typecheck_block(return_t, fd.body.unwrap());
+    let body = match fd.body {
+        Some(b) => Some(typecheck_block(*return_t, b)),
+        None => None,
+    };
+    ast::FunctionDeclaration {
+        name: fd.name,
+        fun_type: fd.fun_type,
+        params: fd.params,
+        body: body,
+        storage_class: fd.storage_class,
+        inline: fd.inline,
     }
 }
 
 pub fn typecheck_file_scope_var_decl(
     vd: ast::VariableDeclaration<ast::UnTypedExp>,
 ) -> ast::VariableDeclaration<ast::TypedExp> {
-    let current_init = match vd.init {
-        Some(ast::Exp::Constant(c)) => symbols::InitialValue::Initial(c),
+    let typed_init = match vd.init {
+        Some(init) => Some(typecheck_init(vd.var_type.clone(), init)),
+        None => None,
+    };
+    let current_init = match typed_init.clone() {
+        Some(e) => to_static_init(vd.var_type.clone(), e),
         None => {
             if vd.storage_class == Some(ast::StorageClass::Extern) {
                 symbols::InitialValue::NoInitializer
@@ -391,15 +624,14 @@ pub fn typecheck_file_scope_var_decl(
                 symbols::InitialValue::Tentative
             }
         }
-        Some(_) => panic!("file scope variable has non-constant initializer."),
     };
     let current_global = vd.storage_class != Some(ast::StorageClass::Extern);
     let old_decl = symbols::get_opt(vd.name.clone());
     let (global, init) = match old_decl {
         None => (current_global, current_init),
         Some(_old_decl) => {
-            if _old_decl.t != types::Type::Int {
-                panic!("function redeclared as variable.");
+            if _old_decl.t != vd.var_type {
+                panic!("variable {} redeclared with a different type.", vd.name);
             } else {
                 match _old_decl.attrs {
                     symbols::IdentifierAttrs::StaticAttr { init: prev_init, global: prev_global } => {
@@ -423,21 +655,218 @@ pub fn typecheck_file_scope_var_decl(
             }
         }
     };
-    symbols::add_static_var(vd.name, types::Type::Int, global, init);
+    symbols::add_static_var(vd.name.clone(), vd.var_type.clone(), global, init);
+    ast::VariableDeclaration {
+        name: vd.name,
+        var_type: vd.var_type,
+        init: typed_init,
+        storage_class: vd.storage_class,
+    }
 }
 
 pub fn typecheck_global_decl(d: ast::Declaration<ast::UnTypedExp>) -> ast::Declaration<ast::TypedExp> {
     match d {
-        ast::Declaration::FunDecl(fd) => typecheck_fn_decl(fd),
-        ast::Declaration::VarDecl(vd) => typecheck_file_scope_var_decl(vd),
+        ast::Declaration::FunDecl(fd) => ast::Declaration::FunDecl(typecheck_fn_decl(fd, true)),
+        ast::Declaration::StaticAssert(sa) => {
+            ast::Declaration::StaticAssert(typecheck_static_assert(sa))
+        }
+        ast::Declaration::VarDecl(vd) => {
+            ast::Declaration::VarDecl(typecheck_file_scope_var_decl(vd))
+        }
     }
 }
 
-pub fn typecheck(program: ast::UntypedProg) -> ast::TypedProg {
+pub fn typecheck(program: ast::UntypedProgType) -> ast::TypedProgType {
     match program {
-        ast::TypedProgType::Program(fn_decls) => {
+        ast::UntypedProgType::Program(fn_decls) => {
+            let mut typed_decls = vec![];
             for fn_decl in fn_decls {
-                typecheck_global_decl(fn_decl);
+                typed_decls.push(typecheck_global_decl(fn_decl));
+            }
+            ast::TypedProgType::Program(typed_decls)
+        }
+    }
+}
+
+#[test]
+fn test_common_type_promotes_narrow_types() {
+    assert_eq!(
+        get_common_type(types::Type::Short, types::Type::Short),
+        types::Type::Int
+    );
+    assert_eq!(
+        get_common_type(types::Type::Bool, types::Type::UShort),
+        types::Type::Int
+    );
+    assert_eq!(
+        get_common_type(types::Type::UShort, types::Type::Int),
+        types::Type::Int
+    );
+    assert_eq!(
+        get_common_type(types::Type::Short, types::Type::Long),
+        types::Type::Long
+    );
+    assert_eq!(
+        get_common_type(types::Type::Long, types::Type::Long),
+        types::Type::Long
+    );
+}
+
+#[test]
+fn test_assignment_and_initializer_convert_to_declared_type() {
+    let int_const = |i: i32| ast::UnTypedExp::Constant(constants::T::ConstInt(i));
+    let typed_decl = typecheck_local_var_decl(ast::VariableDeclaration {
+        name: "typecheck.flag".to_string(),
+        var_type: types::Type::Bool,
+        init: Some(int_const(256)),
+        storage_class: None,
+    });
+    assert_eq!(symbols::get("typecheck.flag".to_string()).t, types::Type::Bool);
+    let expected_cast = ast::TypedExp {
+        e: Box::new(ast::TypedInnerExp::Cast {
+            target_type: types::Type::Bool,
+            e: typecheck_const(constants::T::ConstInt(256)),
+        }),
+        t: types::Type::Bool,
+    };
+    assert_eq!(typed_decl.init, Some(expected_cast.clone()));
+
+    let assignment = typecheck_assignment(
+        ast::UnTypedExp::Var("typecheck.flag".to_string()),
+        int_const(256),
+    );
+    assert_eq!(assignment.t, types::Type::Bool);
+    assert_eq!(
+        *assignment.e,
+        ast::TypedInnerExp::Assignment(typecheck_var("typecheck.flag".to_string()), expected_cast)
+    );
+}
+
+#[test]
+fn test_inline_definitions_and_linkage() {
+    fn typecheck_source(source: &str) {
+        let tokens = crate::lexer::Lexer::new(source.as_bytes()).lex();
+        match crate::parser::Parser::new(tokens).parse() {
+            ast::ProgType::Program(decls) => {
+                for decl in decls {
+                    typecheck_global_decl(decl);
+                }
+            }
+        }
+    }
+    let name = |s: &str| s.to_string();
+
+    // 文件作用域里只有不带extern的inline声明：内联定义，不生成外部定义
+    typecheck_source("inline int typecheck_inline(void) { return 1; }");
+    assert!(symbols::is_inline_definition(name("typecheck_inline")));
+    assert!(!symbols::is_static_inline(name("typecheck_inline")));
+
+    // 任何一个文件作用域声明带了extern，定义就是外部定义
+    typecheck_source(
+        "inline int typecheck_extern_inline(void) { return 2; }
+         extern inline int typecheck_extern_inline(void);",
+    );
+    assert!(!symbols::is_inline_definition(name("typecheck_extern_inline")));
+    assert!(symbols::is_global(name("typecheck_extern_inline")));
+
+    // 不带inline的声明同样让定义成为外部定义
+    typecheck_source(
+        "inline int typecheck_plain_redecl(void);
+         int typecheck_plain_redecl(void) { return 3; }",
+    );
+    assert!(!symbols::is_inline_definition(name("typecheck_plain_redecl")));
+
+    // 块作用域的extern声明不影响内联定义
+    typecheck_source(
+        "inline int typecheck_block_scope(void) { return 4; }
+         int typecheck_block_caller(void) {
+             extern int typecheck_block_scope(void);
+             return typecheck_block_scope();
+         }",
+    );
+    assert!(symbols::is_inline_definition(name("typecheck_block_scope")));
+
+    // static inline是内部链接，只有被引用时才生成定义
+    typecheck_source("static inline int typecheck_static_inline(void) { return 5; }");
+    assert!(symbols::is_static_inline(name("typecheck_static_inline")));
+    assert!(!symbols::is_inline_definition(name("typecheck_static_inline")));
+    assert!(!symbols::is_global(name("typecheck_static_inline")));
+}
+
+#[test]
+#[should_panic(expected = "静态断言失败：int is not 8 bytes")]
+fn test_failing_static_assert() {
+    let source = "_Static_assert(sizeof(int) == 4, \"int is 4 bytes\");
+                  _Static_assert(sizeof(int) == 8, \"int is not 8 bytes\");";
+    let tokens = crate::lexer::Lexer::new(source.as_bytes()).lex();
+    match crate::parser::Parser::new(tokens).parse() {
+        ast::ProgType::Program(decls) => {
+            for decl in decls {
+                typecheck_global_decl(decl);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_static_initializers_fold_to_exact_width() {
+    fn initial_value(source: &str, name: &str) -> symbols::InitialValue {
+        let tokens = crate::lexer::Lexer::new(source.as_bytes()).lex();
+        match crate::parser::Parser::new(tokens).parse() {
+            ast::ProgType::Program(decls) => {
+                for decl in decls {
+                    typecheck_global_decl(decl);
+                }
+            }
+        }
+        match symbols::get(name.to_string()).attrs {
+            symbols::IdentifierAttrs::StaticAttr { init, global: _ } => init,
+            other => panic!("{}不是静态变量：{:?}", name, other),
+        }
+    }
+    let initial = |init: initializers::StaticInit| symbols::InitialValue::Initial(init);
+
+    assert_eq!(
+        initial_value("static short typecheck_s = 70000;", "typecheck_s"),
+        initial(initializers::StaticInit::ShortInit(4464))
+    );
+    assert_eq!(
+        initial_value("static _Bool typecheck_b = 256;", "typecheck_b"),
+        initial(initializers::StaticInit::BoolInit(true))
+    );
+    assert_eq!(
+        initial_value(
+            "static int typecheck_i = -2147483648L - 1;",
+            "typecheck_i"
+        ),
+        initial(initializers::StaticInit::IntInit(2147483647))
+    );
+    assert_eq!(
+        initial_value(
+            "static long typecheck_l = (long)2147483647 + 1;",
+            "typecheck_l"
+        ),
+        initial(initializers::StaticInit::LongInit(2147483648))
+    );
+    assert_eq!(
+        initial_value(
+            "static unsigned short typecheck_t = sizeof(long) == 8 ? -1 : 1L / 0;",
+            "typecheck_t"
+        ),
+        initial(initializers::StaticInit::UShortInit(65535))
+    );
+}
+
+#[test]
+#[should_panic(expected = "non-constant initializer on static variable.")]
+fn test_non_constant_static_initializer() {
+    let source = "int typecheck_x = 1;
+                  int typecheck_y = typecheck_x + 1;";
+    let tokens = crate::lexer::Lexer::new(source.as_bytes()).lex();
+    match crate::parser::Parser::new(tokens).parse() {
+        ast::ProgType::Program(decls) => {
+            for decl in decls {
+                typecheck_global_decl(decl);
             }
         }
     }
@@ -1,9 +1,40 @@
+use std::fmt::Display;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Type {
+    Bool,
+    Short,
+    UShort,
     Int,
     Long,
     FunType {
         param_types: Vec<Box<Type>>,
         ret_type: Box<Type>,
+        // `int f();`这种没有原型的声明不会检查参数，调用时只做默认实参提升
+        has_prototype: bool,
     },
 }
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Bool => write!(f, "bool"),
+            Type::Short => write!(f, "short"),
+            Type::UShort => write!(f, "ushort"),
+            Type::Int => write!(f, "int"),
+            Type::Long => write!(f, "long"),
+            Type::FunType {
+                param_types,
+                ret_type,
+                has_prototype,
+            } => {
+                let params: Vec<String> = param_types.iter().map(|t| t.to_string()).collect();
+                if *has_prototype {
+                    write!(f, "({}) -> {}", params.join(", "), ret_type)
+                } else {
+                    write!(f, "(...) -> {}", ret_type)
+                }
+            }
+        }
+    }
+}
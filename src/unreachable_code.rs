@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+
+use crate::{cfg, ir};
+
+fn eliminate_unreachable_blocks(cfg: &mut cfg::Graph<(), ir::Instruction>) {
+    let mut visited = HashSet::new();
+    let mut stack = vec![cfg::NodeId::Entry];
+    while let Some(node) = stack.pop() {
+        if visited.insert(node.clone()) {
+            stack.extend(cfg::get_succs(&node, cfg));
+        }
+    }
+    let unreachable: Vec<usize> = cfg
+        .basic_blocks
+        .keys()
+        .filter(|idx| !visited.contains(&cfg::NodeId::Block(**idx)))
+        .cloned()
+        .collect();
+    for idx in unreachable {
+        for succ in cfg::get_succs(&cfg::NodeId::Block(idx), cfg) {
+            cfg::remove_edge(cfg::NodeId::Block(idx), succ, cfg);
+        }
+        cfg.basic_blocks.remove(&idx);
+    }
+}
+
+/// 如果一个块的所有后继都是紧跟在它后面的块，块末尾的跳转就是多余的。
+fn eliminate_useless_jumps(cfg: &mut cfg::Graph<(), ir::Instruction>) {
+    let block_ids: Vec<usize> = cfg.basic_blocks.keys().cloned().collect();
+    for (i, idx) in block_ids.iter().enumerate() {
+        if i + 1 == block_ids.len() {
+            break;
+        }
+        let default_succ = cfg::NodeId::Block(block_ids[i + 1]);
+        let block = cfg.basic_blocks.get_mut(idx).unwrap();
+        let is_jump = match block.instructions.last() {
+            Some((_, ir::Instruction::Jump(_)))
+            | Some((_, ir::Instruction::JumpIfZero(_, _)))
+            | Some((_, ir::Instruction::JumpIfNotZero(_, _))) => true,
+            _ => false,
+        };
+        if is_jump && block.succs.iter().all(|succ| *succ == default_succ) {
+            block.instructions.pop();
+        }
+    }
+}
+
+/// 如果一个块只能从前一个块顺序执行下来，没有任何跳转以它为目标，块开头的标签就可以删掉。
+/// 循环生成的`break.*`和`continue.*`标签大多属于这种情况。
+fn eliminate_useless_labels(cfg: &mut cfg::Graph<(), ir::Instruction>) {
+    let block_ids: Vec<usize> = cfg.basic_blocks.keys().cloned().collect();
+    for (i, idx) in block_ids.iter().enumerate() {
+        let default_pred = if i == 0 {
+            cfg::NodeId::Entry
+        } else {
+            cfg::NodeId::Block(block_ids[i - 1])
+        };
+        let block = cfg.basic_blocks.get_mut(idx).unwrap();
+        let is_label = match block.instructions.first() {
+            Some((_, ir::Instruction::Label(_))) => true,
+            _ => false,
+        };
+        if is_label && block.preds.iter().all(|pred| *pred == default_pred) {
+            block.instructions.remove(0);
+        }
+    }
+}
+
+pub fn optimize(instructions: Vec<ir::Instruction>) -> Vec<ir::Instruction> {
+    let mut cfg = cfg::instructions_to_cfg("".to_string(), instructions);
+    eliminate_unreachable_blocks(&mut cfg);
+    eliminate_useless_jumps(&mut cfg);
+    eliminate_useless_labels(&mut cfg);
+    cfg::cfg_to_instructions(cfg)
+}
+
+#[test]
+fn test_remove_code_after_return() {
+    use crate::constants;
+    let ret = ir::Instruction::Return(ir::IrValue::Constant(constants::INT_ONE));
+    let optimized = optimize(vec![
+        ir::Instruction::Jump("end".to_string()),
+        ir::Instruction::Label("dead".to_string()),
+        ir::Instruction::Return(ir::IrValue::Constant(constants::INT_ZERO)),
+        ir::Instruction::Label("end".to_string()),
+        ret.clone(),
+        ir::Instruction::Label("break.loop.0".to_string()),
+        ir::Instruction::Return(ir::IrValue::Constant(constants::INT_ZERO)),
+    ]);
+    assert_eq!(optimized, vec![ret]);
+}
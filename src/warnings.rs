@@ -0,0 +1,28 @@
+use crate::{Diagnostic, Severity, Stage};
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+
+lazy_static! {
+    // 各个阶段发现的警告，不打断编译，由Compiler在编译结束后取走
+    static ref WARNINGS: Mutex<Vec<Diagnostic>> = Mutex::new(vec![]);
+}
+
+pub fn warn(stage: Stage, message: String) {
+    let mut _warnings = WARNINGS.lock().unwrap_or_else(|e| e.into_inner());
+    _warnings.push(Diagnostic {
+        severity: Severity::Warning,
+        stage: stage,
+        message: message,
+    });
+}
+
+pub fn take() -> Vec<Diagnostic> {
+    let mut _warnings = WARNINGS.lock().unwrap_or_else(|e| e.into_inner());
+    std::mem::take(&mut *_warnings)
+}
+
+pub fn clear() {
+    let mut _warnings = WARNINGS.lock().unwrap_or_else(|e| e.into_inner());
+    _warnings.clear();
+    WARNINGS.clear_poison();
+}
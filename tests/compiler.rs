@@ -0,0 +1,95 @@
+// Compiler每次编译前清空全局的符号表，放在单独的测试进程里，不影响库里的单元测试。
+use wacc::{Compiler, Diagnostic, Severity, Stage, Target};
+
+#[test]
+fn test_compile_to_asm() {
+    let output = Compiler::new()
+        .optimize_level(2)
+        .compile_to_asm("int main(void) { return 1 + 2; }")
+        .unwrap();
+    assert!(output.warnings.is_empty());
+    let asm = output.value;
+    assert!(asm.contains("\t.globl main\n"));
+    assert!(asm.contains("main:\n"));
+    assert!(asm.contains("\tret\n"));
+    assert!(wacc::compile_to_asm("int main(void) { return 3; }").is_ok());
+}
+
+#[test]
+fn test_syntax_error() {
+    match Compiler::new().compile_to_asm("int main(void) { return 2 }") {
+        Err(diagnostics) => match diagnostics.as_slice() {
+            [Diagnostic {
+                severity: Severity::Error,
+                stage: Stage::Parse,
+                message: _,
+            }] => (),
+            other => panic!("应该只有一个语法错误：{:?}", other),
+        },
+        Ok(output) => panic!("不应该编译成功：{}", output.value),
+    }
+}
+
+#[test]
+fn test_compiles_do_not_share_symbols() {
+    let compiler = Compiler::new();
+    assert!(compiler
+        .compile_to_asm("int x = 1; int main(void) { return x; }")
+        .is_ok());
+    // 上一次编译的x如果还在符号表里，把它重新声明成函数会报错
+    assert!(compiler
+        .compile_to_asm("int x(void) { return 1; } int main(void) { return x(); }")
+        .is_ok());
+    assert!(compiler
+        .typecheck("long x; int main(void) { return 0; }")
+        .is_ok());
+}
+
+#[test]
+fn test_include_paths_and_target() {
+    let compiler = Compiler::new()
+        .include_path("/usr/local/include")
+        .include_path("include")
+        .target(Target::X86_64SysV);
+    assert_eq!(
+        compiler.include_paths(),
+        &[
+            std::path::PathBuf::from("/usr/local/include"),
+            std::path::PathBuf::from("include")
+        ]
+    );
+    assert_eq!(compiler.get_target(), Target::X86_64SysV);
+    assert!(compiler
+        .compile_to_asm("int main(void) { return 0; }")
+        .is_ok());
+}
+
+#[test]
+fn test_warnings_are_returned_with_output() {
+    let output = Compiler::new()
+        .typecheck(
+            "int main(void) { return undeclared_fn(1); }
+             int old_style();
+             int old_style(a) int a; { return a; }
+             int call(void) { return old_style(1, 2); }",
+        )
+        .unwrap();
+    let warnings: Vec<(Severity, Stage)> = output
+        .warnings
+        .iter()
+        .map(|w| (w.severity, w.stage))
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![
+            (Severity::Warning, Stage::Resolve),
+            (Severity::Warning, Stage::Typecheck)
+        ]
+    );
+    // 出错时之前的警告排在错误前面
+    let diagnostics = Compiler::new()
+        .typecheck("int main(void) { int x = undeclared_fn(); return x(); }")
+        .unwrap_err();
+    let severities: Vec<Severity> = diagnostics.iter().map(|d| d.severity).collect();
+    assert_eq!(severities, vec![Severity::Warning, Severity::Error]);
+}
@@ -0,0 +1,25 @@
+// 解析tests/fixtures里的文本IR。解析结果写进全局的符号表，放在单独的测试进程里。
+use wacc::{ir_interpreter, ir_parser, ir_verifier};
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("无法读取{}：{}", path, e))
+}
+
+#[test]
+fn test_parse_and_run_fixture() {
+    let program = ir_parser::parse(&fixture("sum_to.ir")).unwrap();
+    assert!(ir_verifier::verify(&program).is_ok());
+    assert_eq!(ir_parser::parse(&program.to_string()).unwrap(), program);
+    let outcome = ir_interpreter::run(&program).unwrap();
+    assert_eq!(outcome.output, b"0".to_vec());
+    assert_eq!(outcome.exit_code, 5);
+}
+
+#[test]
+fn test_reject_reused_name() {
+    assert_eq!(
+        ir_parser::parse(&fixture("reused_temporary.ir")),
+        Err("第8行：tmp.1重复声明".to_string())
+    );
+}